@@ -1,34 +1,318 @@
 use clap::{Arg, Command};
-use coffee::extras::OptimizerArgs;
-use coffee::run_coffee;
+use coffee::composition::MatrixStorage;
+use coffee::enumerate::{enumerate_compositions, free_energies, ComplexLimits, EnergyTable};
+use coffee::error::CoffeeError;
+use coffee::extras::{HessianMode, InitialLambda, OptimizerArgs, SolverKind, SubproblemKind};
+use coffee::fileparse::{
+    detect_delimiter, parse_concentrations, parse_lambdas, Delimiter, EnergyLayout, InputFormat,
+    ParseOptions,
+};
+use coffee::format::{
+    composition_table, delimiter_message, melt_table, melting_message, sensitivity_table,
+    sweep_table, OutputFormat,
+};
+use coffee::observer::{Observer, TimeLimit};
+use coffee::sweep::{
+    concentration_sweep, temperature_sweep, ConcentrationSweep, SweepSpacing, TemperatureSweep,
+};
+use coffee::{read_problem, run_coffee, run_coffee_json, OutputPaths};
+use std::fs;
+use std::process::ExitCode;
+use std::time::Duration;
+
+fn cfe_arg() -> Arg {
+    Arg::new("cfe")
+        .help("The file path containing the input file for compositions and free energies.")
+        .required(true)
+        .index(1)
+        .value_parser(|file: &str| {
+            let allowed_extensions = [".cfe", ".ocx", ".txt", ".csv", ".tsv"];
+            if !allowed_extensions.iter().any(|ext| file.ends_with(ext)) {
+                return Err("File must be a .cfe, .ocx, .txt, .csv, or .tsv file".to_string());
+            }
+            Ok(file.to_string())
+        })
+}
+
+fn con_arg() -> Arg {
+    Arg::new("con")
+        .help("The file path containing the input file for concentrations.")
+        .required(true)
+        .index(2)
+        .value_parser(|file: &str| {
+            let allowed_extensions = [".con", ".txt", ".csv", ".tsv"];
+            if !allowed_extensions.iter().any(|ext| file.ends_with(ext)) {
+                return Err("File must be a .con, .txt, .csv, or .tsv file".to_string());
+            }
+            Ok(file.to_string())
+        })
+}
+
+fn verbose_arg() -> Arg {
+    Arg::new("verbose")
+        .short('v')
+        .long("verbose")
+        .required(false)
+        .action(clap::ArgAction::SetTrue)
+        .help("Enable verbose output")
+}
+
+fn temp_arg() -> Arg {
+    Arg::new("temp")
+        .short('t')
+        .long("temp")
+        .required(false)
+        .help("Temperature in Celsius (defaults to 37)")
+        .value_parser(|val: &str| match val.parse::<f64>() {
+            Ok(v) => Ok(v.to_string()),
+            Err(_) => Err("Temperature must be a valid number".to_string()),
+        })
+}
+
+fn energy_columns_arg() -> Arg {
+    Arg::new("energy-columns")
+        .long("energy-columns")
+        .required(false)
+        .default_value("dg")
+        .help("Energy columns at the end of each CFE row: a free energy (dg), enthalpy and entropy (dh-ds), or enthalpy, entropy and heat capacity (dh-ds-dcp)")
+        .value_parser(["dg", "dh-ds", "dh-ds-dcp"])
+}
+
+fn delimiter_arg() -> Arg {
+    Arg::new("delimiter")
+        .long("delimiter")
+        .required(false)
+        .default_value("auto")
+        .help("Delimiter between the fields of each CFE row. The default (auto) detects it from the first rows.")
+        .value_parser(["auto", "tab", "comma", "semicolon", "pipe", "whitespace"])
+}
+
+fn format_arg() -> Arg {
+    Arg::new("format")
+        .long("format")
+        .required(false)
+        .default_value("auto")
+        .help("Layout of the CFE compositions: every column (conventional), after the two index columns of a NUPACK .ocx file (nupack), or a string such as \"A1 B2\" naming strands from the CON file (composition). The default (auto) warns when the layout is ambiguous.")
+        .value_parser(["auto", "conventional", "nupack", "composition"])
+}
+
+fn threads_arg() -> Arg {
+    Arg::new("threads")
+        .long("threads")
+        .required(false)
+        .default_value("0")
+        .help("Number of threads to use. 0 uses every available core. Results don't depend on the number of threads.")
+        .value_parser(clap::value_parser!(usize))
+}
+
+fn solver_arg() -> Arg {
+    Arg::new("solver")
+        .long("solver")
+        .required(false)
+        .default_value("trust-region")
+        .help("The algorithm that minimizes the dual objective: a trust region method (trust-region) or a damped Newton method with a line search (newton-line-search)")
+        .value_parser(["trust-region", "newton-line-search"])
+}
+
+fn subproblem_arg() -> Arg {
+    Arg::new("subproblem")
+        .long("subproblem")
+        .required(false)
+        .default_value("steihaug")
+        .help("How the trust region solver finds each step: truncated conjugate gradient (steihaug) or the dogleg path to the Newton step of the dense Hessian (dogleg)")
+        .value_parser(["steihaug", "dogleg"])
+}
+
+fn table_output_arg() -> Arg {
+    Arg::new("output")
+        .short('o')
+        .long("output")
+        .help("The file path to write the table to. A .csv file is comma separated, otherwise columns are tab separated. If this is not provided, the table prints to stdout.")
+        .required(false)
+        .value_parser(|file: &str| {
+            let allowed_extensions = [".csv", ".tsv", ".txt"];
+            if !allowed_extensions.iter().any(|ext| file.ends_with(ext)) {
+                return Err("File must be a .csv, .tsv, or .txt file".to_string());
+            }
+            Ok(file.to_string())
+        })
+}
+
+fn sweep_command() -> Command {
+    Command::new("sweep")
+        .about("Solve the same system over a range of monomer concentrations")
+        .arg(cfe_arg())
+        .arg(con_arg())
+        .arg(
+            Arg::new("monomer")
+                .short('m')
+                .long("monomer")
+                .required(true)
+                .action(clap::ArgAction::Append)
+                .help("Monomer to vary, as its 1-based line number in the concentrations file. Repeat to vary several monomers together.")
+                .value_parser(clap::value_parser!(u64).range(1..)),
+        )
+        .arg(
+            Arg::new("start")
+                .long("start")
+                .required(true)
+                .help("First concentration of the sweep")
+                .value_parser(clap::value_parser!(f64)),
+        )
+        .arg(
+            Arg::new("stop")
+                .long("stop")
+                .required(true)
+                .help("Last concentration of the sweep")
+                .value_parser(clap::value_parser!(f64)),
+        )
+        .arg(
+            Arg::new("points")
+                .long("points")
+                .required(false)
+                .default_value("10")
+                .help("Number of sweep points, including both ends")
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("spacing")
+                .long("spacing")
+                .required(false)
+                .default_value("linear")
+                .help("Spacing of the sweep points")
+                .value_parser(["linear", "log"]),
+        )
+        .arg(table_output_arg())
+        .arg(verbose_arg())
+        .arg(temp_arg())
+        .arg(energy_columns_arg())
+        .arg(delimiter_arg())
+        .arg(format_arg())
+        .arg(threads_arg())
+        .arg(solver_arg())
+        .arg(subproblem_arg())
+}
+
+fn melt_command() -> Command {
+    Command::new("melt")
+        .about("Solve the same system over a range of temperatures and estimate melting temperatures")
+        .arg(cfe_arg())
+        .arg(con_arg())
+        .arg(
+            Arg::new("start")
+                .long("start")
+                .required(true)
+                .help("First temperature of the sweep, in Celsius")
+                .value_parser(clap::value_parser!(f64)),
+        )
+        .arg(
+            Arg::new("stop")
+                .long("stop")
+                .required(true)
+                .help("Last temperature of the sweep, in Celsius")
+                .value_parser(clap::value_parser!(f64)),
+        )
+        .arg(
+            Arg::new("points")
+                .long("points")
+                .required(false)
+                .default_value("10")
+                .help("Number of sweep points, including both ends")
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("target")
+                .long("target")
+                .required(false)
+                .action(clap::ArgAction::Append)
+                .help("Polymer to estimate the melting temperature of, as its 1-based row number in the CFE file. Repeat for several polymers.")
+                .value_parser(clap::value_parser!(u64).range(1..)),
+        )
+        .arg(table_output_arg())
+        .arg(verbose_arg())
+        .arg(energy_columns_arg())
+        .arg(delimiter_arg())
+        .arg(format_arg())
+        .arg(threads_arg())
+        .arg(solver_arg())
+        .arg(subproblem_arg())
+}
+
+fn sensitivity_command() -> Command {
+    Command::new("sensitivity")
+        .about("Solve the system and report how the concentrations of selected polymers respond to the monomer concentrations and free energies")
+        .arg(cfe_arg())
+        .arg(con_arg())
+        .arg(
+            Arg::new("polymer")
+                .short('p')
+                .long("polymer")
+                .required(true)
+                .action(clap::ArgAction::Append)
+                .help("Polymer to report the sensitivities of, as its 1-based row number in the CFE file. Repeat for several polymers.")
+                .value_parser(clap::value_parser!(u64).range(1..)),
+        )
+        .arg(table_output_arg())
+        .arg(verbose_arg())
+        .arg(temp_arg())
+        .arg(energy_columns_arg())
+        .arg(delimiter_arg())
+        .arg(format_arg())
+        .arg(threads_arg())
+        .arg(solver_arg())
+        .arg(subproblem_arg())
+}
+
+fn enumerate_command() -> Command {
+    Command::new("enumerate")
+        .about("Write every complex of the strands in a concentrations file up to a maximum size")
+        .arg(con_arg().index(1))
+        .arg(
+            Arg::new("max-size")
+                .long("max-size")
+                .required(true)
+                .help("Largest number of strands in a complex, counting repeats")
+                .value_parser(clap::value_parser!(u64).range(1..)),
+        )
+        .arg(
+            Arg::new("cap")
+                .long("cap")
+                .required(false)
+                .action(clap::ArgAction::Append)
+                .help("Largest count of a strand in a complex, at least 1. Repeat once per strand, in the order of the concentrations file.")
+                .value_parser(clap::builder::RangedU64ValueParser::<usize>::new().range(1..)),
+        )
+        .arg(
+            Arg::new("energies")
+                .long("energies")
+                .required(false)
+                .help("The file path containing free energies keyed by composition string, e.g. \"A1 B2, -3.5\". With it, each complex is written with its free energy, as a CFE file. The concentrations file must name the strands."),
+        )
+        .arg(table_output_arg())
+}
 
 fn command() -> Command {
     Command::new("coffee_cli")
         .version("1.0")
         .author("UT Austin Senior Design Group FH12, 2024-2025")
         .about("CLI for COFFEE optimization")
+        .args_conflicts_with_subcommands(true)
+        .subcommand_negates_reqs(true)
+        .subcommand(sweep_command())
+        .subcommand(melt_command())
+        .subcommand(sensitivity_command())
+        .subcommand(enumerate_command())
+        .arg(cfe_arg().required(false).required_unless_present("json"))
+        .arg(con_arg().required(false).required_unless_present("json"))
         .arg(
-            Arg::new("cfe")
-                .help("The file path containing the input file for compositions and free energies.")
-                .required(true)
-                .index(1)
-                .value_parser(|file: &str| {
-                    let allowed_extensions = [".cfe", ".ocx", ".txt", ".csv", ".tsv"];
-                    if !allowed_extensions.iter().any(|ext| file.ends_with(ext)) {
-                        return Err("File must be a .cfe, .ocx, .txt, .csv, or .tsv file".to_string());
-                    }
-                    Ok(file.to_string())
-                }),
-        )
-        .arg(
-            Arg::new("con")
-                .help("The file path containing the input file for concentrations.")
-                .required(true)
-                .index(2)
+            Arg::new("json")
+                .long("json")
+                .help("The file path containing the whole problem as JSON, instead of the CFE and CON files. Temperature and optimizer settings in the file override the flags.")
+                .required(false)
+                .conflicts_with_all(["cfe", "con", "format", "delimiter", "energy-columns"])
                 .value_parser(|file: &str| {
-                    let allowed_extensions = [".con", ".txt", ".csv", ".tsv"];
-                    if !allowed_extensions.iter().any(|ext| file.ends_with(ext)) {
-                        return Err("File must be a .con, .txt, .csv, or .tsv file".to_string());
+                    if !file.ends_with(".json") {
+                        return Err("File must be a .json file".to_string());
                     }
                     Ok(file.to_string())
                 }),
@@ -51,35 +335,77 @@ fn command() -> Command {
             Arg::new("output")
                 .short('o')
                 .long("output")
-                .help("The file path to output only the results. If this is not provided, results will not be saved to a file and does not affect log printing.")
+                .help("The file path to output only the results. If this is not provided, results will not be saved to a file and does not affect log printing. A .json, .csv or .tsv file is written in that format unless --output-format is given.")
                 .required(false)
                 .value_parser(|file: &str| {
-                    let allowed_extensions = [".txt", ".log"];
+                    let allowed_extensions = [".txt", ".log", ".json", ".csv", ".tsv"];
                     if !allowed_extensions.iter().any(|ext| file.ends_with(ext)) {
-                        return Err("File must be a .txt or .log file".to_string());
+                        return Err("File must be a .txt, .log, .json, .csv, or .tsv file".to_string());
                     }
                     Ok(file.to_string())
                 }),
         )
         .arg(
-            Arg::new("verbose")
-                .short('v')
-                .long("verbose")
+            Arg::new("output-format")
+                .long("output-format")
                 .required(false)
-                .action(clap::ArgAction::SetTrue)
-                .help("Enable verbose output"),
+                .help("Format of the results: the concentrations as text, or every value of the results labeled by name as json, csv or tsv. Without a log file, machine-readable formats print only the results to stdout.")
+                .value_parser(["text", "json", "csv", "tsv"]),
         )
         .arg(
-            Arg::new("temp")
-                .short('t')
-                .long("temp")
+            Arg::new("history")
+                .long("history")
+                .help("The file path to output the history of the optimization, one row per iteration with the objective, gradient norm, constraint error, trust radius, rho, whether the step was accepted and the subproblem's inner iterations. Comma-separated for .csv files, otherwise tab-separated.")
+                .required(false),
+        )
+        .arg(
+            Arg::new("time-limit")
+                .long("time-limit")
                 .required(false)
-                .help("Temperature in Celsius (defaults to 37)")
-                .value_parser(|val: &str| match val.parse::<f64>() {
-                    Ok(v) => Ok(v.to_string()),
-                    Err(_) => Err("Temperature must be a valid number".to_string()),
+                .help("Stop the optimization once it has run for this many seconds, reporting the results of the last iteration as cancelled")
+                .value_parser(|value: &str| match value.parse::<f64>() {
+                    Ok(seconds) if seconds.is_finite() && seconds >= 0.0 => Ok(seconds),
+                    _ => Err("Time limit must be a non-negative number of seconds".to_string()),
                 }),
         )
+        .arg(verbose_arg())
+        .arg(temp_arg())
+        .arg(energy_columns_arg())
+        .arg(delimiter_arg())
+        .arg(format_arg())
+        .arg(threads_arg())
+        .arg(solver_arg())
+        .arg(subproblem_arg())
+        .arg(
+            Arg::new("init-lambda")
+                .long("init-lambda")
+                .required(false)
+                .help("The file path containing starting Lagrange multipliers, one per monomer, e.g. the optimal lambdas of a previous run.")
+                .conflicts_with("heuristic-lambda"),
+        )
+        .arg(
+            Arg::new("heuristic-lambda")
+                .long("heuristic-lambda")
+                .required(false)
+                .action(clap::ArgAction::SetTrue)
+                .help("Start the Lagrange multipliers from the log of the monomer concentrations"),
+        )
+        .arg(
+            Arg::new("hessian")
+                .long("hessian")
+                .required(false)
+                .default_value("auto")
+                .help("Whether to build the dense Hessian (dense) or only compute Hessian-vector products (matrix-free). The default picks by the number of monomers.")
+                .value_parser(["auto", "dense", "matrix-free"]),
+        )
+        .arg(
+            Arg::new("storage")
+                .long("storage")
+                .required(false)
+                .default_value("auto")
+                .help("How to store the composition matrix. The default stores it sparse when it is mostly zeros.")
+                .value_parser(["auto", "dense", "sparse"]),
+        )
 }
 
 struct CoffeeArgs {
@@ -101,55 +427,476 @@ impl CoffeeArgs {
         self.desc.get_flag("verbose")
     }
 
+    pub fn heuristic_lambda(&self) -> bool {
+        self.desc.get_flag("heuristic-lambda")
+    }
+
     pub fn temp_celsius(&self) -> Option<f64> {
         self.desc
             .get_one::<String>("temp")
             .and_then(|s| s.parse::<f64>().ok())
     }
+
+    pub fn get_number<T: Clone + Send + Sync + 'static>(&self, arg: &str) -> Option<T> {
+        self.desc.get_one::<T>(arg).cloned()
+    }
+
+    /// Returns the 0-based indices given by a repeatable 1-based index argument.
+    pub fn get_indices(&self, arg: &str) -> Vec<usize> {
+        self.desc
+            .get_many::<u64>(arg)
+            .map(|values| values.map(|&v| v as usize - 1).collect())
+            .unwrap_or_default()
+    }
+
+    /// Returns the format of the results: the one given, or the one the output file's
+    /// extension implies, or text.
+    pub fn output_format(&self) -> OutputFormat {
+        let format = self
+            .desc
+            .get_one::<String>("output-format")
+            .cloned()
+            .or_else(|| {
+                let path = self.get_file("output")?;
+                let (_, extension) = path.rsplit_once('.')?;
+                Some(extension.to_string())
+            });
+        match format.as_deref() {
+            Some("json") => OutputFormat::Json,
+            Some("csv") => OutputFormat::Csv,
+            Some("tsv") => OutputFormat::Tsv,
+            _ => OutputFormat::Text,
+        }
+    }
+
+    pub fn hessian_mode(&self) -> HessianMode {
+        match self.desc.get_one::<String>("hessian").map(|s| s.as_str()) {
+            Some("dense") => HessianMode::Dense,
+            Some("matrix-free") => HessianMode::MatrixFree,
+            _ => HessianMode::Auto,
+        }
+    }
+
+    pub fn solver_kind(&self) -> SolverKind {
+        match self.desc.get_one::<String>("solver").map(|s| s.as_str()) {
+            Some("newton-line-search") => SolverKind::NewtonLineSearch,
+            _ => SolverKind::TrustRegion,
+        }
+    }
+
+    pub fn subproblem_kind(&self) -> SubproblemKind {
+        match self
+            .desc
+            .get_one::<String>("subproblem")
+            .map(|s| s.as_str())
+        {
+            Some("dogleg") => SubproblemKind::Dogleg,
+            _ => SubproblemKind::Steihaug,
+        }
+    }
+
+    pub fn matrix_storage(&self) -> MatrixStorage {
+        match self.desc.get_one::<String>("storage").map(|s| s.as_str()) {
+            Some("dense") => MatrixStorage::Dense,
+            Some("sparse") => MatrixStorage::Sparse,
+            _ => MatrixStorage::Auto,
+        }
+    }
+
+    pub fn parse_options(&self) -> ParseOptions {
+        let energy_layout = match self
+            .desc
+            .get_one::<String>("energy-columns")
+            .map(|s| s.as_str())
+        {
+            Some("dh-ds") => EnergyLayout::EnthalpyEntropy,
+            Some("dh-ds-dcp") => EnergyLayout::EnthalpyEntropyHeatCapacity,
+            _ => EnergyLayout::FreeEnergy,
+        };
+        let delimiter = match self.desc.get_one::<String>("delimiter").map(|s| s.as_str()) {
+            Some("tab") => Delimiter::Tab,
+            Some("comma") => Delimiter::Comma,
+            Some("semicolon") => Delimiter::Semicolon,
+            Some("pipe") => Delimiter::Pipe,
+            Some("whitespace") => Delimiter::Whitespace,
+            _ => Delimiter::Auto,
+        };
+        let format = match self.desc.get_one::<String>("format").map(|s| s.as_str()) {
+            Some("conventional") => InputFormat::Conventional,
+            Some("nupack") => InputFormat::NupackOcx,
+            Some("composition") => InputFormat::CompositionStrings,
+            _ => InputFormat::Auto,
+        };
+        ParseOptions {
+            energy_layout,
+            delimiter,
+            format,
+        }
+    }
+
+    pub fn spacing(&self) -> SweepSpacing {
+        match self.desc.get_one::<String>("spacing").map(|s| s.as_str()) {
+            Some("log") => SweepSpacing::Log,
+            _ => SweepSpacing::Linear,
+        }
+    }
 }
 
-fn main() {
-    let args = CoffeeArgs::new();
+/// Prints the delimiter the CFE file is read with, for verbose output. Nothing is printed if it
+/// can't be detected, as reading the file then reports why.
+fn report_delimiter(cfe_path: &str, options: &ParseOptions) {
+    let message = match options.delimiter {
+        Delimiter::Auto => fs::read(cfe_path)
+            .ok()
+            .and_then(|content| detect_delimiter(&content).ok())
+            .map(|delimiter| delimiter_message(delimiter, true)),
+        delimiter => Some(delimiter_message(delimiter, false)),
+    };
+    if let Some(message) = message {
+        eprint!("{}", message);
+    }
+}
 
-    let cfe_path = if let Some(path) = args.get_file("cfe") {
-        path
-    } else {
-        eprintln!("CFE file path not provided.");
-        return;
+/// Runs the `sweep` subcommand, writing one row per sweep point.
+fn run_sweep(args: &CoffeeArgs) -> Result<(), CoffeeError> {
+    let (Some(cfe_path), Some(con_path)) = (args.get_file("cfe"), args.get_file("con")) else {
+        return Err(CoffeeError::InvalidInput(
+            "CFE and CON file paths must be provided.".to_string(),
+        ));
+    };
+    let verbose = args.verbose();
+    if verbose {
+        report_delimiter(&cfe_path, &args.parse_options());
+    }
+
+    let problem = read_problem(&cfe_path, &con_path, &args.parse_options())?;
+    for warning in problem.warnings() {
+        eprintln!("Warning: {}", warning);
+    }
+
+    let sweep = ConcentrationSweep {
+        monomers: args.get_indices("monomer"),
+        start: args.get_number("start").unwrap_or_default(),
+        stop: args.get_number("stop").unwrap_or_default(),
+        points: args.get_number("points").unwrap_or(10),
+        spacing: args.spacing(),
+    };
+    let optimizer_args = OptimizerArgs {
+        verbose,
+        use_terminal: false,
+        temp_celsius: args.temp_celsius().unwrap_or(37.0),
+        threads: args.get_number("threads").unwrap_or_default(),
+        solver: args.solver_kind(),
+        subproblem: args.subproblem_kind(),
+        ..OptimizerArgs::default()
+    };
+
+    let sweep_results = concentration_sweep(&problem, &sweep, &optimizer_args)?;
+
+    if verbose {
+        for (value, results) in sweep_results.values.iter().zip(&sweep_results.results) {
+            eprintln!(
+                "Concentration {:.6e}: {} after {} iterations, error = {:.6e}",
+                value, results.termination_reason, results.iterations, results.concentration_error
+            );
+        }
+    }
+
+    let out_path = args.get_file("output");
+    let table = sweep_table("concentration", &sweep_results, table_delimiter(&out_path));
+    write_table(out_path, &table)
+}
+
+/// Runs the `melt` subcommand, writing one row per temperature and the melting temperatures
+/// of the targets.
+fn run_melt(args: &CoffeeArgs) -> Result<(), CoffeeError> {
+    let (Some(cfe_path), Some(con_path)) = (args.get_file("cfe"), args.get_file("con")) else {
+        return Err(CoffeeError::InvalidInput(
+            "CFE and CON file paths must be provided.".to_string(),
+        ));
     };
-    let con_path = if let Some(path) = args.get_file("con") {
-        path
+    let verbose = args.verbose();
+    if verbose {
+        report_delimiter(&cfe_path, &args.parse_options());
+    }
+
+    let problem = read_problem(&cfe_path, &con_path, &args.parse_options())?;
+    for warning in problem.warnings() {
+        eprintln!("Warning: {}", warning);
+    }
+
+    let sweep = TemperatureSweep {
+        start: args.get_number("start").unwrap_or_default(),
+        stop: args.get_number("stop").unwrap_or_default(),
+        points: args.get_number("points").unwrap_or(10),
+        targets: args.get_indices("target"),
+    };
+    let optimizer_args = OptimizerArgs {
+        verbose,
+        use_terminal: false,
+        threads: args.get_number("threads").unwrap_or_default(),
+        solver: args.solver_kind(),
+        subproblem: args.subproblem_kind(),
+        ..OptimizerArgs::default()
+    };
+
+    let curve = temperature_sweep(&problem, &sweep, &optimizer_args)?;
+
+    if verbose {
+        for (value, results) in curve.sweep.values.iter().zip(&curve.sweep.results) {
+            eprintln!(
+                "Temperature {:.2} C: {} after {} iterations, error = {:.6e}",
+                value, results.termination_reason, results.iterations, results.concentration_error
+            );
+        }
+    }
+
+    /* Keep stdout a clean table when the table goes there. */
+    let out_path = args.get_file("output");
+    let table = melt_table(&curve, table_delimiter(&out_path));
+    let melting = melting_message(&curve);
+    if out_path.is_some() {
+        print!("{}", melting);
     } else {
-        eprintln!("CON file path not provided.");
-        return;
+        eprint!("{}", melting);
+    }
+    write_table(out_path, &table)
+}
+
+/// Runs the `sensitivity` subcommand, writing one column per selected polymer.
+fn run_sensitivity(args: &CoffeeArgs) -> Result<(), CoffeeError> {
+    let (Some(cfe_path), Some(con_path)) = (args.get_file("cfe"), args.get_file("con")) else {
+        return Err(CoffeeError::InvalidInput(
+            "CFE and CON file paths must be provided.".to_string(),
+        ));
+    };
+    let verbose = args.verbose();
+    if verbose {
+        report_delimiter(&cfe_path, &args.parse_options());
+    }
+
+    let problem = read_problem(&cfe_path, &con_path, &args.parse_options())?;
+    for warning in problem.warnings() {
+        eprintln!("Warning: {}", warning);
+    }
+
+    let optimizer_args = OptimizerArgs {
+        verbose,
+        use_terminal: false,
+        temp_celsius: args.temp_celsius().unwrap_or(37.0),
+        threads: args.get_number("threads").unwrap_or_default(),
+        solver: args.solver_kind(),
+        subproblem: args.subproblem_kind(),
+        ..OptimizerArgs::default()
+    };
+
+    let (results, sensitivities) =
+        problem.solve_with_sensitivities(&optimizer_args, &args.get_indices("polymer"))?;
+
+    if verbose {
+        eprintln!(
+            "Optimization {} after {} iterations, error = {:.6e}",
+            results.termination_reason, results.iterations, results.concentration_error
+        );
+    }
+
+    let out_path = args.get_file("output");
+    let table = sensitivity_table(&sensitivities, table_delimiter(&out_path));
+    write_table(out_path, &table)
+}
+
+/// Runs the `enumerate` subcommand, writing one row per complex.
+fn run_enumerate(args: &CoffeeArgs) -> Result<(), CoffeeError> {
+    let Some(con_path) = args.get_file("con") else {
+        return Err(CoffeeError::InvalidInput(
+            "CON file path must be provided.".to_string(),
+        ));
+    };
+    let (concentrations, strands) = fs::read(&con_path)
+        .map_err(|source| CoffeeError::Io {
+            path: con_path.clone(),
+            source,
+        })
+        .and_then(|content| parse_concentrations(&content))?;
+
+    let caps: Vec<usize> = args
+        .desc
+        .get_many::<usize>("cap")
+        .map(|caps| caps.copied().collect())
+        .unwrap_or_default();
+    let limits = ComplexLimits {
+        max_size: args.get_number::<u64>("max-size").unwrap_or(1) as usize,
+        caps: (!caps.is_empty()).then_some(caps),
+    };
+    let compositions = enumerate_compositions(concentrations.len(), &limits)?;
+
+    let energies = match args.get_file("energies") {
+        Some(path) => {
+            let Some(strands) = &strands else {
+                return Err(CoffeeError::InvalidInput(
+                    "The concentrations file must name the strands to read energies keyed by composition.".to_string(),
+                ));
+            };
+            let energies = fs::read(&path)
+                .map_err(|source| CoffeeError::Io {
+                    path: path.clone(),
+                    source,
+                })
+                .and_then(|content| EnergyTable::from_bytes(&content, strands))
+                .and_then(|table| free_energies(&compositions, &table))?;
+            Some(energies)
+        }
+        None => None,
+    };
+
+    let out_path = args.get_file("output");
+    let table = composition_table(
+        &compositions,
+        strands.as_deref(),
+        energies.as_ref(),
+        table_delimiter(&out_path),
+    );
+    write_table(out_path, &table)
+}
+
+/// Returns the column delimiter for a table output: commas for .csv files, otherwise tabs.
+fn table_delimiter(out_path: &Option<String>) -> char {
+    match out_path {
+        Some(path) if path.ends_with(".csv") => ',',
+        _ => '\t',
+    }
+}
+
+/// Writes a table to the output file if one was given, otherwise to stdout.
+fn write_table(out_path: Option<String>, table: &str) -> Result<(), CoffeeError> {
+    match out_path {
+        Some(path) => fs::write(&path, table).map_err(|source| CoffeeError::Io { path, source }),
+        None => {
+            print!("{}", table);
+            Ok(())
+        }
+    }
+}
+
+/// Returns the exit status for an error, grouping its kinds: 2 for inputs that couldn't be read
+/// or are invalid, 3 for files that couldn't be accessed, 4 for optimizations that failed or
+/// didn't converge, and 1 for anything else.
+///
+/// # Arguments
+///
+/// * `error` - The error the command failed with.
+///
+/// # Returns
+///
+/// The exit code.
+fn exit_code(error: &CoffeeError) -> ExitCode {
+    let code = match error.code() {
+        "parse" | "json" | "monomer_count_mismatch" | "energy_count_mismatch" | "invalid_input" => {
+            2
+        }
+        "io" => 3,
+        "non_finite_objective" | "subproblem_failed" | "singular_hessian" | "not_converged" => 4,
+        _ => 1,
+    };
+    ExitCode::from(code)
+}
+
+fn main() -> ExitCode {
+    let args = CoffeeArgs::new();
+
+    let sub_args = |matches: &clap::ArgMatches| CoffeeArgs {
+        desc: matches.clone(),
+    };
+    let result = match args.desc.subcommand() {
+        Some(("sweep", matches)) => run_sweep(&sub_args(matches)),
+        Some(("melt", matches)) => run_melt(&sub_args(matches)),
+        Some(("sensitivity", matches)) => run_sensitivity(&sub_args(matches)),
+        Some(("enumerate", matches)) => run_enumerate(&sub_args(matches)),
+        _ => run_solve(&args),
     };
 
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            exit_code(&e)
+        }
+    }
+}
+
+/// Runs the default command, solving one problem and writing its results.
+fn run_solve(args: &CoffeeArgs) -> Result<(), CoffeeError> {
     let log_path = args.get_file("log");
     let out_path = args.get_file("output");
+    let history_path = args.get_file("history");
     let verbose = args.verbose();
     let temp_celsius = args.temp_celsius().unwrap_or(37.0);
+    let mut time_limit = args
+        .get_number::<f64>("time-limit")
+        .map(|seconds| TimeLimit::new(Duration::from_secs_f64(seconds)));
 
+    let initial_lambda = if let Some(path) = args.get_file("init-lambda") {
+        let lambda = fs::read(&path)
+            .map_err(|source| CoffeeError::Io {
+                path: path.clone(),
+                source,
+            })
+            .and_then(|c| parse_lambdas(&c))?;
+        InitialLambda::Given(lambda)
+    } else if args.heuristic_lambda() {
+        InitialLambda::Heuristic
+    } else {
+        InitialLambda::Zero
+    };
+
+    /* Keep stdout to the results when they are machine-readable. */
+    let output_format = args.output_format();
     let optimizer_args = OptimizerArgs {
         verbose,
-        use_terminal: log_path.is_none(),
+        use_terminal: log_path.is_none() && output_format == OutputFormat::Text,
         temp_celsius,
+        initial_lambda,
+        hessian_mode: args.hessian_mode(),
+        matrix_storage: args.matrix_storage(),
+        threads: args.get_number("threads").unwrap_or_default(),
+        solver: args.solver_kind(),
+        subproblem: args.subproblem_kind(),
         ..OptimizerArgs::default()
     };
 
-    // Call run_coffee with the file paths and get the result
-    let coffee_result = run_coffee(
-        &cfe_path,
-        &con_path,
-        log_path.as_deref(),
-        out_path.as_deref(),
-        &optimizer_args,
-    );
-
-    // Pass the result to print it
-    match coffee_result {
-        Ok(_) => return,
-        Err(e) => format!("Error: {}", e),
+    let output_paths = OutputPaths {
+        log: log_path.as_deref(),
+        results: out_path.as_deref(),
+        history: history_path.as_deref(),
+        results_format: output_format,
     };
+    let observer = time_limit.as_mut().map(|limit| limit as &mut dyn Observer);
+
+    /* A JSON problem replaces the CFE and CON files, and its settings override the flags. */
+    if let Some(json_path) = args.get_file("json") {
+        run_coffee_json(&json_path, &output_paths, &optimizer_args, observer)?;
+    } else {
+        let Some(cfe_path) = args.get_file("cfe") else {
+            return Err(CoffeeError::InvalidInput(
+                "CFE file path not provided.".to_string(),
+            ));
+        };
+        let Some(con_path) = args.get_file("con") else {
+            return Err(CoffeeError::InvalidInput(
+                "CON file path not provided.".to_string(),
+            ));
+        };
+
+        run_coffee(
+            &cfe_path,
+            &con_path,
+            &output_paths,
+            &args.parse_options(),
+            &optimizer_args,
+            observer,
+        )?;
+    }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -179,6 +926,8 @@ mod tests {
         );
         assert_eq!(args.get_file("log"), None);
         assert_eq!(args.get_file("output"), None);
+        assert_eq!(args.get_file("history"), None);
+        assert_eq!(args.get_number::<f64>("time-limit"), None);
         assert!(!args.verbose());
 
         /* Test 0 and 1 args, which should fail. */
@@ -198,6 +947,42 @@ mod tests {
         assert!(matches.is_err());
     }
 
+    #[test]
+    fn test_json_args() {
+        let matches = command().try_get_matches_from(vec![
+            "coffee_cli",
+            "--json",
+            "problem.json",
+            "-o",
+            "output.txt",
+        ]);
+        let args = CoffeeArgs {
+            desc: matches.unwrap(),
+        };
+        assert_eq!(args.get_file("json"), Some("problem.json".to_string()));
+        assert_eq!(args.get_file("cfe"), None);
+
+        /* JSON replaces the CFE and CON files and their layout flags. */
+        let matches = command().try_get_matches_from(vec![
+            "coffee_cli",
+            "~/coffee-internal/testcases/0/input.ocx",
+            "~/coffee-internal/testcases/0/input.con",
+            "--json",
+            "problem.json",
+        ]);
+        assert!(matches.is_err());
+        let matches = command().try_get_matches_from(vec![
+            "coffee_cli",
+            "--json",
+            "problem.json",
+            "--format",
+            "nupack",
+        ]);
+        assert!(matches.is_err());
+        let matches = command().try_get_matches_from(vec!["coffee_cli", "--json", "problem.txt"]);
+        assert!(matches.is_err());
+    }
+
     #[test]
     fn test_optional_args() {
         /* Test optional args with valid inputs, long version. */
@@ -209,6 +994,10 @@ mod tests {
             "~/coffee-internal/testcases/0/log.txt",
             "--output",
             "~/coffee-internal/testcases/0/output.txt",
+            "--history",
+            "~/coffee-internal/testcases/0/history.csv",
+            "--time-limit",
+            "2.5",
             "--verbose",
         ]);
         assert!(matches.is_ok());
@@ -224,6 +1013,11 @@ mod tests {
             args.get_file("output"),
             Some("~/coffee-internal/testcases/0/output.txt".to_string())
         );
+        assert_eq!(
+            args.get_file("history"),
+            Some("~/coffee-internal/testcases/0/history.csv".to_string())
+        );
+        assert_eq!(args.get_number::<f64>("time-limit"), Some(2.5));
         assert!(args.verbose());
 
         /* Test optional args with valid inputs, short version. */
@@ -302,5 +1096,530 @@ mod tests {
             desc: matches.unwrap()
         }
         .verbose());
+
+        /* A time limit must be a non-negative number of seconds. */
+        matches = command().try_get_matches_from(vec![
+            "coffee_cli",
+            "~/coffee-internal/testcases/0/input.ocx",
+            "~/coffee-internal/testcases/0/input.con",
+            "--time-limit",
+            "-1",
+        ]);
+        assert!(matches.is_err());
+    }
+
+    #[test]
+    fn test_initial_lambda_args() {
+        let matches = command().try_get_matches_from(vec![
+            "coffee_cli",
+            "~/coffee-internal/testcases/0/input.ocx",
+            "~/coffee-internal/testcases/0/input.con",
+            "--init-lambda",
+            "~/coffee-internal/testcases/0/lambda.txt",
+        ]);
+        assert!(matches.is_ok());
+
+        let args = CoffeeArgs {
+            desc: matches.unwrap(),
+        };
+        assert_eq!(
+            args.get_file("init-lambda"),
+            Some("~/coffee-internal/testcases/0/lambda.txt".to_string())
+        );
+        assert!(!args.heuristic_lambda());
+        assert_eq!(args.hessian_mode(), HessianMode::Auto);
+
+        /* A file and the heuristic can't both be the starting point. */
+        let matches = command().try_get_matches_from(vec![
+            "coffee_cli",
+            "~/coffee-internal/testcases/0/input.ocx",
+            "~/coffee-internal/testcases/0/input.con",
+            "--init-lambda",
+            "~/coffee-internal/testcases/0/lambda.txt",
+            "--heuristic-lambda",
+        ]);
+        assert!(matches.is_err());
+    }
+
+    #[test]
+    fn test_sweep_args() {
+        let matches = command().try_get_matches_from(vec![
+            "coffee_cli",
+            "sweep",
+            "~/coffee-internal/testcases/2/input.ocx",
+            "~/coffee-internal/testcases/2/input.con",
+            "--monomer",
+            "1",
+            "-m",
+            "3",
+            "--start",
+            "1e-8",
+            "--stop",
+            "1e-6",
+            "--spacing",
+            "log",
+        ]);
+        assert!(matches.is_ok());
+
+        let matches = matches.unwrap();
+        let (name, sweep_matches) = matches.subcommand().unwrap();
+        assert_eq!(name, "sweep");
+        let args = CoffeeArgs {
+            desc: sweep_matches.clone(),
+        };
+        assert_eq!(args.get_indices("monomer"), vec![0, 2]);
+        assert_eq!(args.get_number::<f64>("start"), Some(1e-8));
+        assert_eq!(args.get_number::<usize>("points"), Some(10));
+        assert_eq!(args.spacing(), SweepSpacing::Log);
+
+        /* Monomers are numbered from 1, and a range is required. */
+        let matches = command().try_get_matches_from(vec![
+            "coffee_cli",
+            "sweep",
+            "~/coffee-internal/testcases/2/input.ocx",
+            "~/coffee-internal/testcases/2/input.con",
+            "--monomer",
+            "0",
+            "--start",
+            "1e-8",
+            "--stop",
+            "1e-6",
+        ]);
+        assert!(matches.is_err());
+
+        let matches = command().try_get_matches_from(vec![
+            "coffee_cli",
+            "sweep",
+            "~/coffee-internal/testcases/2/input.ocx",
+            "~/coffee-internal/testcases/2/input.con",
+            "--monomer",
+            "1",
+        ]);
+        assert!(matches.is_err());
+    }
+
+    #[test]
+    fn test_melt_args() {
+        let matches = command().try_get_matches_from(vec![
+            "coffee_cli",
+            "melt",
+            "~/coffee-internal/testcases/2/input.ocx",
+            "~/coffee-internal/testcases/2/input.con",
+            "--start",
+            "20",
+            "--stop",
+            "90",
+            "--target",
+            "5",
+            "--target",
+            "6",
+            "-o",
+            "~/coffee-internal/testcases/2/melt.csv",
+        ]);
+        assert!(matches.is_ok());
+
+        let matches = matches.unwrap();
+        let (name, melt_matches) = matches.subcommand().unwrap();
+        assert_eq!(name, "melt");
+        let args = CoffeeArgs {
+            desc: melt_matches.clone(),
+        };
+        assert_eq!(args.get_indices("target"), vec![4, 5]);
+        assert_eq!(args.get_number::<f64>("stop"), Some(90.0));
+        assert_eq!(
+            args.get_file("output"),
+            Some("~/coffee-internal/testcases/2/melt.csv".to_string())
+        );
+    }
+
+    #[test]
+    fn test_sensitivity_args() {
+        let matches = command().try_get_matches_from(vec![
+            "coffee_cli",
+            "sensitivity",
+            "~/coffee-internal/testcases/2/input.ocx",
+            "~/coffee-internal/testcases/2/input.con",
+            "--polymer",
+            "5",
+            "-p",
+            "7",
+            "-o",
+            "~/coffee-internal/testcases/2/sensitivity.csv",
+        ]);
+        assert!(matches.is_ok());
+
+        let matches = matches.unwrap();
+        let (name, sensitivity_matches) = matches.subcommand().unwrap();
+        assert_eq!(name, "sensitivity");
+        let args = CoffeeArgs {
+            desc: sensitivity_matches.clone(),
+        };
+        assert_eq!(args.get_indices("polymer"), vec![4, 6]);
+
+        /* At least one polymer is required. */
+        let matches = command().try_get_matches_from(vec![
+            "coffee_cli",
+            "sensitivity",
+            "~/coffee-internal/testcases/2/input.ocx",
+            "~/coffee-internal/testcases/2/input.con",
+        ]);
+        assert!(matches.is_err());
+    }
+
+    #[test]
+    fn test_enumerate_args() {
+        let matches = command().try_get_matches_from(vec![
+            "coffee_cli",
+            "enumerate",
+            "small.con",
+            "--max-size",
+            "3",
+            "--cap",
+            "1",
+            "--cap",
+            "2",
+            "--energies",
+            "small.dg",
+            "-o",
+            "complexes.tsv",
+        ]);
+        assert!(matches.is_ok());
+
+        let matches = matches.unwrap();
+        let (name, enumerate_matches) = matches.subcommand().unwrap();
+        assert_eq!(name, "enumerate");
+        let args = CoffeeArgs {
+            desc: enumerate_matches.clone(),
+        };
+        assert_eq!(args.get_file("con"), Some("small.con".to_string()));
+        assert_eq!(args.get_number::<u64>("max-size"), Some(3));
+        assert_eq!(
+            args.desc
+                .get_many::<usize>("cap")
+                .unwrap()
+                .copied()
+                .collect::<Vec<usize>>(),
+            vec![1, 2]
+        );
+        assert_eq!(args.get_file("energies"), Some("small.dg".to_string()));
+
+        /* The maximum size is required and at least 1. */
+        let matches = command().try_get_matches_from(vec!["coffee_cli", "enumerate", "small.con"]);
+        assert!(matches.is_err());
+        let matches = command().try_get_matches_from(vec![
+            "coffee_cli",
+            "enumerate",
+            "small.con",
+            "--max-size",
+            "0",
+        ]);
+        assert!(matches.is_err());
+
+        /* So is every cap. */
+        let matches = command().try_get_matches_from(vec![
+            "coffee_cli",
+            "enumerate",
+            "small.con",
+            "--max-size",
+            "2",
+            "--cap",
+            "0",
+        ]);
+        assert!(matches.is_err());
+    }
+
+    #[test]
+    fn test_energy_columns_args() {
+        let matches = command().try_get_matches_from(vec![
+            "coffee_cli",
+            "~/coffee-internal/testcases/0/input.ocx",
+            "~/coffee-internal/testcases/0/input.con",
+        ]);
+        let args = CoffeeArgs {
+            desc: matches.unwrap(),
+        };
+        assert_eq!(args.parse_options().energy_layout, EnergyLayout::FreeEnergy);
+
+        let matches = command().try_get_matches_from(vec![
+            "coffee_cli",
+            "melt",
+            "~/coffee-internal/testcases/2/input.ocx",
+            "~/coffee-internal/testcases/2/input.con",
+            "--start",
+            "20",
+            "--stop",
+            "90",
+            "--energy-columns",
+            "dh-ds-dcp",
+        ]);
+        let matches = matches.unwrap();
+        let args = CoffeeArgs {
+            desc: matches.subcommand().unwrap().1.clone(),
+        };
+        assert_eq!(
+            args.parse_options().energy_layout,
+            EnergyLayout::EnthalpyEntropyHeatCapacity
+        );
+
+        let matches = command().try_get_matches_from(vec![
+            "coffee_cli",
+            "~/coffee-internal/testcases/0/input.ocx",
+            "~/coffee-internal/testcases/0/input.con",
+            "--energy-columns",
+            "dg-dh",
+        ]);
+        assert!(matches.is_err());
+    }
+
+    #[test]
+    fn test_delimiter_args() {
+        let matches = command().try_get_matches_from(vec![
+            "coffee_cli",
+            "~/coffee-internal/testcases/0/input.ocx",
+            "~/coffee-internal/testcases/0/input.con",
+        ]);
+        let args = CoffeeArgs {
+            desc: matches.unwrap(),
+        };
+        assert_eq!(args.parse_options().delimiter, Delimiter::Auto);
+
+        let matches = command().try_get_matches_from(vec![
+            "coffee_cli",
+            "sweep",
+            "~/coffee-internal/testcases/2/input.ocx",
+            "~/coffee-internal/testcases/2/input.con",
+            "--monomer",
+            "1",
+            "--start",
+            "1e-7",
+            "--stop",
+            "1e-6",
+            "--delimiter",
+            "whitespace",
+        ]);
+        let matches = matches.unwrap();
+        let args = CoffeeArgs {
+            desc: matches.subcommand().unwrap().1.clone(),
+        };
+        assert_eq!(args.parse_options().delimiter, Delimiter::Whitespace);
+
+        let matches = command().try_get_matches_from(vec![
+            "coffee_cli",
+            "~/coffee-internal/testcases/0/input.ocx",
+            "~/coffee-internal/testcases/0/input.con",
+            "--delimiter",
+            "colon",
+        ]);
+        assert!(matches.is_err());
+    }
+
+    #[test]
+    fn test_format_args() {
+        let matches = command().try_get_matches_from(vec![
+            "coffee_cli",
+            "~/coffee-internal/testcases/0/input.ocx",
+            "~/coffee-internal/testcases/0/input.con",
+        ]);
+        let args = CoffeeArgs {
+            desc: matches.unwrap(),
+        };
+        assert_eq!(args.parse_options().format, InputFormat::Auto);
+
+        let matches = command().try_get_matches_from(vec![
+            "coffee_cli",
+            "melt",
+            "~/coffee-internal/testcases/2/input.ocx",
+            "~/coffee-internal/testcases/2/input.con",
+            "--start",
+            "20",
+            "--stop",
+            "80",
+            "--format",
+            "conventional",
+        ]);
+        let matches = matches.unwrap();
+        let args = CoffeeArgs {
+            desc: matches.subcommand().unwrap().1.clone(),
+        };
+        assert_eq!(args.parse_options().format, InputFormat::Conventional);
+
+        let matches = command().try_get_matches_from(vec![
+            "coffee_cli",
+            "~/coffee-internal/testcases/0/input.ocx",
+            "~/coffee-internal/testcases/0/input.con",
+            "--format",
+            "nupack",
+        ]);
+        let args = CoffeeArgs {
+            desc: matches.unwrap(),
+        };
+        assert_eq!(args.parse_options().format, InputFormat::NupackOcx);
+
+        let matches = command().try_get_matches_from(vec![
+            "coffee_cli",
+            "small.cfe",
+            "small.con",
+            "--format",
+            "composition",
+        ]);
+        let args = CoffeeArgs {
+            desc: matches.unwrap(),
+        };
+        assert_eq!(args.parse_options().format, InputFormat::CompositionStrings);
+    }
+
+    #[test]
+    fn test_output_format_args() {
+        let matches = command().try_get_matches_from(vec![
+            "coffee_cli",
+            "~/coffee-internal/testcases/0/input.ocx",
+            "~/coffee-internal/testcases/0/input.con",
+        ]);
+        let args = CoffeeArgs {
+            desc: matches.unwrap(),
+        };
+        assert_eq!(args.output_format(), OutputFormat::Text);
+
+        /* The output file's extension picks the format unless one is given. */
+        let matches = command().try_get_matches_from(vec![
+            "coffee_cli",
+            "~/coffee-internal/testcases/0/input.ocx",
+            "~/coffee-internal/testcases/0/input.con",
+            "-o",
+            "results.json",
+        ]);
+        let args = CoffeeArgs {
+            desc: matches.unwrap(),
+        };
+        assert_eq!(args.output_format(), OutputFormat::Json);
+
+        let matches = command().try_get_matches_from(vec![
+            "coffee_cli",
+            "~/coffee-internal/testcases/0/input.ocx",
+            "~/coffee-internal/testcases/0/input.con",
+            "-o",
+            "results.txt",
+            "--output-format",
+            "tsv",
+        ]);
+        let args = CoffeeArgs {
+            desc: matches.unwrap(),
+        };
+        assert_eq!(args.output_format(), OutputFormat::Tsv);
+
+        let matches = command().try_get_matches_from(vec![
+            "coffee_cli",
+            "~/coffee-internal/testcases/0/input.ocx",
+            "~/coffee-internal/testcases/0/input.con",
+            "--output-format",
+            "xml",
+        ]);
+        assert!(matches.is_err());
+    }
+
+    #[test]
+    fn test_linear_algebra_args() {
+        let matches = command().try_get_matches_from(vec![
+            "coffee_cli",
+            "~/coffee-internal/testcases/0/input.ocx",
+            "~/coffee-internal/testcases/0/input.con",
+            "--hessian",
+            "matrix-free",
+        ]);
+        let args = CoffeeArgs {
+            desc: matches.unwrap(),
+        };
+        assert_eq!(args.hessian_mode(), HessianMode::MatrixFree);
+        assert_eq!(args.matrix_storage(), MatrixStorage::Auto);
+
+        let matches = command().try_get_matches_from(vec![
+            "coffee_cli",
+            "~/coffee-internal/testcases/0/input.ocx",
+            "~/coffee-internal/testcases/0/input.con",
+            "--storage",
+            "sparse",
+        ]);
+        let args = CoffeeArgs {
+            desc: matches.unwrap(),
+        };
+        assert_eq!(args.matrix_storage(), MatrixStorage::Sparse);
+        assert_eq!(args.get_number::<usize>("threads"), Some(0));
+
+        let matches = command().try_get_matches_from(vec![
+            "coffee_cli",
+            "sweep",
+            "~/coffee-internal/testcases/2/input.ocx",
+            "~/coffee-internal/testcases/2/input.con",
+            "-m",
+            "1",
+            "--start",
+            "1e-8",
+            "--stop",
+            "1e-6",
+            "--threads",
+            "4",
+        ]);
+        let matches = matches.unwrap();
+        let args = CoffeeArgs {
+            desc: matches.subcommand().unwrap().1.clone(),
+        };
+        assert_eq!(args.get_number::<usize>("threads"), Some(4));
+        assert_eq!(args.solver_kind(), SolverKind::TrustRegion);
+        assert_eq!(args.subproblem_kind(), SubproblemKind::Steihaug);
+
+        let matches = command().try_get_matches_from(vec![
+            "coffee_cli",
+            "~/coffee-internal/testcases/0/input.ocx",
+            "~/coffee-internal/testcases/0/input.con",
+            "--solver",
+            "newton-line-search",
+        ]);
+        let args = CoffeeArgs {
+            desc: matches.unwrap(),
+        };
+        assert_eq!(args.solver_kind(), SolverKind::NewtonLineSearch);
+
+        let matches = command().try_get_matches_from(vec![
+            "coffee_cli",
+            "~/coffee-internal/testcases/0/input.ocx",
+            "~/coffee-internal/testcases/0/input.con",
+            "--subproblem",
+            "dogleg",
+        ]);
+        let args = CoffeeArgs {
+            desc: matches.unwrap(),
+        };
+        assert_eq!(args.subproblem_kind(), SubproblemKind::Dogleg);
+
+        let matches = command().try_get_matches_from(vec![
+            "coffee_cli",
+            "~/coffee-internal/testcases/0/input.ocx",
+            "~/coffee-internal/testcases/0/input.con",
+            "--hessian",
+            "sparse",
+        ]);
+        assert!(matches.is_err());
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        let invalid = CoffeeError::InvalidInput("No monomers given to sweep.".to_string());
+        assert_eq!(exit_code(&invalid), ExitCode::from(2));
+        let io = CoffeeError::Io {
+            path: "missing.cfe".to_string(),
+            source: std::io::Error::from(std::io::ErrorKind::NotFound),
+        };
+        assert_eq!(exit_code(&io), ExitCode::from(3));
+
+        /* A failed sweep point exits like the error that failed it. */
+        let sweep = CoffeeError::SweepPointFailed {
+            point: 2,
+            source: Box::new(CoffeeError::SingularHessian),
+        };
+        assert_eq!(exit_code(&sweep), ExitCode::from(4));
+        assert_eq!(
+            exit_code(&CoffeeError::ThreadPool("no threads".to_string())),
+            ExitCode::from(1)
+        );
     }
 }
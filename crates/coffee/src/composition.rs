@@ -0,0 +1,445 @@
+use crate::error::CoffeeError;
+use crate::parallel::{chunk_ranges, ordered_reduce};
+use ndarray::{s, Array1, Array2, ArrayView1, Axis};
+use rayon::prelude::*;
+use serde::Deserialize;
+
+/// Largest fraction of nonzero entries for which `MatrixStorage::Auto` stores the composition
+/// matrix as sparse.
+const AUTO_SPARSE_MAX_DENSITY: f64 = 0.3;
+
+/// How the polymer composition matrix is stored.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MatrixStorage {
+    /// Use sparse storage when the matrix is mostly zeros and only holds small integer counts,
+    /// and dense storage otherwise.
+    #[default]
+    Auto,
+    /// Store every entry as an f64.
+    Dense,
+    /// Store only the nonzero entries, as small integer counts in compressed rows.
+    Sparse,
+}
+
+/// The polymer composition matrix A (N x M), where entry (j, i) is the number of monomer i in
+/// polymer j. Provides the matrix products the optimizer needs regardless of storage.
+/// The products are split over the polymers in parallel, and reductions combine the chunks in a
+/// fixed order so the results don't depend on the number of threads.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CompositionMatrix {
+    Dense(Array2<f64>),
+    Sparse(SparseComposition),
+}
+
+/// Composition matrix in compressed sparse row (CSR) form. The nonzero entries of row j are
+/// `columns[row_offsets[j]..row_offsets[j + 1]]`, with the matching counts.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SparseComposition {
+    num_columns: usize,
+    row_offsets: Vec<usize>,
+    columns: Vec<u32>,
+    counts: Vec<u16>,
+}
+
+impl SparseComposition {
+    /// Compresses a dense composition matrix.
+    ///
+    /// # Arguments
+    ///
+    /// * `polymers` - The dense composition matrix.
+    ///
+    /// # Returns
+    ///
+    /// The sparse matrix, or an error if an entry is not a small non-negative integer.
+    fn from_dense(polymers: &Array2<f64>) -> Result<Self, CoffeeError> {
+        let mut row_offsets = Vec::with_capacity(polymers.nrows() + 1);
+        let mut columns = Vec::new();
+        let mut counts = Vec::new();
+        row_offsets.push(0);
+
+        for (row, values) in polymers.rows().into_iter().enumerate() {
+            for (col, &value) in values.iter().enumerate() {
+                if value == 0.0 {
+                    continue;
+                }
+                if !is_count(value) {
+                    return Err(CoffeeError::InvalidInput(format!(
+                        "Composition of polymer {} for monomer {} is not a count that can be stored sparsely.",
+                        row, col
+                    )));
+                }
+                columns.push(col as u32);
+                counts.push(value as u16);
+            }
+            row_offsets.push(columns.len());
+        }
+
+        Ok(SparseComposition {
+            num_columns: polymers.ncols(),
+            row_offsets,
+            columns,
+            counts,
+        })
+    }
+
+    /// Returns the column indices and counts of the nonzero entries of a row.
+    fn row(&self, row: usize) -> impl Iterator<Item = (usize, f64)> + '_ {
+        let range = self.row_offsets[row]..self.row_offsets[row + 1];
+        self.columns[range.clone()]
+            .iter()
+            .zip(&self.counts[range])
+            .map(|(&col, &count)| (col as usize, count as f64))
+    }
+}
+
+/// Returns whether a value is a non-negative integer small enough for sparse storage.
+fn is_count(value: f64) -> bool {
+    value >= 0.0 && value <= u16::MAX as f64 && value.fract() == 0.0
+}
+
+impl CompositionMatrix {
+    /// Converts the matrix to the given storage.
+    ///
+    /// # Arguments
+    ///
+    /// * `storage` - The storage to use. `Auto` picks sparse storage when at most
+    ///   `AUTO_SPARSE_MAX_DENSITY` of the entries are nonzero and all are small integer counts.
+    ///
+    /// # Returns
+    ///
+    /// The converted matrix, or an error if sparse storage was requested for entries that aren't
+    /// small integer counts.
+    pub fn with_storage(self, storage: MatrixStorage) -> Result<Self, CoffeeError> {
+        match (storage, self) {
+            (MatrixStorage::Dense, CompositionMatrix::Sparse(sparse)) => Ok(
+                CompositionMatrix::Dense(CompositionMatrix::Sparse(sparse).to_dense()),
+            ),
+            (MatrixStorage::Sparse, CompositionMatrix::Dense(dense)) => Ok(
+                CompositionMatrix::Sparse(SparseComposition::from_dense(&dense)?),
+            ),
+            (MatrixStorage::Auto, matrix) => {
+                let sparse = matrix.density() <= AUTO_SPARSE_MAX_DENSITY;
+                match matrix {
+                    CompositionMatrix::Dense(dense)
+                        if sparse && dense.iter().all(|&a| is_count(a)) =>
+                    {
+                        Ok(CompositionMatrix::Sparse(SparseComposition::from_dense(
+                            &dense,
+                        )?))
+                    }
+                    CompositionMatrix::Sparse(_) if !sparse => {
+                        Ok(CompositionMatrix::Dense(matrix.to_dense()))
+                    }
+                    matrix => Ok(matrix),
+                }
+            }
+            (_, matrix) => Ok(matrix),
+        }
+    }
+
+    /// Returns the current storage, either `Dense` or `Sparse`.
+    pub fn storage(&self) -> MatrixStorage {
+        match self {
+            CompositionMatrix::Dense(_) => MatrixStorage::Dense,
+            CompositionMatrix::Sparse(_) => MatrixStorage::Sparse,
+        }
+    }
+
+    /// Returns the number of polymers, N.
+    pub fn nrows(&self) -> usize {
+        match self {
+            CompositionMatrix::Dense(dense) => dense.nrows(),
+            CompositionMatrix::Sparse(sparse) => sparse.row_offsets.len() - 1,
+        }
+    }
+
+    /// Returns the number of monomers, M.
+    pub fn ncols(&self) -> usize {
+        match self {
+            CompositionMatrix::Dense(dense) => dense.ncols(),
+            CompositionMatrix::Sparse(sparse) => sparse.num_columns,
+        }
+    }
+
+    /// Returns the number of nonzero entries.
+    pub fn nnz(&self) -> usize {
+        match self {
+            CompositionMatrix::Dense(dense) => dense.iter().filter(|&&a| a != 0.0).count(),
+            CompositionMatrix::Sparse(sparse) => sparse.counts.len(),
+        }
+    }
+
+    /// Returns the fraction of entries that are nonzero.
+    pub fn density(&self) -> f64 {
+        let size = self.nrows() * self.ncols();
+        if size == 0 {
+            0.0
+        } else {
+            self.nnz() as f64 / size as f64
+        }
+    }
+
+    /// Returns an iterator over the nonzero entries as (polymer, monomer, count), in row order.
+    pub fn nonzeros(&self) -> Box<dyn Iterator<Item = (usize, usize, f64)> + '_> {
+        match self {
+            CompositionMatrix::Dense(dense) => Box::new(
+                dense
+                    .indexed_iter()
+                    .filter(|(_, &a)| a != 0.0)
+                    .map(|((row, col), &a)| (row, col, a)),
+            ),
+            CompositionMatrix::Sparse(sparse) => Box::new(
+                (0..sparse.row_offsets.len() - 1)
+                    .flat_map(move |row| sparse.row(row).map(move |(col, a)| (row, col, a))),
+            ),
+        }
+    }
+
+    /// Finds the concentration of each monomer in its free form, the polymer made of one unit of
+    /// that monomer alone. Monomers with no such polymer have no free concentration.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - The concentration of each polymer.
+    ///
+    /// # Returns
+    ///
+    /// The free concentration of each monomer.
+    pub fn free_monomers(&self, x: &[f64]) -> Vec<f64> {
+        /* Find the rows with a single nonzero entry, and which monomer it is. */
+        let mut single: Vec<Option<(usize, f64)>> = vec![None; self.nrows()];
+        let mut num_nonzero = vec![0usize; self.nrows()];
+        for (row, monomer, count) in self.nonzeros() {
+            num_nonzero[row] += 1;
+            single[row] = Some((monomer, count));
+        }
+
+        let mut free = vec![0.0; self.ncols()];
+        for ((single, num_nonzero), x_val) in single.iter().zip(&num_nonzero).zip(x) {
+            if let (Some((monomer, count)), 1) = (single, num_nonzero) {
+                if *count == 1.0 {
+                    free[*monomer] += x_val;
+                }
+            }
+        }
+        free
+    }
+
+    /// Returns the composition of one polymer, with one count per monomer.
+    ///
+    /// # Arguments
+    ///
+    /// * `row` - The index of the polymer.
+    ///
+    /// # Panics
+    ///
+    /// If `row` is out of bounds.
+    pub fn row(&self, row: usize) -> Array1<f64> {
+        match self {
+            CompositionMatrix::Dense(dense) => dense.row(row).to_owned(),
+            CompositionMatrix::Sparse(sparse) => {
+                let mut composition = Array1::zeros(sparse.num_columns);
+                for (col, a) in sparse.row(row) {
+                    composition[col] = a;
+                }
+                composition
+            }
+        }
+    }
+
+    /// Calculates A v.
+    ///
+    /// # Arguments
+    ///
+    /// * `v` - A vector with one entry per monomer.
+    ///
+    /// # Returns
+    ///
+    /// A vector with one entry per polymer.
+    pub fn dot(&self, v: ArrayView1<f64>) -> Array1<f64> {
+        let chunks: Vec<Array1<f64>> = chunk_ranges(self.nrows())
+            .into_par_iter()
+            .map(|range| match self {
+                CompositionMatrix::Dense(dense) => dense.slice(s![range, ..]).dot(&v),
+                CompositionMatrix::Sparse(sparse) => range
+                    .map(|row| sparse.row(row).map(|(col, a)| a * v[col]).sum())
+                    .collect(),
+            })
+            .collect();
+        chunks.iter().flatten().copied().collect()
+    }
+
+    /// Calculates A^T w.
+    ///
+    /// # Arguments
+    ///
+    /// * `w` - A vector with one entry per polymer.
+    ///
+    /// # Returns
+    ///
+    /// A vector with one entry per monomer.
+    pub fn t_dot(&self, w: ArrayView1<f64>) -> Array1<f64> {
+        ordered_reduce(
+            self.nrows(),
+            Array1::zeros(self.ncols()),
+            |range| match self {
+                CompositionMatrix::Dense(dense) => dense
+                    .slice(s![range.clone(), ..])
+                    .t()
+                    .dot(&w.slice(s![range])),
+                CompositionMatrix::Sparse(sparse) => {
+                    let mut result = Array1::zeros(sparse.num_columns);
+                    for row in range {
+                        for (col, a) in sparse.row(row) {
+                            result[col] += a * w[row];
+                        }
+                    }
+                    result
+                }
+            },
+            |total, part| *total += &part,
+        )
+    }
+
+    /// Calculates A^T diag(w) A.
+    ///
+    /// # Arguments
+    ///
+    /// * `w` - A vector with one weight per polymer.
+    ///
+    /// # Returns
+    ///
+    /// A symmetric M x M matrix.
+    pub fn weighted_gram(&self, w: ArrayView1<f64>) -> Array2<f64> {
+        ordered_reduce(
+            self.nrows(),
+            Array2::zeros((self.ncols(), self.ncols())),
+            |range| match self {
+                CompositionMatrix::Dense(dense) => {
+                    let rows = dense.slice(s![range.clone(), ..]);
+                    let weighted = &rows * &w.slice(s![range]).insert_axis(Axis(1));
+                    rows.t().dot(&weighted)
+                }
+                CompositionMatrix::Sparse(sparse) => {
+                    let mut result = Array2::zeros((sparse.num_columns, sparse.num_columns));
+                    for row in range {
+                        for (i, a) in sparse.row(row) {
+                            for (j, b) in sparse.row(row) {
+                                result[[i, j]] += a * b * w[row];
+                            }
+                        }
+                    }
+                    result
+                }
+            },
+            |total, part| *total += &part,
+        )
+    }
+
+    /// Returns the matrix as a dense 2-dimensional array.
+    pub fn to_dense(&self) -> Array2<f64> {
+        match self {
+            CompositionMatrix::Dense(dense) => dense.clone(),
+            CompositionMatrix::Sparse(_) => {
+                let mut dense = Array2::zeros((self.nrows(), self.ncols()));
+                for (row, col, a) in self.nonzeros() {
+                    dense[[row, col]] = a;
+                }
+                dense
+            }
+        }
+    }
+}
+
+impl From<Array2<f64>> for CompositionMatrix {
+    fn from(polymers: Array2<f64>) -> Self {
+        CompositionMatrix::Dense(polymers)
+    }
+}
+
+impl From<&Array2<f64>> for CompositionMatrix {
+    fn from(polymers: &Array2<f64>) -> Self {
+        CompositionMatrix::Dense(polymers.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn test_sparse_products() {
+        let polymers = array![
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 0.0, 1.0],
+            [2.0, 1.0, 0.0],
+            [0.0, 1.0, 3.0]
+        ];
+        let dense = CompositionMatrix::from(&polymers);
+        let sparse = dense.clone().with_storage(MatrixStorage::Sparse).unwrap();
+        assert_eq!(sparse.storage(), MatrixStorage::Sparse);
+        assert_eq!(sparse.nnz(), 7);
+        assert_eq!(sparse.to_dense(), polymers);
+
+        let v = array![0.5, -1.0, 2.0];
+        let w = array![1.0, 2.0, 3.0, 4.0, 5.0];
+        assert_eq!(sparse.dot(v.view()), dense.dot(v.view()));
+        assert_eq!(sparse.t_dot(w.view()), dense.t_dot(w.view()));
+        assert_eq!(
+            sparse.weighted_gram(w.view()),
+            dense.weighted_gram(w.view())
+        );
+        assert_eq!(
+            sparse.nonzeros().collect::<Vec<_>>(),
+            dense.nonzeros().collect::<Vec<_>>()
+        );
+        assert_eq!(sparse.row(4), array![0.0, 1.0, 3.0]);
+        assert_eq!(dense.row(3), sparse.row(3));
+    }
+
+    #[test]
+    fn test_storage_selection() {
+        /* A mostly zero matrix of counts is stored sparsely. */
+        let mut polymers = Array2::<f64>::zeros((10, 10));
+        for i in 0..10 {
+            polymers[[i, i]] = 1.0;
+        }
+        let auto = CompositionMatrix::from(&polymers)
+            .with_storage(MatrixStorage::Auto)
+            .unwrap();
+        assert_eq!(auto.storage(), MatrixStorage::Sparse);
+
+        /* A full matrix stays dense, and converts back when it's forced sparse. */
+        let full = CompositionMatrix::from(Array2::<f64>::ones((3, 2)));
+        assert_eq!(
+            full.clone()
+                .with_storage(MatrixStorage::Auto)
+                .unwrap()
+                .storage(),
+            MatrixStorage::Dense
+        );
+        let forced = full.with_storage(MatrixStorage::Sparse).unwrap();
+        assert_eq!(
+            forced.with_storage(MatrixStorage::Auto).unwrap().storage(),
+            MatrixStorage::Dense
+        );
+
+        /* Fractional compositions can't be stored sparsely, but stay dense under Auto. */
+        polymers[[0, 1]] = 0.5;
+        let fractional = CompositionMatrix::from(polymers);
+        assert!(fractional
+            .clone()
+            .with_storage(MatrixStorage::Sparse)
+            .is_err());
+        assert_eq!(
+            fractional
+                .with_storage(MatrixStorage::Auto)
+                .unwrap()
+                .storage(),
+            MatrixStorage::Dense
+        );
+    }
+}
@@ -0,0 +1,229 @@
+use crate::error::CoffeeError;
+use crate::extras::SubproblemOutcome;
+use crate::hessian::{damped_newton_step, HessianOperator};
+use crate::solver::SubproblemSolver;
+use ndarray::{Array1, ArrayView1};
+
+/// The dogleg method for solving trust region subproblems:
+/// min f(x) = 1/2 x^T H x + g^T x
+/// s.t. ||x|| <= delta
+/// where H is the Hessian matrix and g is the gradient.
+///
+/// The step follows the path from the origin to the Cauchy point (the model minimum along the
+/// steepest descent direction) and on to the Newton step -H^-1 g, stopping where the path
+/// leaves the trust region. The Newton step comes from a Cholesky factorization of the dense
+/// Hessian, which is cheap for the few monomers of most designs, with damping added if the
+/// Hessian is numerically singular.
+pub struct Dogleg {
+    vector_size: usize,
+    curr_step: Array1<f64>,
+}
+
+impl Dogleg {
+    pub fn new(vector_size: usize) -> Self {
+        Self {
+            vector_size,
+            curr_step: Array1::zeros(vector_size),
+        }
+    }
+
+    /// Calculates the norm of the given vector.
+    ///
+    /// # Arguments
+    ///
+    /// * `v` - A 1-dimensional array representing the vector to calculate the norm of.
+    ///
+    /// # Returns
+    ///
+    /// The norm of the vector.
+    fn norm(&self, v: ArrayView1<f64>) -> f64 {
+        v.iter().map(|&x| x * x).sum::<f64>().sqrt()
+    }
+
+    /// Solves ||from + t (to - from)|| = delta for t in [0, 1], where `from` is inside the trust
+    /// region and `to` is outside it.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - The start of the segment.
+    /// * `to` - The end of the segment.
+    /// * `delta` - The trust region radius.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<f64>` - The position of the boundary along the segment.
+    fn boundary_fraction(&self, from: &Array1<f64>, to: &Array1<f64>, delta: f64) -> Option<f64> {
+        let direction = to - from;
+        let a = direction.dot(&direction);
+        let b = 2.0 * from.dot(&direction);
+        let c = from.dot(from) - delta * delta;
+
+        let t = (-b + (b * b - 4.0 * a * c).sqrt()) / (2.0 * a);
+        if t.is_finite() {
+            Some(t.clamp(0.0, 1.0))
+        } else {
+            None
+        }
+    }
+
+    /// Find the dogleg step of the trust region subproblem.
+    ///
+    /// # Arguments
+    ///
+    /// * `gradient` - The gradient vector.
+    /// * `hessian` - The Hessian. Operators that only give Hessian-vector products are built
+    ///   into the dense matrix first.
+    /// * `delta` - The trust region radius.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<SubproblemOutcome, CoffeeError>` - How the subproblem was solved, or an
+    ///   error if the step isn't finite.
+    ///
+    /// # Panics
+    ///
+    /// If the vectors are not sized correctly.
+    pub fn iterate(
+        &mut self,
+        gradient: &Array1<f64>,
+        hessian: &dyn HessianOperator,
+        delta: f64,
+    ) -> Result<SubproblemOutcome, CoffeeError> {
+        /* Assert that vectors are sized correctly. */
+        assert_eq!(gradient.dim(), self.vector_size);
+        assert_eq!(hessian.dim(), self.vector_size);
+
+        let mut outcome = SubproblemOutcome::default();
+        let gradient_norm = self.norm(gradient.view());
+        if gradient_norm == 0.0 {
+            self.curr_step.fill(0.0);
+            outcome.converged = true;
+            return Ok(outcome);
+        }
+
+        /* The full Newton step is the answer whenever it fits inside the trust region. A damped
+         * step is still taken, but it doesn't solve the subproblem. */
+        let (newton, damped) = match damped_newton_step(&hessian.to_dense(), gradient) {
+            Ok((newton, damped)) => (Some(newton), damped),
+            Err(_) => (None, false),
+        };
+        if let Some(newton) = &newton {
+            if self.norm(newton.view()) <= delta {
+                self.curr_step = newton.clone();
+                outcome.converged = !damped;
+                return Ok(outcome);
+            }
+        }
+
+        /* Minimize the model along steepest descent. Without upward curvature along it, the
+         * model keeps decreasing up to the boundary. */
+        let curvature = gradient.dot(&hessian.apply(gradient.view()));
+        let boundary_step = gradient * (-delta / gradient_norm);
+        let cauchy = gradient * (-gradient.dot(gradient) / curvature);
+        outcome.negative_curvature = curvature <= 0.0;
+        outcome.hit_boundary = true;
+
+        self.curr_step = match newton {
+            /* The Cauchy point is outside or doesn't exist, so stop on the boundary along it. */
+            _ if outcome.negative_curvature || self.norm(cauchy.view()) >= delta => boundary_step,
+            /* Follow the path from the Cauchy point towards the Newton step to the boundary. */
+            Some(newton) => match self.boundary_fraction(&cauchy, &newton, delta) {
+                Some(t) => &cauchy + &(t * (&newton - &cauchy)),
+                None => boundary_step,
+            },
+            /* Without a Newton step, the Cauchy point is the best step available. */
+            None => {
+                outcome.hit_boundary = false;
+                cauchy
+            }
+        };
+
+        if self.curr_step.iter().all(|v| v.is_finite()) {
+            Ok(outcome)
+        } else {
+            Err(CoffeeError::SubproblemFailed(
+                "The dogleg step is not finite.".to_string(),
+            ))
+        }
+    }
+
+    /// Get the result.
+    ///
+    /// # Returns
+    ///
+    /// * `Array1<f64>` - The copy of the result.
+    pub fn get_result(&self) -> Array1<f64> {
+        self.curr_step.clone()
+    }
+}
+
+impl SubproblemSolver for Dogleg {
+    fn solve(
+        &mut self,
+        gradient: &Array1<f64>,
+        hessian: &dyn HessianOperator,
+        _eps: f64,
+        delta: f64,
+    ) -> Result<SubproblemOutcome, CoffeeError> {
+        self.iterate(gradient, hessian, delta)
+    }
+
+    fn step(&self) -> Array1<f64> {
+        self.get_result()
+    }
+
+    fn reset(&mut self) {
+        self.curr_step.fill(0.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn test_dogleg_step() {
+        let hessian = array![[2.0, 0.0], [0.0, 8.0]];
+        let gradient = array![-2.0, -4.0];
+        let mut dogleg = Dogleg::new(2);
+
+        /* A large region holds the Newton step, -H^-1 g. */
+        let outcome = dogleg.iterate(&gradient, &hessian, 10.0).unwrap();
+        assert!(outcome.converged && !outcome.hit_boundary);
+        let step = dogleg.get_result();
+        assert!((step[0] - 1.0).abs() < 1e-12 && (step[1] - 0.5).abs() < 1e-12);
+
+        /* A region smaller than the Cauchy point cuts the steepest descent step. */
+        assert!(
+            dogleg
+                .iterate(&gradient, &hessian, 0.1)
+                .unwrap()
+                .hit_boundary
+        );
+        let step = dogleg.get_result();
+        let expected = &gradient * (-0.1 / gradient.dot(&gradient).sqrt());
+        assert!((&step - &expected).iter().all(|d| d.abs() < 1e-12));
+
+        /* In between, the step ends on the boundary between the two points. */
+        assert!(
+            dogleg
+                .iterate(&gradient, &hessian, 0.8)
+                .unwrap()
+                .hit_boundary
+        );
+        let step = dogleg.get_result();
+        assert!((step.dot(&step).sqrt() - 0.8).abs() < 1e-12);
+        let model = |p: &Array1<f64>| gradient.dot(p) + 0.5 * p.dot(&hessian.dot(p));
+        let descent = &gradient * (-0.8 / gradient.dot(&gradient).sqrt());
+        assert!(model(&step) < model(&descent));
+
+        /* A singular Hessian still gives a finite descent step, but only by damping it, so the
+         * subproblem isn't solved. */
+        let singular = array![[1.0, 1.0], [1.0, 1.0]];
+        let outcome = dogleg.iterate(&gradient, &singular, 1.0e15).unwrap();
+        assert!(!outcome.converged && !outcome.hit_boundary);
+        let step = dogleg.get_result();
+        assert!(step.iter().all(|v| v.is_finite()) && step.dot(&gradient) < 0.0);
+    }
+}
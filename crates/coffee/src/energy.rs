@@ -0,0 +1,179 @@
+use crate::error::CoffeeError;
+use ndarray::Array1;
+
+/// Temperature (in Celsius) at which enthalpies, entropies and heat capacities are given.
+pub const REFERENCE_TEMP_CELSIUS: f64 = 37.0;
+
+/// Offset between Celsius and Kelvin.
+const KELVIN_OFFSET: f64 = 273.15;
+
+/// The free energies of the polymers, either fixed or as a function of temperature.
+/// All energies are in kcal/mol, and entropies and heat capacities in kcal/(mol K).
+#[derive(Clone, Debug, PartialEq)]
+pub enum PolymerEnergies {
+    /// Free energies that don't change with temperature.
+    Fixed(Array1<f64>),
+    /// Enthalpies and entropies, and optionally heat capacities, at a reference temperature.
+    /// The free energy at temperature T is
+    /// dG(T) = dH - T dS + dCp ((T - T0) - T ln(T / T0)).
+    Thermodynamic {
+        enthalpy: Array1<f64>,
+        entropy: Array1<f64>,
+        heat_capacity: Option<Array1<f64>>,
+        reference_temp_celsius: f64,
+    },
+}
+
+impl PolymerEnergies {
+    /// Creates temperature-dependent energies given at `REFERENCE_TEMP_CELSIUS`.
+    ///
+    /// # Arguments
+    ///
+    /// * `enthalpy` - The enthalpy of each polymer.
+    /// * `entropy` - The entropy of each polymer.
+    /// * `heat_capacity` - The heat capacity of each polymer, if known.
+    pub fn thermodynamic(
+        enthalpy: Array1<f64>,
+        entropy: Array1<f64>,
+        heat_capacity: Option<Array1<f64>>,
+    ) -> Self {
+        PolymerEnergies::Thermodynamic {
+            enthalpy,
+            entropy,
+            heat_capacity,
+            reference_temp_celsius: REFERENCE_TEMP_CELSIUS,
+        }
+    }
+
+    /// Returns the number of polymers with energies.
+    pub fn len(&self) -> usize {
+        match self {
+            PolymerEnergies::Fixed(energies) => energies.len(),
+            PolymerEnergies::Thermodynamic { enthalpy, .. } => enthalpy.len(),
+        }
+    }
+
+    /// Returns whether there are no energies.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns whether the free energies change with temperature.
+    pub fn is_temperature_dependent(&self) -> bool {
+        matches!(self, PolymerEnergies::Thermodynamic { .. })
+    }
+
+    /// Checks that every column has one finite value per polymer.
+    ///
+    /// # Returns
+    ///
+    /// A `CoffeeError` describing the first invalid value found, if any.
+    pub fn validate(&self) -> Result<(), CoffeeError> {
+        let columns: Vec<(&str, &Array1<f64>)> = match self {
+            PolymerEnergies::Fixed(energies) => vec![("Free energy", energies)],
+            PolymerEnergies::Thermodynamic {
+                enthalpy,
+                entropy,
+                heat_capacity,
+                reference_temp_celsius,
+            } => {
+                if !reference_temp_celsius.is_finite() || *reference_temp_celsius <= -KELVIN_OFFSET
+                {
+                    return Err(CoffeeError::InvalidInput(
+                        "Reference temperature is not valid.".to_string(),
+                    ));
+                }
+                let mut columns = vec![("Enthalpy", enthalpy), ("Entropy", entropy)];
+                if let Some(heat_capacity) = heat_capacity {
+                    columns.push(("Heat capacity", heat_capacity));
+                }
+                columns
+            }
+        };
+
+        for (name, column) in columns {
+            if column.len() != self.len() {
+                return Err(CoffeeError::EnergyCountMismatch {
+                    energies: column.len(),
+                    rows: self.len(),
+                });
+            }
+            if let Some(index) = column.iter().position(|v| !v.is_finite()) {
+                return Err(CoffeeError::InvalidInput(format!(
+                    "{} of polymer {} is not finite.",
+                    name, index
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Calculates the free energy of each polymer at the given temperature.
+    ///
+    /// # Arguments
+    ///
+    /// * `temp_celsius` - The temperature in Celsius.
+    ///
+    /// # Returns
+    ///
+    /// The free energies in kcal/mol.
+    pub fn free_energies(&self, temp_celsius: f64) -> Array1<f64> {
+        match self {
+            PolymerEnergies::Fixed(energies) => energies.clone(),
+            PolymerEnergies::Thermodynamic {
+                enthalpy,
+                entropy,
+                heat_capacity,
+                reference_temp_celsius,
+            } => {
+                let t = temp_celsius + KELVIN_OFFSET;
+                let t0 = reference_temp_celsius + KELVIN_OFFSET;
+                let mut energies = enthalpy - &(entropy * t);
+                if let Some(heat_capacity) = heat_capacity {
+                    energies = energies + heat_capacity * ((t - t0) - t * (t / t0).ln());
+                }
+                energies
+            }
+        }
+    }
+}
+
+impl From<Array1<f64>> for PolymerEnergies {
+    fn from(energies: Array1<f64>) -> Self {
+        PolymerEnergies::Fixed(energies)
+    }
+}
+
+impl From<&Array1<f64>> for PolymerEnergies {
+    fn from(energies: &Array1<f64>) -> Self {
+        PolymerEnergies::Fixed(energies.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn test_free_energies() {
+        let energies = PolymerEnergies::thermodynamic(array![-10.0], array![-0.03], None);
+
+        /* At the reference temperature, dG = dH - T dS. */
+        let dg = energies.free_energies(REFERENCE_TEMP_CELSIUS);
+        assert!((dg[0] - (-10.0 + 0.03 * 310.15)).abs() < 1.0e-12);
+
+        /* Higher temperatures make an entropically unfavourable polymer less stable. */
+        assert!(energies.free_energies(60.0)[0] > dg[0]);
+
+        /* The heat capacity term vanishes at the reference temperature. */
+        let energies =
+            PolymerEnergies::thermodynamic(array![-10.0], array![-0.03], Some(array![-0.5]));
+        assert!((energies.free_energies(REFERENCE_TEMP_CELSIUS)[0] - dg[0]).abs() < 1.0e-12);
+        assert!(energies.validate().is_ok());
+
+        let energies =
+            PolymerEnergies::thermodynamic(array![-10.0, 1.0], array![f64::NAN, 0.0], None);
+        assert!(energies.validate().is_err());
+    }
+}
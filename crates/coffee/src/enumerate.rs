@@ -0,0 +1,328 @@
+use std::collections::HashMap;
+
+use ndarray::{Array1, Array2};
+
+use crate::error::CoffeeError;
+use crate::extras::Names;
+use crate::fileparse::parse_composition_energies;
+use crate::problem::EquilibriumProblem;
+
+/// Limits on the complexes to enumerate.
+#[derive(Clone, Debug)]
+pub struct ComplexLimits {
+    /// Largest number of strands in a complex, counting repeats.
+    pub max_size: usize,
+    /// Largest count of each strand in a complex, one per strand and at least 1. `None` only
+    /// limits the size.
+    pub caps: Option<Vec<usize>>,
+}
+
+/// Gives the free energy of a complex from its composition.
+pub trait EnergyModel {
+    /// Returns the free energy of a complex.
+    ///
+    /// # Arguments
+    ///
+    /// * `composition` - The count of each strand in the complex, in column order.
+    ///
+    /// # Returns
+    ///
+    /// The free energy in kcal/mol, or an error if the model has none for the complex.
+    fn free_energy(&self, composition: &[usize]) -> Result<f64, CoffeeError>;
+}
+
+/// Any closure taking a composition can model energies.
+impl<F> EnergyModel for F
+where
+    F: Fn(&[usize]) -> Result<f64, CoffeeError>,
+{
+    fn free_energy(&self, composition: &[usize]) -> Result<f64, CoffeeError> {
+        self(composition)
+    }
+}
+
+/// Free energies looked up by composition, e.g. from a file of `A1 B2, -3.5` rows.
+#[derive(Clone, Debug)]
+pub struct EnergyTable {
+    strands: Vec<String>,
+    energies: HashMap<Vec<usize>, f64>,
+}
+
+impl EnergyTable {
+    /// Reads a table from rows of a composition string and a free energy.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_content` - The contents of the energies input.
+    /// * `strands` - The strand names the composition strings use, in column order.
+    ///
+    /// # Returns
+    ///
+    /// The table, or an error if the input can't be parsed or lists a composition twice.
+    pub fn from_bytes(file_content: &[u8], strands: &[String]) -> Result<Self, CoffeeError> {
+        let (compositions, free_energies) = parse_composition_energies(file_content, strands)?;
+        let mut energies = HashMap::with_capacity(free_energies.len());
+        for (composition, &energy) in compositions.rows().into_iter().zip(free_energies.iter()) {
+            let counts: Vec<usize> = composition.iter().map(|&count| count as usize).collect();
+            if energies.insert(counts.clone(), energy).is_some() {
+                return Err(CoffeeError::InvalidInput(format!(
+                    "The free energy of {} is given more than once.",
+                    composition_string(&counts, strands)
+                )));
+            }
+        }
+        Ok(EnergyTable {
+            strands: strands.to_vec(),
+            energies,
+        })
+    }
+}
+
+impl EnergyModel for EnergyTable {
+    fn free_energy(&self, composition: &[usize]) -> Result<f64, CoffeeError> {
+        self.energies.get(composition).copied().ok_or_else(|| {
+            CoffeeError::InvalidInput(format!(
+                "No free energy is given for {}.",
+                composition_string(composition, &self.strands)
+            ))
+        })
+    }
+}
+
+/// Formats a composition as a composition string, e.g. `A1 B2 D1`, leaving out absent strands.
+///
+/// # Arguments
+///
+/// * `composition` - The count of each strand, in column order.
+/// * `strands` - The strand names, in column order.
+pub fn composition_string(composition: &[usize], strands: &[String]) -> String {
+    composition
+        .iter()
+        .zip(strands)
+        .filter(|(&count, _)| count > 0)
+        .map(|(count, name)| format!("{}{}", name, count))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Adds every composition of the remaining strands with the given number of strands left, in
+/// decreasing order of the earlier strands' counts.
+fn push_compositions(
+    counts: &mut Vec<usize>,
+    caps: &[usize],
+    remaining: usize,
+    compositions: &mut Vec<Vec<usize>>,
+) {
+    let strand = counts.len();
+    if strand == caps.len() - 1 {
+        if remaining <= caps[strand] {
+            counts.push(remaining);
+            compositions.push(counts.clone());
+            counts.pop();
+        }
+        return;
+    }
+    for count in (0..=remaining.min(caps[strand])).rev() {
+        counts.push(count);
+        push_compositions(counts, caps, remaining - count, compositions);
+        counts.pop();
+    }
+}
+
+/// Enumerates every complex of up to `max_size` strands. Complexes are ordered by size, so the
+/// single strands come first in column order, and then by decreasing count of the earlier
+/// strands, e.g. `A2`, `A1 B1`, `B2` for two strands of size 2.
+///
+/// # Arguments
+///
+/// * `num_strands` - The number of strands, M.
+/// * `limits` - The largest complex size and the optional per-strand caps.
+///
+/// # Returns
+///
+/// The composition matrix, one row per complex and one column per strand, or an error if there
+/// are no strands, the size is zero or the caps don't have one positive entry per strand.
+pub fn enumerate_compositions(
+    num_strands: usize,
+    limits: &ComplexLimits,
+) -> Result<Array2<f64>, CoffeeError> {
+    if num_strands == 0 {
+        return Err(CoffeeError::InvalidInput(
+            "No strands to enumerate complexes of.".to_string(),
+        ));
+    }
+    if limits.max_size == 0 {
+        return Err(CoffeeError::InvalidInput(
+            "The maximum complex size must be at least 1.".to_string(),
+        ));
+    }
+    let caps = match &limits.caps {
+        Some(caps) if caps.len() != num_strands => {
+            return Err(CoffeeError::InvalidInput(format!(
+                "{} strand caps given for {} strands.",
+                caps.len(),
+                num_strands
+            )))
+        }
+        /* A strand capped at 0 would be in no complex, not even on its own. */
+        Some(caps) if caps.contains(&0) => {
+            return Err(CoffeeError::InvalidInput(
+                "Strand caps must be at least 1.".to_string(),
+            ))
+        }
+        Some(caps) => caps.clone(),
+        None => vec![limits.max_size; num_strands],
+    };
+
+    let mut compositions = Vec::new();
+    for size in 1..=limits.max_size {
+        push_compositions(&mut Vec::new(), &caps, size, &mut compositions);
+    }
+    let values = compositions.concat().into_iter().map(|count| count as f64);
+    Ok(Array2::from_shape_vec((compositions.len(), num_strands), values.collect()).unwrap())
+}
+
+/// Finds the free energy of every complex of a composition matrix.
+///
+/// # Arguments
+///
+/// * `compositions` - The composition matrix, e.g. from `enumerate_compositions`.
+/// * `model` - The energy model to evaluate.
+///
+/// # Returns
+///
+/// One free energy per row, or the first error from the model.
+pub fn free_energies(
+    compositions: &Array2<f64>,
+    model: &dyn EnergyModel,
+) -> Result<Array1<f64>, CoffeeError> {
+    compositions
+        .rows()
+        .into_iter()
+        .map(|row| {
+            let counts: Vec<usize> = row.iter().map(|&count| count as usize).collect();
+            model.free_energy(&counts)
+        })
+        .collect::<Result<Vec<f64>, CoffeeError>>()
+        .map(Array1::from)
+}
+
+/// Builds a problem from every complex of up to a maximum size, with energies from a model.
+/// Named strands name each complex by its composition string.
+///
+/// # Arguments
+///
+/// * `concentrations` - The strand concentrations.
+/// * `strands` - The strand names, if named.
+/// * `limits` - The largest complex size and the optional per-strand caps.
+/// * `model` - The energy model giving each complex its free energy.
+///
+/// # Returns
+///
+/// The problem, or an error if the enumeration, the model or the problem's validation fails.
+pub fn enumerated_problem(
+    concentrations: Array1<f64>,
+    strands: Option<Vec<String>>,
+    limits: &ComplexLimits,
+    model: &dyn EnergyModel,
+) -> Result<EquilibriumProblem, CoffeeError> {
+    let compositions = enumerate_compositions(concentrations.len(), limits)?;
+    let energies = free_energies(&compositions, model)?;
+    let polymers = strands.as_ref().map(|strands| {
+        compositions
+            .rows()
+            .into_iter()
+            .map(|row| {
+                let counts: Vec<usize> = row.iter().map(|&count| count as usize).collect();
+                composition_string(&counts, strands)
+            })
+            .collect()
+    });
+
+    EquilibriumProblem::new(concentrations, compositions, energies)?.with_names(Names {
+        monomers: strands,
+        polymers,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn test_enumerate_compositions() {
+        let limits = ComplexLimits {
+            max_size: 2,
+            caps: None,
+        };
+        let compositions = enumerate_compositions(2, &limits).unwrap();
+        assert_eq!(
+            compositions,
+            array![[1.0, 0.0], [0.0, 1.0], [2.0, 0.0], [1.0, 1.0], [0.0, 2.0]]
+        );
+
+        /* Every multiset of up to 4 of 3 strands: C(3 + 4, 4) - 1. */
+        let limits = ComplexLimits {
+            max_size: 4,
+            caps: None,
+        };
+        assert_eq!(enumerate_compositions(3, &limits).unwrap().nrows(), 34);
+
+        /* Caps limit each strand's count. */
+        let limits = ComplexLimits {
+            max_size: 3,
+            caps: Some(vec![1, 3]),
+        };
+        let compositions = enumerate_compositions(2, &limits).unwrap();
+        assert!(compositions.column(0).iter().all(|&count| count <= 1.0));
+        assert_eq!(compositions.nrows(), 6);
+
+        let limits = ComplexLimits {
+            max_size: 2,
+            caps: Some(vec![1]),
+        };
+        assert!(enumerate_compositions(2, &limits).is_err());
+        let limits = ComplexLimits {
+            max_size: 2,
+            caps: Some(vec![0, 2]),
+        };
+        assert!(matches!(
+            enumerate_compositions(2, &limits),
+            Err(CoffeeError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_enumerated_problem() {
+        let strands = vec!["A".to_string(), "B".to_string()];
+        let limits = ComplexLimits {
+            max_size: 2,
+            caps: None,
+        };
+
+        /* Energies keyed by composition, with a missing one reported by name. */
+        let table =
+            EnergyTable::from_bytes(b"A1, 0\nB1, 0\nA2, -1\nA1 B1, -20\nB2, -1\n", &strands)
+                .unwrap();
+        let problem =
+            enumerated_problem(array![1e-6, 1e-6], Some(strands.clone()), &limits, &table).unwrap();
+        assert_eq!(problem.num_polymers(), 5);
+        assert_eq!(problem.names().polymer(3), Some("A1 B1"));
+        assert_eq!(
+            problem.energies().free_energies(37.0).to_vec(),
+            vec![0.0, 0.0, -1.0, -20.0, -1.0]
+        );
+
+        let table = EnergyTable::from_bytes(b"A1, 0\nB1, 0\n", &strands).unwrap();
+        let error = enumerated_problem(array![1e-6, 1e-6], None, &limits, &table).unwrap_err();
+        assert!(error.to_string().contains("A2"));
+
+        /* Closures are energy models too. */
+        let model = |composition: &[usize]| -> Result<f64, CoffeeError> {
+            Ok(-(composition.iter().sum::<usize>() as f64 - 1.0))
+        };
+        let problem = enumerated_problem(array![1e-6, 1e-6], None, &limits, &model).unwrap();
+        assert_eq!(problem.energies().free_energies(37.0)[4], -1.0);
+    }
+}
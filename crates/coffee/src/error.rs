@@ -0,0 +1,220 @@
+use crate::extras::TerminationReason;
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// The input an error was found in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputFile {
+    /// The compositions and free energies (CFE) input.
+    Compositions,
+    /// The monomer concentrations (CON) input.
+    Concentrations,
+    /// A list of Lagrange multipliers.
+    Lambdas,
+}
+
+impl fmt::Display for InputFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            InputFile::Compositions => "CFE",
+            InputFile::Concentrations => "CON",
+            InputFile::Lambdas => "lambda",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Everything that can go wrong reading, validating or solving an equilibrium problem.
+#[derive(Debug)]
+pub enum CoffeeError {
+    /// An input couldn't be parsed. `line` and `column` are 1-based, with columns counting
+    /// fields.
+    Parse {
+        file: InputFile,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    /// A JSON input couldn't be parsed or doesn't follow the schema. `path` locates the
+    /// offending value, e.g. `complexes[2].composition`, or is `.` for the whole document.
+    Json { path: String, message: String },
+    /// The composition matrix doesn't have one column per monomer.
+    MonomerCountMismatch { monomers: usize, columns: usize },
+    /// There isn't one energy per row of the composition matrix.
+    EnergyCountMismatch { energies: usize, rows: usize },
+    /// An input or parameter is empty, out of range or not a valid value.
+    InvalidInput(String),
+    /// The objective is not finite at the multipliers of the given iteration.
+    NonFiniteObjective { iteration: usize },
+    /// The solver couldn't compute a step.
+    SubproblemFailed(String),
+    /// The Hessian at the optimum is singular, so sensitivities are undefined there.
+    SingularHessian,
+    /// The optimization stopped for `reason` before converging, so its solution can't be used.
+    NotConverged { reason: TerminationReason },
+    /// A point of a sweep couldn't be solved.
+    SweepPointFailed {
+        point: usize,
+        source: Box<CoffeeError>,
+    },
+    /// The thread pool couldn't be created.
+    ThreadPool(String),
+    /// A file couldn't be read or written.
+    Io { path: String, source: io::Error },
+}
+
+impl CoffeeError {
+    /// Returns a stable name for the kind of error, e.g. to map it to a response code. A failed
+    /// sweep point has the code of the error that failed it.
+    pub fn code(&self) -> &'static str {
+        match self {
+            CoffeeError::Parse { .. } => "parse",
+            CoffeeError::Json { .. } => "json",
+            CoffeeError::MonomerCountMismatch { .. } => "monomer_count_mismatch",
+            CoffeeError::EnergyCountMismatch { .. } => "energy_count_mismatch",
+            CoffeeError::InvalidInput(_) => "invalid_input",
+            CoffeeError::NonFiniteObjective { .. } => "non_finite_objective",
+            CoffeeError::SubproblemFailed(_) => "subproblem_failed",
+            CoffeeError::SingularHessian => "singular_hessian",
+            CoffeeError::NotConverged { .. } => "not_converged",
+            CoffeeError::SweepPointFailed { source, .. } => source.code(),
+            CoffeeError::ThreadPool(_) => "thread_pool",
+            CoffeeError::Io { .. } => "io",
+        }
+    }
+
+    /// Creates a parse error.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The input the error was found in.
+    /// * `line` - The 1-based line.
+    /// * `column` - The 1-based field.
+    /// * `message` - What couldn't be parsed.
+    pub(crate) fn parse(
+        file: InputFile,
+        line: usize,
+        column: usize,
+        message: impl Into<String>,
+    ) -> Self {
+        CoffeeError::Parse {
+            file,
+            line,
+            column,
+            message: message.into(),
+        }
+    }
+
+    /// Returns a function wrapping an I/O error with the path it happened on, for `map_err`.
+    pub(crate) fn io(path: &str) -> impl FnOnce(io::Error) -> Self + '_ {
+        move |source| CoffeeError::Io {
+            path: path.to_string(),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for CoffeeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CoffeeError::Parse {
+                file,
+                line,
+                column,
+                message,
+            } => write!(
+                f,
+                "Error parsing {} input at line {}, column {}: {}",
+                file, line, column, message
+            ),
+            CoffeeError::Json { path, message } => {
+                write!(f, "Error in JSON input at {}: {}", path, message)
+            }
+            CoffeeError::MonomerCountMismatch { monomers, columns } => write!(
+                f,
+                "Monomers and polymer compositions inconsistent: {} monomers but {} composition columns.",
+                monomers, columns
+            ),
+            CoffeeError::EnergyCountMismatch { energies, rows } => write!(
+                f,
+                "Polymers and polymer quantities have different sizes: {} energies but {} polymers.",
+                energies, rows
+            ),
+            CoffeeError::InvalidInput(message) => write!(f, "{}", message),
+            CoffeeError::NonFiniteObjective { iteration } => write!(
+                f,
+                "The objective is not finite at iteration {}.",
+                iteration
+            ),
+            CoffeeError::SubproblemFailed(message) => {
+                write!(f, "The solver could not compute a step: {}", message)
+            }
+            CoffeeError::SingularHessian => write!(f, "The Hessian is not positive definite."),
+            CoffeeError::NotConverged { reason } => {
+                write!(f, "The optimization did not converge: {}.", reason)
+            }
+            CoffeeError::SweepPointFailed { point, source } => {
+                write!(f, "Optimization failed at sweep point {}: {}", point, source)
+            }
+            CoffeeError::ThreadPool(message) => write!(f, "{}", message),
+            CoffeeError::Io { path, source } => write!(f, "Error accessing {}: {}", path, source),
+        }
+    }
+}
+
+impl Error for CoffeeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CoffeeError::SweepPointFailed { source, .. } => Some(source.as_ref()),
+            CoffeeError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_display() {
+        let error = CoffeeError::parse(InputFile::Compositions, 3, 2, "bad number x");
+        assert_eq!(
+            error.to_string(),
+            "Error parsing CFE input at line 3, column 2: bad number x"
+        );
+        assert_eq!(error.code(), "parse");
+
+        let error = CoffeeError::Json {
+            path: "complexes[2].energy".to_string(),
+            message: "missing".to_string(),
+        };
+        assert_eq!(
+            error.to_string(),
+            "Error in JSON input at complexes[2].energy: missing"
+        );
+        assert_eq!(error.code(), "json");
+
+        /* A failed sweep point keeps the code and source of its cause. */
+        let error = CoffeeError::SweepPointFailed {
+            point: 4,
+            source: Box::new(CoffeeError::NonFiniteObjective { iteration: 0 }),
+        };
+        assert_eq!(error.code(), "non_finite_objective");
+        assert!(error.source().is_some());
+
+        let error = CoffeeError::NotConverged {
+            reason: TerminationReason::MaxIterations,
+        };
+        assert_eq!(error.code(), "not_converged");
+        assert_eq!(
+            error.to_string(),
+            "The optimization did not converge: maximum iterations reached."
+        );
+
+        let error = CoffeeError::io("missing.ocx")(io::Error::from(io::ErrorKind::NotFound));
+        assert_eq!(error.code(), "io");
+        assert!(error.to_string().starts_with("Error accessing missing.ocx"));
+    }
+}
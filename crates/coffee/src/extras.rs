@@ -1,4 +1,7 @@
-use std::error::Error;
+use crate::composition::MatrixStorage;
+use crate::error::CoffeeError;
+use serde::Deserialize;
+use std::collections::HashSet;
 use std::fmt;
 
 /// Struct containing optional parameters for the optimizer.
@@ -16,6 +19,228 @@ pub struct OptimizerArgs {
     pub scalarity: bool,
     pub temp_celsius: f64,
     pub verbose: bool,
+    /// Stop once the norm of the gradient falls below this value. Zero disables the test.
+    pub gradient_tolerance: f64,
+    /// Stop once the concentration constraint error falls below this value. Zero disables the test.
+    pub constraint_tolerance: f64,
+    /// Stop once an accepted step changes the Lagrangian by less than this fraction of its
+    /// magnitude. Zero disables the test.
+    pub lagrangian_tolerance: f64,
+    /// Starting point for the Lagrange multipliers.
+    pub initial_lambda: InitialLambda,
+    /// How the Hessian is given to the trust region subproblem.
+    pub hessian_mode: HessianMode,
+    /// How the polymer composition matrix is stored.
+    pub matrix_storage: MatrixStorage,
+    /// Number of threads for the per-polymer products. Zero uses every available core.
+    /// Results are identical for any number of threads.
+    pub threads: usize,
+    /// Algorithm that drives the Lagrange multipliers to the optimum.
+    pub solver: SolverKind,
+    /// Method for the trust region subproblem, used when `solver` is `SolverKind::TrustRegion`.
+    pub subproblem: SubproblemKind,
+}
+
+/// Algorithm the optimizer uses to minimize the dual objective.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SolverKind {
+    /// Trust region steps, with the radius adapted to how well each step was predicted.
+    #[default]
+    TrustRegion,
+    /// Damped Newton steps with a backtracking line search.
+    NewtonLineSearch,
+}
+
+/// Method for solving the trust region subproblem.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SubproblemKind {
+    /// Truncated conjugate gradient (Steihaug), which only needs Hessian-vector products.
+    #[default]
+    Steihaug,
+    /// Dogleg steps between the Cauchy point and the Newton step, which factorizes the dense
+    /// Hessian and suits systems with few monomers.
+    Dogleg,
+}
+
+/// How the optimizer represents the M x M Hessian of the dual objective.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HessianMode {
+    /// Build the dense matrix for small numbers of monomers and use Hessian-vector products
+    /// otherwise.
+    #[default]
+    Auto,
+    /// Build the dense matrix every iteration, at a cost of O(N M^2).
+    Dense,
+    /// Never build the matrix, computing each Hessian-vector product in O(N M).
+    MatrixFree,
+}
+
+/// Starting point for the Lagrange multipliers of an optimization.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum InitialLambda {
+    /// Start every multiplier at zero.
+    #[default]
+    Zero,
+    /// Start from the log of the monomer concentrations, a cheap estimate of the optimum when
+    /// most monomers are unbound.
+    Heuristic,
+    /// Start from the given multipliers, e.g. `optimal_lambda` from a previous solve of a
+    /// similar problem. Must have one entry per monomer.
+    Given(Vec<f64>),
+}
+
+/// Reason the optimizer stopped iterating.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TerminationReason {
+    /// One of the convergence tests in `OptimizerArgs` was satisfied.
+    Converged,
+    /// The iteration limit was reached before any convergence test was satisfied.
+    MaxIterations,
+    /// A step made no change to the Lagrangian, so no further progress is possible.
+    Stalled,
+    /// The solver could not compute a step, e.g. the trust region subproblem could not be solved.
+    SubproblemFailed,
+    /// An observer stopped the optimization, e.g. because it was cancelled or ran out of time.
+    Cancelled,
+}
+
+impl TerminationReason {
+    /// Returns whether the optimization converged.
+    pub fn is_converged(&self) -> bool {
+        *self == TerminationReason::Converged
+    }
+
+    /// Returns a stable name for the reason, e.g. for machine-readable outputs.
+    pub fn code(&self) -> &'static str {
+        match self {
+            TerminationReason::Converged => "converged",
+            TerminationReason::MaxIterations => "max_iterations",
+            TerminationReason::Stalled => "stalled",
+            TerminationReason::SubproblemFailed => "subproblem_failed",
+            TerminationReason::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl fmt::Display for TerminationReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            TerminationReason::Converged => "converged",
+            TerminationReason::MaxIterations => "maximum iterations reached",
+            TerminationReason::Stalled => "stalled",
+            TerminationReason::SubproblemFailed => "subproblem failed",
+            TerminationReason::Cancelled => "cancelled",
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+/// How the trust region subproblem of one outer iteration was solved.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SubproblemOutcome {
+    /// Number of inner iterations, e.g. conjugate gradient steps. Direct solvers report 0.
+    pub inner_iterations: usize,
+    /// Whether the step ended on the trust region boundary.
+    pub hit_boundary: bool,
+    /// Whether the model had zero or negative curvature along a search direction.
+    pub negative_curvature: bool,
+    /// Whether the step solves the subproblem to its tolerance inside the trust region.
+    pub converged: bool,
+}
+
+/// The state of the optimizer after one outer iteration.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct IterationRecord {
+    /// The 0-based iteration number.
+    pub iteration: usize,
+    /// The objective (Lagrangian) after the iteration.
+    pub objective: f64,
+    /// The norm of the gradient at the point the step was taken from.
+    pub gradient_norm: f64,
+    /// The concentration constraint error after the iteration.
+    pub constraint_error: f64,
+    /// The trust region radius the step was limited to. None for solvers without one.
+    pub delta: Option<f64>,
+    /// The ratio of the actual to the predicted reduction of the objective. None if the step
+    /// made no change.
+    pub rho: Option<f64>,
+    /// Whether the step was taken.
+    pub accepted: bool,
+    /// The inner iterations of the trust region subproblem, e.g. Steihaug conjugate gradient
+    /// steps. 0 for solvers without a subproblem.
+    pub inner_iterations: usize,
+}
+
+/// Optional names of the monomers (strands) and polymers (complexes) of a problem, used to label
+/// their values in the outputs.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Names {
+    /// One name per monomer, in column order.
+    pub monomers: Option<Vec<String>>,
+    /// One name per polymer, in row order.
+    pub polymers: Option<Vec<String>>,
+}
+
+impl Names {
+    /// Returns the name of a monomer, if monomers are named.
+    pub fn monomer(&self, index: usize) -> Option<&str> {
+        self.monomers.as_ref()?.get(index).map(String::as_str)
+    }
+
+    /// Returns the name of a polymer, if polymers are named.
+    pub fn polymer(&self, index: usize) -> Option<&str> {
+        self.polymers.as_ref()?.get(index).map(String::as_str)
+    }
+
+    /// Checks that there is one name per monomer and polymer, and that names are non-empty and
+    /// unique.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_monomers` - The number of monomers, M.
+    /// * `num_polymers` - The number of polymers, N.
+    ///
+    /// # Returns
+    ///
+    /// An `InvalidInput` error describing the first problem found, if any.
+    pub fn validate(&self, num_monomers: usize, num_polymers: usize) -> Result<(), CoffeeError> {
+        for (kind, names, expected) in [
+            ("monomer", &self.monomers, num_monomers),
+            ("polymer", &self.polymers, num_polymers),
+        ] {
+            let Some(names) = names else {
+                continue;
+            };
+            if names.len() != expected {
+                return Err(CoffeeError::InvalidInput(format!(
+                    "{} {} names given for {} {}s.",
+                    names.len(),
+                    kind,
+                    expected,
+                    kind
+                )));
+            }
+            let mut seen = HashSet::new();
+            for name in names {
+                if name.is_empty() {
+                    return Err(CoffeeError::InvalidInput(format!(
+                        "A {} name is empty.",
+                        kind
+                    )));
+                }
+                if !seen.insert(name) {
+                    return Err(CoffeeError::InvalidInput(format!(
+                        "The {} name \"{}\" is used more than once.",
+                        kind, name
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Clone)]
@@ -26,6 +251,21 @@ pub struct OptimizerResults {
     pub concentration_error: f64,
     pub log_messages: Vec<String>,
     pub elapsed_time: usize,
+    pub termination_reason: TerminationReason,
+    pub iterations: usize,
+    /// Number of polymer concentrations in `optimal_x` too large for an f64, which were clamped
+    /// to the largest finite value.
+    pub clamped_values: usize,
+    /// How the trust region subproblem was solved in each outer iteration. Empty for solvers
+    /// without a subproblem.
+    pub subproblem_outcomes: Vec<SubproblemOutcome>,
+    /// The state after each iteration, to follow the convergence.
+    pub history: Vec<IterationRecord>,
+    /// The names of the problem's monomers and polymers, if it has any.
+    pub names: Names,
+    /// The concentration of each monomer in its free form, the polymer made of one unit of that
+    /// monomer alone, or zero for monomers with no such polymer.
+    pub free_monomers: Vec<f64>,
 }
 
 /// Default implementation for `OptimizerArgs`.
@@ -33,7 +273,7 @@ pub struct OptimizerResults {
 impl Default for OptimizerArgs {
     fn default() -> Self {
         OptimizerArgs {
-            max_iterations: 250,
+            max_iterations: 1000,
             max_delta: 1000.0,
             eta: 0.15,
             norm_ratio_threshold: 0.95,
@@ -43,17 +283,15 @@ impl Default for OptimizerArgs {
             scalarity: true,
             temp_celsius: 37.0,
             verbose: false,
+            gradient_tolerance: 1e-12,
+            constraint_tolerance: 1e-15,
+            lagrangian_tolerance: 0.0,
+            initial_lambda: InitialLambda::Zero,
+            hessian_mode: HessianMode::Auto,
+            matrix_storage: MatrixStorage::Auto,
+            threads: 0,
+            solver: SolverKind::TrustRegion,
+            subproblem: SubproblemKind::Steihaug,
         }
     }
 }
-
-#[derive(Debug)]
-pub struct OptimizerError(pub String);
-
-impl fmt::Display for OptimizerError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.0)
-    }
-}
-
-impl Error for OptimizerError {}
@@ -1,167 +1,1228 @@
-use std::error::Error;
-use std::io::Cursor;
+use std::fmt;
 use std::result::Result;
 
-use polars::prelude::{CsvReader, DataFrame, DataType, PolarsError, SerReader, Series};
+use ndarray::{s, Array1, Array2};
 
-type ParsedData = (DataFrame, Series, Series);
+use crate::energy::PolymerEnergies;
+use crate::error::{CoffeeError, InputFile};
+use crate::extras::Names;
 
-pub fn read_inputs_to_dataframe(
-    file_content_cfe: &[u8],
-    file_content_con: &[u8],
-) -> Result<ParsedData, Box<dyn Error>> {
-    let cfe_delimiter = detect_delimiter(file_content_cfe)?;
-    let cfe_cursor = Cursor::new(file_content_cfe);
+/// Number of leading rows checked for the NUPACK index columns when detecting the format.
+const NUPACK_SAMPLE_ROWS: usize = 20;
 
-    let mut cfe_df = CsvReader::new(cfe_cursor)
-        .has_header(false)
-        .with_delimiter(cfe_delimiter)
-        .finish()?;
+/// Number of leading rows sampled to detect the delimiter.
+const DELIMITER_SAMPLE_ROWS: usize = 20;
 
-    let num_columns = cfe_df.width();
-    let num_rows = cfe_df.height();
+/// The delimiters tried when detecting one, in order of preference when several split the
+/// sampled rows equally well.
+const DELIMITER_CANDIDATES: [Delimiter; 5] = [
+    Delimiter::Tab,
+    Delimiter::Comma,
+    Delimiter::Semicolon,
+    Delimiter::Pipe,
+    Delimiter::Whitespace,
+];
 
-    let mut is_nupack = true;
-    let sample_size = 20.min(num_rows);
+/// Layout of the energy columns at the end of each CFE row.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EnergyLayout {
+    /// A single free energy column.
+    #[default]
+    FreeEnergy,
+    /// An enthalpy column followed by an entropy column.
+    EnthalpyEntropy,
+    /// Enthalpy, entropy and heat capacity columns.
+    EnthalpyEntropyHeatCapacity,
+}
 
-    // Check first min(rows, sample_size) entries for NUPACK formatting
-    for index in 0..sample_size {
-        let row = cfe_df.get(index).ok_or("Failed to get row")?;
-        let value1: i64 = row.first().unwrap().extract::<i64>().unwrap_or(0);
-        let value2: i64 = row.get(1).unwrap().extract::<i64>().unwrap_or(0);
+impl EnergyLayout {
+    /// Returns the number of energy columns in this layout.
+    pub fn num_columns(&self) -> usize {
+        match self {
+            EnergyLayout::FreeEnergy => 1,
+            EnergyLayout::EnthalpyEntropy => 2,
+            EnergyLayout::EnthalpyEntropyHeatCapacity => 3,
+        }
+    }
+}
 
-        // Any failed comparison sets is_nupack to false
-        is_nupack &= value1 == (index + 1) as i64;
-        is_nupack &= value2 == 1;
+/// The delimiter between the fields of a CFE row.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Delimiter {
+    /// Detected from the first rows of the input.
+    #[default]
+    Auto,
+    Tab,
+    Comma,
+    Semicolon,
+    Pipe,
+    /// Runs of spaces and tabs, as in space-aligned tables.
+    Whitespace,
+}
 
-        if !is_nupack {
-            break;
+impl Delimiter {
+    /// Returns how this delimiter splits a line, or `None` for `Auto`.
+    fn separator(&self) -> Option<Separator> {
+        match self {
+            Delimiter::Auto => None,
+            Delimiter::Tab => Some(Separator::Byte(b'\t')),
+            Delimiter::Comma => Some(Separator::Byte(b',')),
+            Delimiter::Semicolon => Some(Separator::Byte(b';')),
+            Delimiter::Pipe => Some(Separator::Byte(b'|')),
+            Delimiter::Whitespace => Some(Separator::Whitespace),
         }
     }
+}
 
-    let float_col = cfe_df
-        .select_at_idx(num_columns - 1)
-        .ok_or("Failed to find float_col column")?
-        .clone();
-    cfe_df = cfe_df.drop(cfe_df.get_column_names()[num_columns - 1])?;
-
-    if is_nupack {
-        cfe_df = cfe_df.drop(cfe_df.get_column_names()[0])?;
-        cfe_df = cfe_df.drop(cfe_df.get_column_names()[0])?;
+impl fmt::Display for Delimiter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Delimiter::Auto => "auto",
+            Delimiter::Tab => "tab",
+            Delimiter::Comma => "comma",
+            Delimiter::Semicolon => "semicolon",
+            Delimiter::Pipe => "pipe",
+            Delimiter::Whitespace => "whitespace",
+        };
+        write!(f, "{}", name)
     }
+}
 
-    // Parse .con file
-    let con_cursor = Cursor::new(file_content_con);
-    let con_df = CsvReader::new(con_cursor).has_header(false).finish()?;
+/// The layout of the composition columns of a CFE row.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InputFormat {
+    /// Detected from the first fields of the rows, the index columns and the number of
+    /// concentrations.
+    #[default]
+    Auto,
+    /// Every column before the energies is a composition.
+    Conventional,
+    /// NUPACK .ocx files, whose rows start with a complex index and a permutation index that
+    /// aren't compositions.
+    NupackOcx,
+    /// Rows holding a composition string, such as `A1 B2 D1`, before the energies. The strands
+    /// are named by the CON input, which sets their order.
+    CompositionStrings,
+}
 
-    if con_df.width() != 1 {
-        return Err(PolarsError::ComputeError("Invalid .con file".into()).into());
+impl fmt::Display for InputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            InputFormat::Auto => "auto",
+            InputFormat::Conventional => "conventional",
+            InputFormat::NupackOcx => "nupack",
+            InputFormat::CompositionStrings => "composition",
+        };
+        write!(f, "{}", name)
     }
+}
 
-    let con_vector = con_df
-        .select_at_idx(0)
-        .ok_or("Failed to select column")?
-        .clone();
+/// Options for how the CFE and CON inputs are read.
+#[derive(Clone, Debug, Default)]
+pub struct ParseOptions {
+    pub energy_layout: EnergyLayout,
+    pub delimiter: Delimiter,
+    pub format: InputFormat,
+}
 
-    Ok((cfe_df, float_col, con_vector))
+/// The numbers read from the CFE and CON inputs.
+#[derive(Clone, Debug)]
+pub struct ParsedInputs {
+    /// The composition matrix, one row per polymer and one column per monomer.
+    pub compositions: Array2<f64>,
+    /// The energies from the columns of the layout.
+    pub energies: PolymerEnergies,
+    /// The monomer concentrations.
+    pub concentrations: Array1<f64>,
+    /// The monomer and polymer names given in the inputs.
+    pub names: Names,
+    /// The format the CFE input was read as, never `Auto`.
+    pub format: InputFormat,
+    /// Anything suspicious about the inputs that didn't stop them being read, such as an
+    /// ambiguous format.
+    pub warnings: Vec<String>,
 }
 
-pub fn parse_float(series: &Series) -> Result<Vec<f64>, Box<dyn Error>> {
-    /* Normal format, what Rust can natively handle. */
-    if series.dtype() == &DataType::Float64 {
-        let float_series = match series.f64() {
-            Ok(s) => s,
-            Err(e) => {
-                return Err(format!("Error converting series to f64: {}", e).into());
-            }
-        };
-        Ok(float_series.into_iter().flatten().collect())
-    }
-    /* Integer format, in case all values were given as ints. */
-    else if series.dtype() == &DataType::Int64 {
-        let int_series = match series.i64() {
-            Ok(s) => s,
-            Err(e) => {
-                return Err(format!("Error converting series to i64: {}", e).into());
-            }
-        };
-        return Ok(int_series
-            .into_iter()
-            .map(|v| v.unwrap_or(0) as f64)
-            .collect());
-    }
-    /* Default format as str, which means series has combination on weird number formats that Rust can't handle natively. */
-    else if series.dtype() == &DataType::Utf8 {
-        /* Convert to utf8, or strings. */
-        let utf8_series = match series.utf8() {
-            Ok(s) => s,
-            Err(e) => {
-                return Err(format!("Error converting series to utf8: {}", e).into());
+/// How the fields of a line are separated.
+#[derive(Clone, Copy, Debug)]
+enum Separator {
+    /// A single delimiter byte between every pair of fields.
+    Byte(u8),
+    /// Any run of whitespace.
+    Whitespace,
+    /// Any run of whitespace and commas.
+    WhitespaceOrComma,
+}
+
+impl Separator {
+    /// Returns whether a character is part of a run separating fields, for run separators.
+    fn is_run_char(&self, c: char) -> bool {
+        match *self {
+            Separator::Byte(_) => false,
+            Separator::Whitespace => c.is_whitespace(),
+            Separator::WhitespaceOrComma => c.is_whitespace() || c == ',',
+        }
+    }
+
+    /// Splits a line into its fields, trimming the whitespace around them.
+    fn split<'a>(&self, line: &'a str) -> Vec<&'a str> {
+        match *self {
+            Separator::Byte(delimiter) => line.split(delimiter as char).map(str::trim).collect(),
+            Separator::Whitespace | Separator::WhitespaceOrComma => line
+                .split(|c: char| self.is_run_char(c))
+                .filter(|field| !field.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Returns the 1-based field that the end of a partial line falls in.
+    fn column_at(&self, partial_line: &str) -> usize {
+        match *self {
+            Separator::Byte(delimiter) => {
+                partial_line.bytes().filter(|&b| b == delimiter).count() + 1
             }
-        };
-        let mut float_values = Vec::new();
-        for (index, value) in utf8_series.into_iter().enumerate() {
-            /* Get string from wrap. */
-            let value_str = match value {
-                Some(v) => v,
-                None => return Err(format!("Error parsing number at index: {} ", index).into()),
-            };
-
-            /* Check whether it's in exponent format with the e. */
-            if value_str.contains('e') {
-                let (base, exponent) = value_str.split_once('e').unwrap();
-                if let Ok(base_value) = base.trim().parse::<f64>() {
-                    if let Ok(exp_value) = exponent.trim().parse::<i32>() {
-                        float_values.push(base_value * 10f64.powi(exp_value));
-                    } else {
-                        return Err(format!(
-                            "Error parsing number: {} at index {}.",
-                            value_str, index
-                        )
-                        .into());
-                    }
+            Separator::Whitespace | Separator::WhitespaceOrComma => {
+                let fields = self.split(partial_line).len();
+                let in_field = partial_line
+                    .chars()
+                    .last()
+                    .is_some_and(|c| !self.is_run_char(c));
+                if in_field {
+                    fields
+                } else {
+                    fields + 1
                 }
-            } else {
-                /* Else it's in decimal form. */
-                let (base_value, decimal_value) =
-                    value_str.split_once('.').unwrap_or((value_str, ""));
-
-                if let Ok(base_value_f64) = base_value.trim().parse::<f64>() {
-                    if decimal_value.is_empty() {
-                        /* No decimal value, just push base. */
-                        float_values.push(base_value_f64);
-                    } else {
-                        /* Do calculation for number = base + decimal / 10^len.  */
-                        if let Ok(decimal_value_f64) = decimal_value.trim().parse::<f64>() {
-                            float_values.push(
-                                base_value_f64
-                                    + decimal_value_f64 / 10f64.powi(decimal_value.len() as i32),
-                            );
-                        } else {
-                            return Err(format!(
-                                "Error parsing decimal part: {} at index {}.",
-                                decimal_value, index
-                            )
-                            .into());
-                        }
-                    }
+            }
+        }
+    }
+}
+
+/// One line of an input holding values, split into its fields.
+struct Row<'a> {
+    /// The 1-based line number.
+    line: usize,
+    fields: Vec<&'a str>,
+}
+
+/// Removes the byte order mark some editors start UTF-8 files with.
+fn strip_bom(content: &str) -> &str {
+    content.strip_prefix('\u{feff}').unwrap_or(content)
+}
+
+/// Checks that an input is valid UTF-8, and removes any byte order mark.
+///
+/// # Arguments
+///
+/// * `file_content` - The contents of the input.
+/// * `file` - Which input it is, for errors.
+/// * `separator` - How its fields are separated, to locate an invalid byte.
+///
+/// # Returns
+///
+/// The input as text, or a parse error at the first invalid byte.
+fn decode(file_content: &[u8], file: InputFile, separator: Separator) -> Result<&str, CoffeeError> {
+    let content = std::str::from_utf8(file_content).map_err(|e| {
+        /* The bytes before the error are valid, so they can be split like any other line. */
+        let valid = std::str::from_utf8(&file_content[..e.valid_up_to()]).unwrap_or_default();
+        let line_start = valid.rfind('\n').map_or(0, |i| i + 1);
+        CoffeeError::parse(
+            file,
+            valid.matches('\n').count() + 1,
+            separator.column_at(&valid[line_start..]),
+            "The input is not valid UTF-8.",
+        )
+    })?;
+    Ok(strip_bom(content))
+}
+
+/// Splits an input into the fields of each line, skipping blank lines.
+///
+/// # Arguments
+///
+/// * `content` - The input text.
+/// * `separator` - How the fields of a line are separated.
+///
+/// # Returns
+///
+/// The rows, in order, with their line numbers.
+fn tokenize(content: &str, separator: Separator) -> Vec<Row<'_>> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, text)| !text.trim().is_empty())
+        .map(|(index, text)| Row {
+            line: index + 1,
+            fields: separator.split(text),
+        })
+        .collect()
+}
+
+/// Returns whether the text follows the number grammar
+/// `[+-]? (digits [. digits?] | . digits) ([eE] [+-]? digits)?`.
+fn is_number(text: &[u8]) -> bool {
+    let digits = |position: &mut usize| {
+        let start = *position;
+        while text.get(*position).is_some_and(u8::is_ascii_digit) {
+            *position += 1;
+        }
+        *position - start
+    };
+
+    let mut position = 0;
+    if matches!(text.first(), Some(b'+' | b'-')) {
+        position += 1;
+    }
+    let mut mantissa_digits = digits(&mut position);
+    if text.get(position) == Some(&b'.') {
+        position += 1;
+        mantissa_digits += digits(&mut position);
+    }
+    if mantissa_digits == 0 {
+        return false;
+    }
+    if matches!(text.get(position), Some(b'e' | b'E')) {
+        position += 1;
+        if matches!(text.get(position), Some(b'+' | b'-')) {
+            position += 1;
+        }
+        if digits(&mut position) == 0 {
+            return false;
+        }
+    }
+    position == text.len()
+}
+
+/// Parses one value of an input. Only plain decimal and scientific notation is accepted, e.g.
+/// `-1.25`, `.5` or `6.02E23`; infinities, NaN, hex, digit separators and values too large for
+/// an f64 are rejected.
+///
+/// # Arguments
+///
+/// * `text` - The value, without surrounding whitespace.
+/// * `file` - The input the value is from, for errors.
+/// * `line` - The 1-based line of the value, for errors.
+/// * `column` - The 1-based field of the value, for errors.
+///
+/// # Returns
+///
+/// The value, or a parse error at its position.
+pub fn parse_number(
+    text: &str,
+    file: InputFile,
+    line: usize,
+    column: usize,
+) -> Result<f64, CoffeeError> {
+    if text.is_empty() {
+        return Err(CoffeeError::parse(file, line, column, "Missing value."));
+    }
+    if !is_number(text.as_bytes()) {
+        return Err(CoffeeError::parse(
+            file,
+            line,
+            column,
+            format!("\"{}\" is not a number.", text),
+        ));
+    }
+    match text.parse::<f64>() {
+        Ok(value) if value.is_finite() => Ok(value),
+        _ => Err(CoffeeError::parse(
+            file,
+            line,
+            column,
+            format!("{} is too large.", text),
+        )),
+    }
+}
+
+/// Parses the fields of the rows into a matrix, skipping leading name columns. All rows must
+/// have as many fields as the first.
+///
+/// # Arguments
+///
+/// * `rows` - The rows of the input, at least one.
+/// * `file` - The input the rows are from, for errors.
+/// * `first_column` - The number of leading fields that aren't values.
+///
+/// # Returns
+///
+/// The matrix, one row per input row, or a parse error at the first bad value.
+fn parse_table(
+    rows: &[Row],
+    file: InputFile,
+    first_column: usize,
+) -> Result<Array2<f64>, CoffeeError> {
+    let num_columns = rows[0].fields.len();
+    let mut values = Vec::with_capacity(rows.len() * num_columns);
+    for row in rows {
+        check_width(row, num_columns, file)?;
+        for (index, field) in row.fields.iter().enumerate().skip(first_column) {
+            values.push(parse_number(field, file, row.line, index + 1)?);
+        }
+    }
+    Ok(Array2::from_shape_vec((rows.len(), num_columns - first_column), values).unwrap())
+}
+
+/// Checks that a row has the expected number of fields.
+///
+/// # Arguments
+///
+/// * `row` - The row to check.
+/// * `num_columns` - The number of fields expected, like the first row.
+/// * `file` - The input the row is from, for errors.
+///
+/// # Returns
+///
+/// A parse error at the first missing or extra field, if any.
+fn check_width(row: &Row, num_columns: usize, file: InputFile) -> Result<(), CoffeeError> {
+    if row.fields.len() == num_columns {
+        return Ok(());
+    }
+    Err(CoffeeError::parse(
+        file,
+        row.line,
+        row.fields.len().min(num_columns) + 1,
+        format!(
+            "{} fields, expected {} like the first row.",
+            row.fields.len(),
+            num_columns
+        ),
+    ))
+}
+
+/// Reads one name per row from a column of an input.
+///
+/// # Arguments
+///
+/// * `rows` - The rows of the input.
+/// * `file` - The input the rows are from, for errors.
+/// * `column` - The 0-based column holding the names.
+///
+/// # Returns
+///
+/// The names, or a parse error at the first missing one.
+fn read_names(rows: &[Row], file: InputFile, column: usize) -> Result<Vec<String>, CoffeeError> {
+    rows.iter()
+        .map(|row| match row.fields.get(column) {
+            Some(name) if !name.is_empty() => Ok(name.to_string()),
+            _ => Err(CoffeeError::parse(
+                file,
+                row.line,
+                column + 1,
+                "Missing name.",
+            )),
+        })
+        .collect()
+}
+
+/// Returns whether a field holds a name rather than a value.
+fn is_name(field: &str) -> bool {
+    !field.is_empty() && !is_number(field.as_bytes())
+}
+
+/// The concentrations read from a CON input.
+struct Concentrations {
+    values: Array1<f64>,
+    /// The monomer names, if the input has a name column.
+    names: Option<Vec<String>>,
+    /// The 1-based line of each concentration.
+    lines: Vec<usize>,
+}
+
+/// Parses the CON input: one concentration per line, each optionally after a name.
+///
+/// # Arguments
+///
+/// * `file_content` - The contents of the concentrations input.
+///
+/// # Returns
+///
+/// The concentrations, named if the first line starts with a name, or a parse error at the
+/// first problem.
+fn read_concentrations(file_content: &[u8]) -> Result<Concentrations, CoffeeError> {
+    let separator = detect_delimiter(file_content)
+        .ok()
+        .and_then(|delimiter| delimiter.separator())
+        .unwrap_or(Separator::Byte(b','));
+    let rows = tokenize(
+        decode(file_content, InputFile::Concentrations, separator)?,
+        separator,
+    );
+    if rows.is_empty() {
+        return Err(CoffeeError::parse(
+            InputFile::Concentrations,
+            1,
+            1,
+            "The input is empty.",
+        ));
+    }
+    let named = is_name(rows[0].fields[0]);
+    let expected_fields = 1 + usize::from(named);
+    if let Some(row) = rows.iter().find(|row| row.fields.len() != expected_fields) {
+        return Err(CoffeeError::parse(
+            InputFile::Concentrations,
+            row.line,
+            row.fields.len().min(expected_fields) + 1,
+            format!(
+                "{} fields, expected {} per line.",
+                row.fields.len(),
+                if named {
+                    "a name and a concentration"
                 } else {
-                    return Err(
-                        format!("Error parsing number: {} at index {}.", value_str, index).into(),
-                    );
+                    "one concentration"
                 }
+            ),
+        ));
+    }
+    let concentrations = parse_table(&rows, InputFile::Concentrations, usize::from(named))?
+        .column(0)
+        .to_owned();
+    let names = if named {
+        Some(read_names(&rows, InputFile::Concentrations, 0)?)
+    } else {
+        None
+    };
+    Ok(Concentrations {
+        values: concentrations,
+        names,
+        lines: rows.iter().map(|row| row.line).collect(),
+    })
+}
+
+/// Parses a composition string such as `A1 B2 D1`: strand names from the CON input, each
+/// followed by its count, which can be left out for 1. A strand named more than once has its
+/// counts added.
+///
+/// # Arguments
+///
+/// * `text` - The composition string.
+/// * `strands` - The strand names, in column order.
+/// * `line` - The 1-based line of the string, for errors.
+///
+/// # Returns
+///
+/// The count of each strand, or a parse error if a part isn't a known strand and a count, or
+/// the count doesn't fit in a `u16`.
+pub(crate) fn parse_composition(
+    text: &str,
+    strands: &[String],
+    line: usize,
+) -> Result<Vec<f64>, CoffeeError> {
+    let error = |message: String| CoffeeError::parse(InputFile::Compositions, line, 1, message);
+    if text.split_whitespace().next().is_none() {
+        return Err(error("Missing composition.".to_string()));
+    }
+
+    let mut counts = vec![0.0; strands.len()];
+    for part in text.split_whitespace() {
+        /* Names may end in digits, so try every strand the part could start with. */
+        let matches: Vec<(usize, &str)> = strands
+            .iter()
+            .enumerate()
+            .filter_map(|(index, name)| {
+                let count = part.strip_prefix(name.as_str())?;
+                count
+                    .bytes()
+                    .all(|b| b.is_ascii_digit())
+                    .then_some((index, count))
+            })
+            .collect();
+        match matches[..] {
+            [(index, "")] => counts[index] += 1.0,
+            [(index, count)] => match count.parse::<u16>() {
+                Ok(count) => counts[index] += f64::from(count),
+                Err(_) => {
+                    return Err(error(format!(
+                        "The count of \"{}\" is larger than {}.",
+                        part,
+                        u16::MAX
+                    )))
+                }
+            },
+            [] => {
+                return Err(error(format!(
+                    "\"{}\" is not a strand name from the CON input and a count.",
+                    part
+                )))
             }
+            _ => {
+                return Err(error(format!(
+                    "\"{}\" could be more than one strand and count.",
+                    part
+                )))
+            }
+        }
+    }
+    Ok(counts)
+}
+
+/// Builds the energies of the layout from the energy columns at the end of a table.
+pub(crate) fn read_energies(table: &Array2<f64>, layout: EnergyLayout) -> PolymerEnergies {
+    let energy_start = table.ncols() - layout.num_columns();
+    let energy = |offset: usize| table.column(energy_start + offset).to_owned();
+    match layout {
+        EnergyLayout::FreeEnergy => PolymerEnergies::Fixed(energy(0)),
+        EnergyLayout::EnthalpyEntropy => PolymerEnergies::thermodynamic(energy(0), energy(1), None),
+        EnergyLayout::EnthalpyEntropyHeatCapacity => {
+            PolymerEnergies::thermodynamic(energy(0), energy(1), Some(energy(2)))
         }
-        return Ok(float_values);
+    }
+}
+
+/// Splits a CFE input into its rows, separating a first row without any numbers as a header.
+///
+/// # Arguments
+///
+/// * `file_content` - The contents of the CFE input.
+/// * `delimiter` - The delimiter between fields, or `Auto` to detect it.
+///
+/// # Returns
+///
+/// The header, if any, and the other rows, at least one, or a parse error if there are none.
+fn read_cfe_rows(
+    file_content: &[u8],
+    delimiter: Delimiter,
+) -> Result<(Option<Row<'_>>, Vec<Row<'_>>), CoffeeError> {
+    let delimiter = match delimiter {
+        Delimiter::Auto => detect_delimiter(file_content)?,
+        delimiter => delimiter,
+    };
+    let separator = delimiter.separator().unwrap_or(Separator::Whitespace);
+    let mut rows = tokenize(
+        decode(file_content, InputFile::Compositions, separator)?,
+        separator,
+    );
+    if rows.is_empty() {
+        return Err(CoffeeError::parse(
+            InputFile::Compositions,
+            1,
+            1,
+            "The input is empty.",
+        ));
+    }
+
+    /* A first row without any numbers is a header. */
+    let header = if rows[0].fields.iter().all(|field| is_name(field)) {
+        Some(rows.remove(0))
     } else {
-        return Err("Unsupported number types given in files for float conversion".into());
+        None
+    };
+    if rows.is_empty() {
+        return Err(CoffeeError::parse(
+            InputFile::Compositions,
+            header.map_or(1, |header| header.line + 1),
+            1,
+            "The input has a header but no polymers.",
+        ));
+    }
+    Ok((header, rows))
+}
+
+/// Builds the composition matrix of rows starting with a composition string.
+///
+/// # Arguments
+///
+/// * `rows` - The rows, each starting with its composition string.
+/// * `strands` - The strand names, in column order.
+///
+/// # Returns
+///
+/// The composition matrix, one row per input row, or a parse error at the first bad string.
+fn read_composition_column(rows: &[Row], strands: &[String]) -> Result<Array2<f64>, CoffeeError> {
+    let mut compositions = Array2::zeros((rows.len(), strands.len()));
+    for (row, mut composition) in rows.iter().zip(compositions.rows_mut()) {
+        let counts = parse_composition(row.fields[0], strands, row.line)?;
+        composition.assign(&Array1::from(counts));
+    }
+    Ok(compositions)
+}
+
+/// Parses the CFE and CON inputs. The CFE input holds one row per polymer, with its composition
+/// followed by the energy columns of the layout. NUPACK files start each row with two index
+/// columns, which are dropped. The CON input holds one concentration per line.
+///
+/// Names are optional. A first CFE row without any numbers is a header naming every column,
+/// and the names of its composition columns are the monomer names. A first CFE column that
+/// isn't a number holds polymer names. A CON input can name its monomers in a column before
+/// the concentrations; if the CFE header names them too, the names must match.
+///
+/// Instead of a column per monomer, each CFE row can hold a composition string such as
+/// `A1 B2 D1` before its energies. The strands are then named by the CON input, in the order of
+/// the composition columns, and each string names its polymer.
+///
+/// In `Auto` format, rows with only a name and the energies hold composition strings. Otherwise
+/// an input is read as NUPACK if its first rows are numbered from 1 with a permutation index
+/// of 1. When there is also one concentration per column, the input could be either, so it's
+/// read as conventional with a warning.
+///
+/// # Arguments
+///
+/// * `file_content_cfe` - The contents of the compositions and free energies input.
+/// * `file_content_con` - The contents of the concentrations input.
+/// * `options` - How the inputs are laid out.
+///
+/// # Returns
+///
+/// The parsed inputs, or a parse error at the line and column of the first problem. Composition
+/// strings can't be read with the whitespace delimiter, which would split them.
+pub fn read_inputs(
+    file_content_cfe: &[u8],
+    file_content_con: &[u8],
+    options: &ParseOptions,
+) -> Result<ParsedInputs, CoffeeError> {
+    if options.format == InputFormat::CompositionStrings
+        && options.delimiter == Delimiter::Whitespace
+    {
+        return Err(CoffeeError::InvalidInput(
+            "Composition strings contain spaces, so they can't be read with the whitespace \
+             delimiter. Separate the fields with commas or tabs."
+                .to_string(),
+        ));
+    }
+    let (header, rows) = read_cfe_rows(file_content_cfe, options.delimiter)?;
+    let header = header.as_ref();
+    let first_row = &rows[0];
+
+    let first_line = first_row.line;
+    let num_columns = first_row.fields.len();
+    let name_columns = usize::from(is_name(first_row.fields[0]));
+    let num_energy_columns = options.energy_layout.num_columns();
+    let composition_strings = match options.format {
+        InputFormat::Auto => name_columns == 1 && num_columns == 1 + num_energy_columns,
+        format => format == InputFormat::CompositionStrings,
+    };
+    if composition_strings && num_columns != 1 + num_energy_columns {
+        return Err(CoffeeError::parse(
+            InputFile::Compositions,
+            first_line,
+            num_columns.min(1 + num_energy_columns) + 1,
+            format!(
+                "{} columns, expected a composition string followed by {} energy column(s).",
+                num_columns, num_energy_columns
+            ),
+        ));
+    }
+    if !composition_strings && num_columns <= name_columns + num_energy_columns {
+        return Err(CoffeeError::parse(
+            InputFile::Compositions,
+            first_line,
+            num_columns,
+            format!(
+                "{} columns, expected compositions followed by {} energy column(s).",
+                num_columns, num_energy_columns
+            ),
+        ));
+    }
+    if let Some(header) = header {
+        check_width(header, num_columns, InputFile::Compositions)?;
+    }
+
+    let concentrations = read_concentrations(file_content_con)?;
+    if composition_strings {
+        return read_composition_strings(&rows, concentrations, options.energy_layout);
+    }
+
+    let table = parse_table(&rows, InputFile::Compositions, name_columns)?;
+    let polymer_names = match name_columns {
+        0 => None,
+        _ => Some(read_names(&rows, InputFile::Compositions, 0)?),
+    };
+    let energies = read_energies(&table, options.energy_layout);
+
+    /* Columns are counted in the table from here, without the name column. */
+    let energy_start = table.ncols() - num_energy_columns;
+    let mut warnings = Vec::new();
+    let format = match options.format {
+        InputFormat::Auto => {
+            let looks_nupack = energy_start >= 2
+                && table
+                    .rows()
+                    .into_iter()
+                    .take(NUPACK_SAMPLE_ROWS)
+                    .enumerate()
+                    .all(|(index, row)| row[0] == (index + 1) as f64 && row[1] == 1.0);
+            if looks_nupack && concentrations.values.len() == energy_start {
+                warnings.push(format!(
+                    "The first two CFE columns look like NUPACK indices, but there are {} \
+                     concentrations, one per column, so they were read as compositions. Set the \
+                     input format to choose.",
+                    concentrations.values.len()
+                ));
+                InputFormat::Conventional
+            } else if looks_nupack {
+                InputFormat::NupackOcx
+            } else {
+                InputFormat::Conventional
+            }
+        }
+        format => format,
+    };
+    let first_composition = match format {
+        InputFormat::NupackOcx => 2,
+        _ => 0,
+    };
+    if first_composition >= energy_start {
+        return Err(CoffeeError::parse(
+            InputFile::Compositions,
+            first_line,
+            num_columns,
+            format!(
+                "{} columns, expected two NUPACK indices, compositions and {} energy column(s).",
+                num_columns, num_energy_columns
+            ),
+        ));
+    }
+
+    /* Monomer names come from the header's composition columns or the CON name column. */
+    let header_names = header.map(|header| {
+        header.fields[name_columns + first_composition..name_columns + energy_start]
+            .iter()
+            .map(|name| name.to_string())
+            .collect::<Vec<String>>()
+    });
+    if let (Some(header_names), Some(con_names)) = (&header_names, &concentrations.names) {
+        let mismatch = header_names
+            .iter()
+            .zip(con_names)
+            .position(|(header_name, con_name)| header_name != con_name);
+        if let Some(index) = mismatch {
+            return Err(CoffeeError::parse(
+                InputFile::Concentrations,
+                concentrations.lines[index],
+                1,
+                format!(
+                    "\"{}\" doesn't match the CFE header, which names monomer {} \"{}\".",
+                    con_names[index],
+                    index + 1,
+                    header_names[index]
+                ),
+            ));
+        }
+    }
+
+    Ok(ParsedInputs {
+        compositions: table
+            .slice(s![.., first_composition..energy_start])
+            .to_owned(),
+        energies,
+        concentrations: concentrations.values,
+        format,
+        names: Names {
+            monomers: header_names.or(concentrations.names),
+            polymers: polymer_names,
+        },
+        warnings,
+    })
+}
+
+/// Reads CFE rows holding a composition string followed by the energy columns.
+///
+/// # Arguments
+///
+/// * `rows` - The CFE rows, without any header.
+/// * `concentrations` - The CON input, which must name the strands.
+/// * `energy_layout` - The energy columns after each composition string.
+///
+/// # Returns
+///
+/// The parsed inputs, with each composition string naming its polymer, or a parse error at the
+/// first problem.
+fn read_composition_strings(
+    rows: &[Row],
+    concentrations: Concentrations,
+    energy_layout: EnergyLayout,
+) -> Result<ParsedInputs, CoffeeError> {
+    let Some(strands) = concentrations.names else {
+        return Err(CoffeeError::parse(
+            InputFile::Concentrations,
+            concentrations.lines[0],
+            1,
+            "Composition strings need the CON input to name each strand before its concentration.",
+        ));
+    };
+
+    let table = parse_table(rows, InputFile::Compositions, 1)?;
+    Ok(ParsedInputs {
+        compositions: read_composition_column(rows, &strands)?,
+        energies: read_energies(&table, energy_layout),
+        concentrations: concentrations.values,
+        format: InputFormat::CompositionStrings,
+        names: Names {
+            monomers: Some(strands),
+            polymers: Some(read_names(rows, InputFile::Compositions, 0)?),
+        },
+        warnings: Vec::new(),
+    })
+}
+
+/// Parses a CON input on its own, e.g. to enumerate the complexes of its strands.
+///
+/// # Arguments
+///
+/// * `file_content` - The contents of the concentrations input.
+///
+/// # Returns
+///
+/// The concentrations and, if the input names them, the strand names, or a parse error at the
+/// first problem.
+pub fn parse_concentrations(
+    file_content: &[u8],
+) -> Result<(Array1<f64>, Option<Vec<String>>), CoffeeError> {
+    let concentrations = read_concentrations(file_content)?;
+    Ok((concentrations.values, concentrations.names))
+}
+
+/// Parses a list of free energies keyed by composition string, one `A1 B2, -3.5` row per
+/// complex, with an optional header.
+///
+/// # Arguments
+///
+/// * `file_content` - The contents of the energies input.
+/// * `strands` - The strand names the composition strings use, in column order.
+///
+/// # Returns
+///
+/// The composition matrix and the free energy of each row, or a parse error at the first
+/// problem.
+pub fn parse_composition_energies(
+    file_content: &[u8],
+    strands: &[String],
+) -> Result<(Array2<f64>, Array1<f64>), CoffeeError> {
+    let (_, rows) = read_cfe_rows(file_content, Delimiter::Auto)?;
+    if let Some(row) = rows.iter().find(|row| row.fields.len() != 2) {
+        return Err(CoffeeError::parse(
+            InputFile::Compositions,
+            row.line,
+            row.fields.len().min(2) + 1,
+            format!(
+                "{} fields, expected a composition string and a free energy.",
+                row.fields.len()
+            ),
+        ));
+    }
+    let energies = parse_table(&rows, InputFile::Compositions, 1)?
+        .column(0)
+        .to_owned();
+    Ok((read_composition_column(&rows, strands)?, energies))
+}
+
+/// Parses a list of Lagrange multipliers, e.g. the `optimal_lambda` of a previous solve.
+/// Values may be separated by whitespace, commas or new lines.
+///
+/// # Arguments
+///
+/// * `file_content` - The contents of the lambda input.
+///
+/// # Returns
+///
+/// The parsed multipliers, in monomer order, or a parse error at the first value that isn't a
+/// number.
+pub fn parse_lambdas(file_content: &[u8]) -> Result<Vec<f64>, CoffeeError> {
+    let separator = Separator::WhitespaceOrComma;
+    let rows = tokenize(
+        decode(file_content, InputFile::Lambdas, separator)?,
+        separator,
+    );
+    let mut lambdas = Vec::new();
+    for row in rows.iter() {
+        for (index, field) in row.fields.iter().enumerate() {
+            lambdas.push(parse_number(
+                field,
+                InputFile::Lambdas,
+                row.line,
+                index + 1,
+            )?);
+        }
+    }
+    Ok(lambdas)
+}
+
+/// Detects the delimiter of a CFE input from its first rows. Each candidate splits the sampled
+/// rows, and the one giving the same number of fields, at least two, on the most rows wins.
+///
+/// # Arguments
+///
+/// * `file_content` - The contents of the CFE input.
+///
+/// # Returns
+///
+/// The delimiter, or a parse error if no candidate splits the rows into several fields.
+pub fn detect_delimiter(file_content: &[u8]) -> Result<Delimiter, CoffeeError> {
+    /* Invalid UTF-8 is reported when the input is read, so it only needs to be skipped here. */
+    let content = String::from_utf8_lossy(file_content);
+    let sample: Vec<&str> = strip_bom(&content)
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .take(DELIMITER_SAMPLE_ROWS)
+        .collect();
+
+    let mut best: Option<(Delimiter, usize)> = None;
+    for candidate in DELIMITER_CANDIDATES {
+        let separator = candidate.separator().unwrap_or(Separator::Whitespace);
+        let counts: Vec<usize> = sample
+            .iter()
+            .map(|line| separator.split(line).len())
+            .collect();
+
+        /* Score by the number of rows with the most common number of fields. */
+        let (consistent_rows, fields) = counts
+            .iter()
+            .map(|&c| (counts.iter().filter(|&&d| d == c).count(), c))
+            .max()
+            .unwrap_or_default();
+        if fields >= 2 && best.is_none_or(|(_, score)| consistent_rows > score) {
+            best = Some((candidate, consistent_rows));
+        }
     }
+
+    best.map(|(delimiter, _)| delimiter).ok_or_else(|| {
+        CoffeeError::parse(InputFile::Compositions, 1, 1, "Failed to detect delimiter")
+    })
 }
 
-fn detect_delimiter(file_content: &[u8]) -> std::result::Result<u8, Box<dyn Error>> {
-    file_content
-        .iter()
-        .copied()
-        .find(|&b| !b.is_ascii_alphanumeric())
-        .ok_or_else(|| "Failed to detect delimiter".into())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the line and column of a parse error.
+    fn position(error: CoffeeError) -> (usize, usize) {
+        match error {
+            CoffeeError::Parse { line, column, .. } => (line, column),
+            e => panic!("expected a parse error, got {}", e),
+        }
+    }
+
+    #[test]
+    fn test_parse_number() {
+        let parse = |text| parse_number(text, InputFile::Compositions, 1, 1);
+        assert_eq!(parse("-1.25").unwrap(), -1.25);
+        assert_eq!(parse("+.5").unwrap(), 0.5);
+        assert_eq!(parse("3.").unwrap(), 3.0);
+        assert_eq!(parse("-2.5E-3").unwrap(), -2.5e-3);
+        assert_eq!(parse("0e+0").unwrap(), 0.0);
+        assert_eq!(parse("1203.66").unwrap(), 1203.66);
+
+        for text in [
+            "", "inf", "-inf", "NaN", "1e", "e5", ".", "-", "1.2.3", "1e5.0", "0x10", "1,000",
+            "1_000", "1e400", "- 1",
+        ] {
+            assert!(parse(text).is_err(), "{} should not parse", text);
+        }
+    }
+
+    #[test]
+    fn test_read_inputs() {
+        let options = ParseOptions::default();
+        let inputs = read_inputs(
+            b"1\t0\t-1.25\n0\t1\t-0.5E1\n\n1\t1\t-20\n",
+            b"1e-6\n2e-6\n",
+            &options,
+        )
+        .unwrap();
+        assert_eq!(inputs.compositions.dim(), (3, 2));
+        assert_eq!(
+            inputs.energies.free_energies(37.0).to_vec(),
+            vec![-1.25, -5.0, -20.0]
+        );
+        assert_eq!(inputs.concentrations.to_vec(), vec![1e-6, 2e-6]);
+
+        /* NUPACK files have their index columns dropped. */
+        let inputs = read_inputs(b"1,1,1,0,-1\n2,1,0,1,-2\n", b"1e-6\n1e-6\n", &options).unwrap();
+        assert_eq!(inputs.compositions.dim(), (2, 2));
+
+        /* Every failure is located, counting blank lines. */
+        let con = b"1e-6\n1e-6\n";
+        let error = read_inputs(b"1,0,-1\n\n0,1,nan\n", con, &options).unwrap_err();
+        assert_eq!(position(error), (3, 3));
+        let error = read_inputs(b"1,0,-1\n0,1\n", con, &options).unwrap_err();
+        assert_eq!(position(error), (2, 3));
+        let error = read_inputs(b"1,0,-1\n0,1,-2\n", b"1e-6\n1e-6,2\n", &options).unwrap_err();
+        assert_eq!(position(error), (2, 2));
+        let error = read_inputs(b"1,0,-1\n0,1,\xff\n", con, &options).unwrap_err();
+        assert_eq!(position(error), (2, 3));
+
+        let error = parse_lambdas(b"1.0, 2.0\n3.0 x\n").unwrap_err();
+        assert_eq!(position(error), (2, 2));
+    }
+
+    #[test]
+    fn test_detect_delimiter() {
+        let cases: [(&[u8], Delimiter); 7] = [
+            (b"1\t0\t-1.5\n0\t1\t-2\n", Delimiter::Tab),
+            (b"1, 0, .5\n0, 1, +2\n", Delimiter::Comma),
+            (b"1;0;-1\n0;1;-2\n", Delimiter::Semicolon),
+            (b"1|0|-1\n0|1|-2\n", Delimiter::Pipe),
+            (
+                b"  1    0   -1.25\n  0    1  -20.5\n",
+                Delimiter::Whitespace,
+            ),
+            (b"\xef\xbb\xbf1,0,-1\n0,1,-2\n", Delimiter::Comma),
+            /* A row with a missing field doesn't outvote the rest. */
+            (b"1,0,-1\n0,1\n1,1,-2\n", Delimiter::Comma),
+        ];
+        for (content, expected) in cases {
+            assert_eq!(detect_delimiter(content).unwrap(), expected);
+        }
+        assert!(detect_delimiter(b"12\n").is_err());
+
+        /* Space-aligned tables with a BOM parse, and a given delimiter overrides detection. */
+        let inputs = read_inputs(
+            b"\xef\xbb\xbf  1  0  -1.25\n  0  1  -2\n  1  1  -20\n",
+            b"\xef\xbb\xbf1e-6\n1e-6\n",
+            &ParseOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(inputs.compositions.dim(), (3, 2));
+        let options = ParseOptions {
+            delimiter: Delimiter::Semicolon,
+            ..Default::default()
+        };
+        assert!(read_inputs(b"1,0,-1\n0,1,-2\n", b"1e-6\n1e-6\n", &options).is_err());
+    }
+
+    #[test]
+    fn test_input_format() {
+        let cfe = b"1,1,1,0,-1\n2,1,0,1,-2\n3,1,1,1,-3\n4,1,0,2,-4\n";
+        let with_format = |format| ParseOptions {
+            format,
+            ..Default::default()
+        };
+
+        /* Two concentrations fit the NUPACK reading. */
+        let inputs = read_inputs(cfe, b"1e-6\n1e-6\n", &ParseOptions::default()).unwrap();
+        assert_eq!(inputs.format, InputFormat::NupackOcx);
+        assert_eq!(inputs.compositions.dim(), (4, 2));
+        assert!(inputs.warnings.is_empty());
+
+        /* Four fit the conventional one, which is kept with a warning. */
+        let con = b"1e-6\n1e-6\n1e-6\n1e-6\n";
+        let inputs = read_inputs(cfe, con, &ParseOptions::default()).unwrap();
+        assert_eq!(inputs.format, InputFormat::Conventional);
+        assert_eq!(inputs.compositions.dim(), (4, 4));
+        assert_eq!(inputs.warnings.len(), 1);
+
+        /* A given format is used as is, without warnings. */
+        let inputs = read_inputs(cfe, con, &with_format(InputFormat::Conventional)).unwrap();
+        assert_eq!(
+            inputs.compositions.column(0).to_vec(),
+            vec![1.0, 2.0, 3.0, 4.0]
+        );
+        assert!(inputs.warnings.is_empty());
+        let inputs = read_inputs(
+            b"1,1,1,0,-1\n1,2,0,1,-2\n",
+            b"1e-6\n1e-6\n",
+            &with_format(InputFormat::NupackOcx),
+        )
+        .unwrap();
+        assert_eq!(inputs.compositions.dim(), (2, 2));
+
+        /* NUPACK rows need a composition after the index columns. */
+        let error = read_inputs(
+            b"1,1,-1\n2,1,-2\n",
+            b"1e-6\n",
+            &with_format(InputFormat::NupackOcx),
+        )
+        .unwrap_err();
+        assert_eq!(position(error), (1, 3));
+    }
+
+    #[test]
+    fn test_names() {
+        let options = ParseOptions::default();
+        let con = b"1e-6\n2e-6\n";
+
+        /* A header names the monomers, and a first column of names names the polymers. */
+        let cfe = b"complex,A,B,dG\nA,1,0,0\nB,0,1,0\nAB,1,1,-10\n";
+        let inputs = read_inputs(cfe, con, &options).unwrap();
+        assert_eq!(inputs.compositions.dim(), (3, 2));
+        assert_eq!(inputs.names.monomers, Some(vec!["A".into(), "B".into()]));
+        assert_eq!(
+            inputs.names.polymers,
+            Some(vec!["A".into(), "B".into(), "AB".into()])
+        );
+
+        /* NUPACK index columns aren't monomers. */
+        let cfe = b"id\tperm\tA\tB\tdG\n1\t1\t1\t0\t0\n2\t1\t0\t1\t0\n3\t1\t1\t1\t-10\n";
+        let inputs = read_inputs(cfe, con, &options).unwrap();
+        assert_eq!(inputs.names.monomers, Some(vec!["A".into(), "B".into()]));
+        assert_eq!(inputs.names.polymers, None);
+
+        /* A CON name column names the monomers, and must agree with a header. */
+        let cfe = b"A,B,dG\n1,0,0\n0,1,0\n1,1,-10\n";
+        let inputs = read_inputs(cfe, b"A\t1e-6\nB\t2e-6\n", &options).unwrap();
+        assert_eq!(inputs.concentrations.to_vec(), vec![1e-6, 2e-6]);
+        assert_eq!(inputs.names.monomers, Some(vec!["A".into(), "B".into()]));
+        let error = read_inputs(cfe, b"A,1e-6\nC,2e-6\n", &options).unwrap_err();
+        assert_eq!(position(error), (2, 1));
+        let error = read_inputs(cfe, b"A,1e-6\n2e-6\n", &options).unwrap_err();
+        assert_eq!(position(error), (2, 2));
+
+        /* Headers need a name for every column, and polymers after them. */
+        let error = read_inputs(b"A,B\n1,0,0\n0,1,0\n", con, &options).unwrap_err();
+        assert_eq!(position(error), (1, 3));
+        let error = read_inputs(b"A,B,dG\n\n", con, &options).unwrap_err();
+        assert_eq!(position(error), (2, 1));
+    }
+
+    #[test]
+    fn test_composition_strings() {
+        let options = ParseOptions::default();
+        let con = b"A, 1e-6\nB, 2e-6\nD, 1e-6\n";
+
+        /* Strands follow the CON order, whatever order the strings name them in. */
+        let cfe = b"composition, dG\nA1, 0\nB1, 0\nD1, 0\nD1 A1 B2, -1203.66\nA1 A1, -5\n";
+        let inputs = read_inputs(cfe, con, &options).unwrap();
+        assert_eq!(inputs.format, InputFormat::CompositionStrings);
+        assert_eq!(inputs.compositions.row(3).to_vec(), vec![1.0, 2.0, 1.0]);
+        assert_eq!(inputs.compositions.row(4).to_vec(), vec![2.0, 0.0, 0.0]);
+        assert_eq!(
+            inputs.energies.free_energies(37.0).to_vec(),
+            vec![0.0, 0.0, 0.0, -1203.66, -5.0]
+        );
+        assert_eq!(inputs.names.polymer(3), Some("D1 A1 B2"));
+
+        /* Names ending in digits are matched against the strands, and counts of 1 can be left
+         * out. */
+        let inputs = read_inputs(
+            b"S1 S22\t-3\nS1\t0\nS2\t0\n",
+            b"S1\t1e-6\nS2\t1e-6\n",
+            &options,
+        )
+        .unwrap();
+        assert_eq!(inputs.compositions.row(0).to_vec(), vec![1.0, 2.0]);
+
+        /* Unknown or ambiguous strands and unnamed concentrations are errors. */
+        let error = read_inputs(b"A1, 0\nC2, -1\n", con, &options).unwrap_err();
+        assert_eq!(position(error), (2, 1));
+        let error = read_inputs(b"S12, 0\n", b"S, 1e-6\nS1, 1e-6\n", &options).unwrap_err();
+        assert_eq!(position(error), (1, 1));
+
+        /* Counts too large to be a real complex are errors rather than infinite. */
+        let cfe = b"A1, 0\nA99999999999999999999999 B1, -20\n";
+        let error = read_inputs(cfe, con, &options).unwrap_err();
+        assert_eq!(position(error), (2, 1));
+        let error = read_inputs(b"A1, 0\nB1, 0\n", b"1e-6\n1e-6\n", &options).unwrap_err();
+        assert!(matches!(
+            error,
+            CoffeeError::Parse {
+                file: InputFile::Concentrations,
+                ..
+            }
+        ));
+        let options = ParseOptions {
+            format: InputFormat::CompositionStrings,
+            ..Default::default()
+        };
+        let error = read_inputs(b"A1, 0, 1\n", con, &options).unwrap_err();
+        assert_eq!(position(error), (1, 3));
+
+        /* The whitespace delimiter would split the strings themselves. */
+        let options = ParseOptions {
+            delimiter: Delimiter::Whitespace,
+            ..options
+        };
+        assert!(matches!(
+            read_inputs(b"A1 B1, -20\n", con, &options),
+            Err(CoffeeError::InvalidInput(_))
+        ));
+    }
 }
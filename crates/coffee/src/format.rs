@@ -1,9 +1,104 @@
-use crate::extras::OptimizerResults;
+use crate::extras::{IterationRecord, Names, OptimizerResults, TerminationReason};
+use crate::fileparse::Delimiter;
+use crate::sensitivity::Sensitivities;
+use crate::sweep::{MeltCurve, SweepResults};
+use ndarray::{Array1, Array2};
+use serde::Serialize;
+use std::fmt;
+
+/// How the results of an optimization are written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// The polymer concentrations, on one line or one per line after their names.
+    #[default]
+    Text,
+    /// A JSON object holding every value of the results, labeled by name.
+    Json,
+    /// A comma-separated table with one value per row.
+    Csv,
+    /// A tab-separated table with one value per row.
+    Tsv,
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            OutputFormat::Text => "text",
+            OutputFormat::Json => "json",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Tsv => "tsv",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The results as written by `OutputFormat::Json`.
+#[derive(Serialize)]
+struct ResultsDocument<'a> {
+    termination: &'static str,
+    converged: bool,
+    iterations: usize,
+    elapsed_us: usize,
+    optimal_lagrangian: f64,
+    concentration_error: f64,
+    clamped_values: usize,
+    monomers: Vec<MonomerEntry<'a>>,
+    polymers: Vec<PolymerEntry<'a>>,
+}
+
+#[derive(Serialize)]
+struct MonomerEntry<'a> {
+    name: Option<&'a str>,
+    lambda: f64,
+    free_concentration: f64,
+}
+
+#[derive(Serialize)]
+struct PolymerEntry<'a> {
+    name: Option<&'a str>,
+    concentration: f64,
+}
+
+/// Returns the label of a monomer in the outputs: its name, or its 1-based index.
+fn monomer_label(names: &Names, index: usize) -> String {
+    names
+        .monomer(index)
+        .map_or_else(|| (index + 1).to_string(), str::to_string)
+}
+
+/// Returns the label of a polymer in the outputs: its name, or its 1-based index.
+fn polymer_label(names: &Names, index: usize) -> String {
+    names
+        .polymer(index)
+        .map_or_else(|| (index + 1).to_string(), str::to_string)
+}
+
+/// Returns the header of a polymer's column in a table: its name, or `polymer_` and its
+/// 1-based index.
+fn polymer_column(names: &Names, index: usize) -> String {
+    names
+        .polymer(index)
+        .map_or_else(|| format!("polymer_{}", index + 1), str::to_string)
+}
 
 pub fn start_message() -> String {
     "Starting COFFEE optimization...\r\n".to_string()
 }
 
+/// Formats which delimiter the CFE input was read with, for verbose output.
+///
+/// # Arguments
+///
+/// * `delimiter` - The delimiter used.
+/// * `detected` - Whether it was detected rather than given.
+pub fn delimiter_message(delimiter: Delimiter, detected: bool) -> String {
+    format!(
+        "CFE delimiter: {}{}\r\n",
+        delimiter,
+        if detected { " (detected)" } else { "" }
+    )
+}
+
 pub fn process_message(it: usize, lag: f64, error: f64) -> String {
     format!(
         "Iteration {}: f = {:.12}, error = {:.6e}\r\n",
@@ -13,21 +108,26 @@ pub fn process_message(it: usize, lag: f64, error: f64) -> String {
 
 pub fn conclude_message(
     it: usize,
-    success: bool,
+    reason: TerminationReason,
     time_us: usize,
     display_time: bool,
     results: Option<&OptimizerResults>,
 ) -> String {
     let mut msg1 = format!(
-        "Optimization {} after {} iterations.\r\n\r\n",
-        if success { "complete" } else { "failed" },
-        it
+        "Optimization {} after {} iterations ({}).\r\n\r\n",
+        match reason {
+            TerminationReason::SubproblemFailed => "failed",
+            TerminationReason::Cancelled => "stopped",
+            _ => "complete",
+        },
+        it,
+        reason
     );
 
     if let Some(results) = results {
         /* Format the number of monomers and polymers. */
         msg1.push_str(&format!(
-            "Number of monomers: {}\nNumber of polymers: {}\r\n\r\n",
+            "Number of monomers: {}\r\nNumber of polymers: {}\r\n\r\n",
             results.optimal_lambda.len(),
             results.optimal_x.len()
         ));
@@ -38,17 +138,35 @@ pub fn conclude_message(
             results.optimal_lagrangian
         ));
 
-        /* Format the lambdas. */
+        /* Format the lambdas, one per line next to its monomer's name if monomers are named. */
         msg1.push_str("Optimal Lambdas:\r\n");
-        for l_val in results.optimal_lambda.iter() {
-            msg1.push_str(&format!("{:.6e} ", l_val));
+        if results.names.monomers.is_some() {
+            for (index, l_val) in results.optimal_lambda.iter().enumerate() {
+                msg1.push_str(&format!(
+                    "{}: {:.6e}\r\n",
+                    monomer_label(&results.names, index),
+                    l_val
+                ));
+            }
+            msg1.push_str("\r\n");
+        } else {
+            for l_val in results.optimal_lambda.iter() {
+                msg1.push_str(&format!("{:.6e} ", l_val));
+            }
+            msg1.push_str("\r\n\r\n");
         }
-        msg1.push_str("\r\n\r\n");
 
         msg1.push_str(&format!(
             "Concentration Constraint Error: {:.6e}\r\n",
             results.concentration_error
         ));
+
+        if results.clamped_values > 0 {
+            msg1.push_str(&format!(
+                "Warning: {} polymer concentrations overflowed and were clamped.\r\n",
+                results.clamped_values
+            ));
+        }
     }
     if display_time {
         let et = time_us as f64 / 1000.0; //convert micro to milliseconds
@@ -62,11 +180,433 @@ pub fn conclude_message(
     msg1
 }
 
+/// Formats the polymer concentrations of the results. Named polymers are listed one per line
+/// after their names, and unnamed ones on a single line.
 pub fn results_message(results: &OptimizerResults) -> String {
     let mut msg = String::new();
 
-    for x_val in results.optimal_x.iter() {
-        msg.push_str(&format!("{:.2e} ", x_val));
+    if results.names.polymers.is_some() {
+        for (index, x_val) in results.optimal_x.iter().enumerate() {
+            msg.push_str(&format!(
+                "{}: {:.2e}\r\n",
+                polymer_label(&results.names, index),
+                x_val
+            ));
+        }
+    } else {
+        for x_val in results.optimal_x.iter() {
+            msg.push_str(&format!("{:.2e} ", x_val));
+        }
     }
     msg
 }
+
+/// Formats the results in the given format. Every format but `Text` holds the polymer and free
+/// monomer concentrations, the lambdas, the constraint error, the number of iterations, the
+/// termination reason and the elapsed time in microseconds, with values at full precision.
+///
+/// # Arguments
+///
+/// * `results` - The results of the optimization.
+/// * `format` - The format to write.
+///
+/// # Returns
+///
+/// The formatted results. `Text` is the same as `results_message`.
+pub fn results_output(results: &OptimizerResults, format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Text => results_message(results),
+        OutputFormat::Json => results_json(results),
+        OutputFormat::Csv => results_table(results, ','),
+        OutputFormat::Tsv => results_table(results, '\t'),
+    }
+}
+
+/// Formats the results as a JSON object, with a `monomers` and a `polymers` array after the
+/// summary values. Unnamed monomers and polymers have a null name.
+fn results_json(results: &OptimizerResults) -> String {
+    let document = ResultsDocument {
+        termination: results.termination_reason.code(),
+        converged: results.termination_reason.is_converged(),
+        iterations: results.iterations,
+        elapsed_us: results.elapsed_time,
+        optimal_lagrangian: results.optimal_lagrangian,
+        concentration_error: results.concentration_error,
+        clamped_values: results.clamped_values,
+        monomers: results
+            .optimal_lambda
+            .iter()
+            .zip(&results.free_monomers)
+            .enumerate()
+            .map(|(index, (&lambda, &free_concentration))| MonomerEntry {
+                name: results.names.monomer(index),
+                lambda,
+                free_concentration,
+            })
+            .collect(),
+        polymers: results
+            .optimal_x
+            .iter()
+            .enumerate()
+            .map(|(index, &concentration)| PolymerEntry {
+                name: results.names.polymer(index),
+                concentration,
+            })
+            .collect(),
+    };
+    /* Only non-finite values could fail, and serde_json writes those as null. */
+    let mut json = serde_json::to_string_pretty(&document).unwrap();
+    json.push('\n');
+    json
+}
+
+/// Quotes a table field if it holds the delimiter, a quote or a line break.
+fn table_field(text: &str, delimiter: char) -> String {
+    if text.contains([delimiter, '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+/// Formats the results as a table of `quantity`, `index`, `name` and `value` columns, one
+/// value per row: the summary values first, then the `lambda` and `free_monomer` of each
+/// monomer and the concentration of each `polymer`, by 1-based index. Values are written in
+/// the shortest scientific notation that reads back exactly.
+fn results_table(results: &OptimizerResults, delimiter: char) -> String {
+    let summary = [
+        ("termination", results.termination_reason.code().to_string()),
+        ("iterations", results.iterations.to_string()),
+        ("elapsed_us", results.elapsed_time.to_string()),
+        (
+            "optimal_lagrangian",
+            format!("{:e}", results.optimal_lagrangian),
+        ),
+        (
+            "concentration_error",
+            format!("{:e}", results.concentration_error),
+        ),
+        ("clamped_values", results.clamped_values.to_string()),
+    ];
+    let mut rows: Vec<[String; 4]> = summary
+        .into_iter()
+        .map(|(quantity, value)| [quantity.to_string(), String::new(), String::new(), value])
+        .collect();
+    for (quantity, values) in [
+        ("lambda", &results.optimal_lambda),
+        ("free_monomer", &results.free_monomers),
+    ] {
+        for (index, value) in values.iter().enumerate() {
+            rows.push([
+                quantity.to_string(),
+                (index + 1).to_string(),
+                results.names.monomer(index).unwrap_or_default().to_string(),
+                format!("{:e}", value),
+            ]);
+        }
+    }
+    for (index, value) in results.optimal_x.iter().enumerate() {
+        rows.push([
+            "polymer".to_string(),
+            (index + 1).to_string(),
+            results.names.polymer(index).unwrap_or_default().to_string(),
+            format!("{:e}", value),
+        ]);
+    }
+
+    let delimiter_text = delimiter.to_string();
+    let mut table = ["quantity", "index", "name", "value"].join(&delimiter_text);
+    table.push('\n');
+    for row in rows {
+        let fields: Vec<String> = row
+            .iter()
+            .map(|field| table_field(field, delimiter))
+            .collect();
+        table.push_str(&fields.join(&delimiter_text));
+        table.push('\n');
+    }
+    table
+}
+
+/// Formats the results of a sweep as a table, with one row per sweep point and one column
+/// per polymer after the swept value. Columns of named polymers are headed by their names.
+///
+/// # Arguments
+///
+/// * `label` - The header of the swept value column.
+/// * `sweep` - The results of the sweep.
+/// * `delimiter` - The column delimiter, e.g. a tab or a comma.
+///
+/// # Returns
+///
+/// The table, including a header row.
+pub fn sweep_table(label: &str, sweep: &SweepResults, delimiter: char) -> String {
+    let (num_polymers, names) = sweep.results.first().map_or((0, Names::default()), |r| {
+        (r.optimal_x.len(), r.names.clone())
+    });
+
+    let mut table = label.to_string();
+    for polymer in 0..num_polymers {
+        table.push_str(&format!("{}{}", delimiter, polymer_column(&names, polymer)));
+    }
+    table.push('\n');
+
+    for (value, results) in sweep.values.iter().zip(sweep.results.iter()) {
+        table.push_str(&format!("{:.6e}", value));
+        for x_val in results.optimal_x.iter() {
+            table.push_str(&format!("{}{:.6e}", delimiter, x_val));
+        }
+        table.push('\n');
+    }
+    table
+}
+
+/// Formats a melt curve as a table, with one row per temperature, one column per polymer and
+/// then one column per monomer holding its bound fraction, labeled by name if named.
+///
+/// # Arguments
+///
+/// * `curve` - The results of the temperature sweep.
+/// * `delimiter` - The column delimiter, e.g. a tab or a comma.
+///
+/// # Returns
+///
+/// The table, including a header row.
+pub fn melt_table(curve: &MeltCurve, delimiter: char) -> String {
+    let num_monomers = curve.fraction_bound.first().map_or(0, |f| f.len());
+    let names = curve
+        .sweep
+        .results
+        .first()
+        .map_or_else(Names::default, |r| r.names.clone());
+    let mut lines = sweep_table("temperature", &curve.sweep, delimiter)
+        .lines()
+        .map(|line| line.to_string())
+        .collect::<Vec<String>>();
+
+    if let Some(header) = lines.first_mut() {
+        for monomer in 0..num_monomers {
+            header.push_str(&format!(
+                "{}fraction_bound_{}",
+                delimiter,
+                monomer_label(&names, monomer)
+            ));
+        }
+    }
+    for (line, fractions) in lines.iter_mut().skip(1).zip(curve.fraction_bound.iter()) {
+        for fraction in fractions.iter() {
+            line.push_str(&format!("{}{:.6e}", delimiter, fraction));
+        }
+    }
+
+    let mut table = lines.join("\n");
+    table.push('\n');
+    table
+}
+
+/// Formats sensitivities as a table, with one column per selected polymer. The `x0_i` rows hold
+/// d x_j / d x0_i for each monomer i, and the `dG_k` rows hold d ln x_j / d dG_k for each
+/// polymer k. Named monomers and polymers are labeled by their names.
+///
+/// # Arguments
+///
+/// * `sensitivities` - The sensitivities of the selected polymers.
+/// * `delimiter` - The column delimiter, e.g. a tab or a comma.
+///
+/// # Returns
+///
+/// The table, including a header row.
+pub fn sensitivity_table(sensitivities: &Sensitivities, delimiter: char) -> String {
+    let mut table = "parameter".to_string();
+    let names = &sensitivities.names;
+    for &polymer in sensitivities.polymers.iter() {
+        table.push_str(&format!("{}{}", delimiter, polymer_column(names, polymer)));
+    }
+    table.push('\n');
+
+    for (label, columns, row_label) in [
+        (
+            "x0",
+            sensitivities.concentration.columns(),
+            monomer_label as fn(&Names, usize) -> String,
+        ),
+        ("dG", sensitivities.energy.columns(), polymer_label),
+    ] {
+        for (index, column) in columns.into_iter().enumerate() {
+            table.push_str(&format!("{}_{}", label, row_label(names, index)));
+            for value in column.iter() {
+                table.push_str(&format!("{}{:.6e}", delimiter, value));
+            }
+            table.push('\n');
+        }
+    }
+    table
+}
+
+/// Formats a composition matrix as a table, one row per complex, e.g. to write the complexes
+/// from `enumerate_compositions`. With energies, the table is a CFE input.
+///
+/// # Arguments
+///
+/// * `compositions` - The composition matrix, one column per strand.
+/// * `strands` - The strand names, which head their columns if given.
+/// * `energies` - The free energy of each complex, written after its composition if given.
+/// * `delimiter` - The column delimiter, e.g. a tab or a comma.
+///
+/// # Returns
+///
+/// The table, with a header row if the strands are named.
+pub fn composition_table(
+    compositions: &Array2<f64>,
+    strands: Option<&[String]>,
+    energies: Option<&Array1<f64>>,
+    delimiter: char,
+) -> String {
+    let delimiter = delimiter.to_string();
+    let mut table = String::new();
+    if let Some(strands) = strands {
+        table.push_str(&strands.join(&delimiter));
+        if energies.is_some() {
+            table.push_str(&format!("{}dG", delimiter));
+        }
+        table.push('\n');
+    }
+
+    for (index, composition) in compositions.rows().into_iter().enumerate() {
+        let mut row: Vec<String> = composition.iter().map(|count| count.to_string()).collect();
+        if let Some(energies) = energies {
+            row.push(energies[index].to_string());
+        }
+        table.push_str(&row.join(&delimiter));
+        table.push('\n');
+    }
+    table
+}
+
+/// Formats the iteration history of an optimization as a table, with one row per iteration.
+/// Values that don't apply to the solver, such as the trust region radius of a line search,
+/// are left empty.
+///
+/// # Arguments
+///
+/// * `history` - The iteration records from `OptimizerResults::history`.
+/// * `delimiter` - The column delimiter, e.g. a tab or a comma.
+///
+/// # Returns
+///
+/// The table, including a header row.
+pub fn history_table(history: &[IterationRecord], delimiter: char) -> String {
+    let optional = |value: Option<f64>| value.map_or(String::new(), |v| format!("{:.6e}", v));
+
+    let mut table = [
+        "iteration",
+        "objective",
+        "gradient_norm",
+        "constraint_error",
+        "delta",
+        "rho",
+        "accepted",
+        "inner_iterations",
+    ]
+    .join(&delimiter.to_string());
+    table.push('\n');
+
+    for record in history {
+        let row = [
+            record.iteration.to_string(),
+            format!("{:.12e}", record.objective),
+            format!("{:.6e}", record.gradient_norm),
+            format!("{:.6e}", record.constraint_error),
+            optional(record.delta),
+            optional(record.rho),
+            record.accepted.to_string(),
+            record.inner_iterations.to_string(),
+        ];
+        table.push_str(&row.join(&delimiter.to_string()));
+        table.push('\n');
+    }
+    table
+}
+
+/// Formats the estimated melting temperatures of a melt curve, one target per line.
+pub fn melting_message(curve: &MeltCurve) -> String {
+    let names = curve
+        .sweep
+        .results
+        .first()
+        .map_or_else(Names::default, |r| r.names.clone());
+    let mut msg = String::new();
+    for &(target, tm) in curve.melting_temperatures.iter() {
+        match tm {
+            Some(tm) => msg.push_str(&format!(
+                "Melting temperature of polymer {}: {:.2} C\r\n",
+                polymer_label(&names, target),
+                tm
+            )),
+            None => msg.push_str(&format!(
+                "Melting temperature of polymer {}: not reached in range\r\n",
+                polymer_label(&names, target)
+            )),
+        }
+    }
+    msg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extras::OptimizerArgs;
+    use crate::problem::dimer_problem;
+
+    #[test]
+    fn test_results_output() {
+        let problem = dimer_problem(-20.0)
+            .with_names(Names {
+                monomers: Some(vec!["A".to_string(), "B".to_string()]),
+                polymers: Some(vec!["A".to_string(), "B".to_string(), "A,B".to_string()]),
+            })
+            .unwrap();
+        let args = OptimizerArgs {
+            use_terminal: false,
+            ..Default::default()
+        };
+        let results = problem.solve(&args).unwrap();
+        assert_eq!(results.free_monomers, results.optimal_x[..2].to_vec());
+
+        let json: serde_json::Value =
+            serde_json::from_str(&results_output(&results, OutputFormat::Json)).unwrap();
+        assert_eq!(json["termination"], "converged");
+        assert_eq!(json["iterations"], results.iterations);
+        assert_eq!(json["monomers"][1]["name"], "B");
+        assert_eq!(json["polymers"][2]["concentration"], results.optimal_x[2]);
+
+        /* One row per summary value, per monomer lambda and free concentration, and per
+         * polymer, after the header. Names holding the delimiter are quoted. */
+        let table = results_output(&results, OutputFormat::Csv);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 1 + 6 + 2 * 2 + 3);
+        assert_eq!(lines[0], "quantity,index,name,value");
+        assert!(lines[13].starts_with("polymer,3,\"A,B\","));
+        let table = results_output(&results, OutputFormat::Tsv);
+        assert!(table
+            .lines()
+            .nth(13)
+            .unwrap()
+            .starts_with("polymer\t3\tA,B\t"));
+
+        assert_eq!(
+            results_output(&results, OutputFormat::Text),
+            results_message(&results)
+        );
+
+        /* The log uses one line ending throughout. */
+        let message = conclude_message(
+            results.iterations,
+            results.termination_reason,
+            results.elapsed_time,
+            true,
+            Some(&results),
+        );
+        assert!(!message.replace("\r\n", "").contains('\n'));
+    }
+}
@@ -0,0 +1,231 @@
+use crate::composition::CompositionMatrix;
+use crate::error::CoffeeError;
+use nalgebra::DMatrix;
+use ndarray::{Array1, Array2, ArrayView1, Axis};
+
+/// Largest number of times the damping of a singular Newton system is increased.
+const MAX_DAMPING_INCREASES: usize = 20;
+
+/// A symmetric M x M operator that can be multiplied by a vector, used by the trust region
+/// subproblem solvers in place of an explicit Hessian matrix.
+pub trait HessianOperator {
+    /// Returns the size M of the operator.
+    fn dim(&self) -> usize;
+
+    /// Calculates the product of the operator with the given vector.
+    ///
+    /// # Arguments
+    ///
+    /// * `v` - A vector of size M.
+    ///
+    /// # Returns
+    ///
+    /// The product Hv, of size M.
+    fn apply(&self, v: ArrayView1<f64>) -> Array1<f64>;
+
+    /// Builds the dense M x M matrix of the operator, one column at a time.
+    ///
+    /// # Returns
+    ///
+    /// The operator as a 2-dimensional array.
+    fn to_dense(&self) -> Array2<f64> {
+        let size = self.dim();
+        let mut dense = Array2::zeros((size, size));
+        let mut unit = Array1::zeros(size);
+        for i in 0..size {
+            unit[i] = 1.0;
+            dense.column_mut(i).assign(&self.apply(unit.view()));
+            unit[i] = 0.0;
+        }
+        dense
+    }
+}
+
+/// A dense Hessian matrix is its own operator.
+impl HessianOperator for Array2<f64> {
+    fn dim(&self) -> usize {
+        self.len_of(Axis(0))
+    }
+
+    fn apply(&self, v: ArrayView1<f64>) -> Array1<f64> {
+        self.dot(&v)
+    }
+
+    fn to_dense(&self) -> Array2<f64> {
+        self.clone()
+    }
+}
+
+/// Matrix-free Hessian of the dual objective ln(q . exp(A lambda) - lambda . x0).
+/// With polymer weights w = q * exp(A lambda) / exp(L) and gradient g, the Hessian is
+/// A^T diag(w) A - g g^T, so Hv = A^T (w * (A v)) - g (g . v).
+/// Each product costs O(N M) instead of the O(N M^2) needed to build the dense matrix.
+pub struct DualHessian<'a> {
+    polymers: &'a CompositionMatrix,
+    weights: ArrayView1<'a, f64>,
+    gradient: ArrayView1<'a, f64>,
+}
+
+impl<'a> DualHessian<'a> {
+    /// Creates the operator for the current point of the optimization.
+    ///
+    /// # Arguments
+    ///
+    /// * `polymers` - The polymer composition matrix A (N x M).
+    /// * `weights` - The polymer weights w (length N).
+    /// * `gradient` - The gradient g of the dual objective (length M).
+    pub fn new(
+        polymers: &'a CompositionMatrix,
+        weights: ArrayView1<'a, f64>,
+        gradient: ArrayView1<'a, f64>,
+    ) -> Self {
+        Self {
+            polymers,
+            weights,
+            gradient,
+        }
+    }
+}
+
+impl HessianOperator for DualHessian<'_> {
+    fn dim(&self) -> usize {
+        self.polymers.ncols()
+    }
+
+    fn apply(&self, v: ArrayView1<f64>) -> Array1<f64> {
+        let weighted = &self.polymers.dot(v) * &self.weights;
+        self.polymers.t_dot(weighted.view()) - &self.gradient * self.gradient.dot(&v)
+    }
+}
+
+/// Solves H X = B for a symmetric positive definite H with a Cholesky factorization.
+///
+/// # Arguments
+///
+/// * `hessian` - The M x M matrix H.
+/// * `rhs` - The M x K right-hand sides B.
+///
+/// # Returns
+///
+/// The M x K solution X, or an error if H is not positive definite.
+pub(crate) fn solve_positive_definite(
+    hessian: &Array2<f64>,
+    rhs: &Array2<f64>,
+) -> Result<Array2<f64>, CoffeeError> {
+    let size = hessian.nrows();
+    let matrix = DMatrix::from_fn(size, size, |i, j| hessian[[i, j]]);
+    let Some(cholesky) = matrix.cholesky() else {
+        return Err(CoffeeError::SingularHessian);
+    };
+
+    let solution = cholesky.solve(&DMatrix::from_fn(size, rhs.ncols(), |i, j| rhs[[i, j]]));
+    Ok(Array2::from_shape_fn((size, rhs.ncols()), |(i, j)| {
+        solution[(i, j)]
+    }))
+}
+
+/// Solves the damped Newton system (H + mu I) p = -g, starting with no damping and increasing
+/// it until the matrix is positive definite. The Hessian of the dual objective is only positive
+/// semi-definite, and monomers with vanishing concentrations make it numerically singular.
+///
+/// # Arguments
+///
+/// * `hessian` - The dense M x M Hessian H.
+/// * `gradient` - The gradient g.
+///
+/// # Returns
+///
+/// The Newton step p and whether damping was needed to find it, or an error if no damping made
+/// the system solvable. A damped step doesn't solve the undamped system.
+pub(crate) fn damped_newton_step(
+    hessian: &Array2<f64>,
+    gradient: &Array1<f64>,
+) -> Result<(Array1<f64>, bool), CoffeeError> {
+    let size = gradient.len();
+    let rhs = (-gradient).into_shape_with_order((size, 1)).unwrap();
+    let scale = hessian
+        .diag()
+        .iter()
+        .fold(f64::MIN_POSITIVE, |a, &b| a.max(b.abs()));
+
+    let mut damping = 0.0;
+    for _ in 0..MAX_DAMPING_INCREASES {
+        let damped = hessian + &(Array2::<f64>::eye(size) * damping);
+        if let Ok(solution) = solve_positive_definite(&damped, &rhs) {
+            let step = solution.column(0).to_owned();
+            if step.iter().all(|v| v.is_finite()) {
+                return Ok((step, damping > 0.0));
+            }
+        }
+        damping = if damping == 0.0 {
+            1.0e-12 * scale
+        } else {
+            damping * 100.0
+        };
+    }
+    Err(CoffeeError::SubproblemFailed(
+        "The Newton system could not be solved.".to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::composition::MatrixStorage;
+    use ndarray::array;
+
+    #[test]
+    fn test_dual_hessian() {
+        let polymers = array![[1.0, 0.0], [0.0, 1.0], [1.0, 1.0], [2.0, 1.0]];
+        let weights = array![0.1, 0.2, 0.3, 0.4];
+        let gradient = array![0.5, -0.25];
+        let sparse = CompositionMatrix::from(&polymers)
+            .with_storage(MatrixStorage::Sparse)
+            .unwrap();
+        let hessian = DualHessian::new(&sparse, weights.view(), gradient.view());
+
+        /* Build A^T diag(w) A - g g^T explicitly. */
+        let weighted = &polymers * &weights.view().insert_axis(Axis(1));
+        let expected = polymers.t().dot(&weighted)
+            - gradient
+                .view()
+                .insert_axis(Axis(1))
+                .dot(&gradient.view().insert_axis(Axis(0)));
+
+        let dense = hessian.to_dense();
+        assert_eq!(dense.dim(), (2, 2));
+        for (a, b) in dense.iter().zip(expected.iter()) {
+            assert!((a - b).abs() < 1.0e-12);
+        }
+
+        let v = array![1.5, -2.0];
+        let product = hessian.apply(v.view());
+        for (a, b) in product.iter().zip(expected.apply(v.view()).iter()) {
+            assert!((a - b).abs() < 1.0e-12);
+        }
+    }
+
+    #[test]
+    fn test_solve_positive_definite() {
+        let hessian = array![[4.0, 1.0], [1.0, 3.0]];
+        let rhs = array![[1.0, 0.0], [2.0, 1.0]];
+        let solution = solve_positive_definite(&hessian, &rhs).unwrap();
+        for (a, b) in hessian.dot(&solution).iter().zip(rhs.iter()) {
+            assert!((a - b).abs() < 1.0e-12);
+        }
+
+        let singular = array![[1.0, 1.0], [1.0, 1.0]];
+        assert!(solve_positive_definite(&singular, &rhs).is_err());
+
+        /* Damping only enters when the system needs it. */
+        let gradient = array![1.0, -2.0];
+        let (step, damped) = damped_newton_step(&hessian, &gradient).unwrap();
+        assert!(!damped);
+        for (a, b) in hessian.dot(&step).iter().zip(gradient.iter()) {
+            assert!((a + b).abs() < 1.0e-12);
+        }
+        let (step, damped) = damped_newton_step(&singular, &gradient).unwrap();
+        assert!(damped);
+        assert!(step.iter().all(|v| v.is_finite()) && step.dot(&gradient) < 0.0);
+    }
+}
@@ -0,0 +1,539 @@
+use std::collections::HashSet;
+
+use ndarray::{Array1, Array2, ArrayView1};
+use serde::Deserialize;
+
+use crate::composition::{CompositionMatrix, MatrixStorage};
+use crate::enumerate::composition_string;
+use crate::error::CoffeeError;
+use crate::extras::{HessianMode, InitialLambda, Names, OptimizerArgs, SolverKind, SubproblemKind};
+use crate::fileparse::{parse_composition, read_energies, EnergyLayout};
+use crate::problem::EquilibriumProblem;
+
+/// A problem read from a JSON input, with the optimizer parameters to solve it with.
+#[derive(Clone)]
+pub struct JsonProblem {
+    pub problem: EquilibriumProblem,
+    /// The base parameters, with the temperature and any overrides from the input applied.
+    pub optimizer_args: OptimizerArgs,
+}
+
+/// The whole JSON document.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProblemDocument {
+    strands: Option<Vec<String>>,
+    concentrations: Vec<f64>,
+    complexes: Vec<ComplexEntry>,
+    temperature: Option<f64>,
+    #[serde(default)]
+    optimizer: OptimizerOverrides,
+}
+
+/// One complex, with its energy either fixed or as enthalpy and entropy.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ComplexEntry {
+    composition: CompositionEntry,
+    name: Option<String>,
+    energy: Option<f64>,
+    enthalpy: Option<f64>,
+    entropy: Option<f64>,
+    heat_capacity: Option<f64>,
+}
+
+#[derive(Deserialize)]
+#[serde(
+    untagged,
+    expecting = "expected a list of strand counts or a composition string"
+)]
+enum CompositionEntry {
+    Counts(Vec<f64>),
+    Text(String),
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum LambdaStart {
+    Zero,
+    Heuristic,
+}
+
+#[derive(Deserialize)]
+#[serde(
+    untagged,
+    expecting = "expected \"zero\", \"heuristic\" or a list of multipliers"
+)]
+enum LambdaEntry {
+    Start(LambdaStart),
+    Given(Vec<f64>),
+}
+
+/// The `OptimizerArgs` fields an input may set. Output settings such as `use_terminal` are left
+/// to the caller.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct OptimizerOverrides {
+    max_iterations: Option<usize>,
+    max_delta: Option<f64>,
+    eta: Option<f64>,
+    norm_ratio_threshold: Option<f64>,
+    rho_thresholds: Option<[f64; 2]>,
+    scale_factors: Option<[f64; 2]>,
+    scalarity: Option<bool>,
+    verbose: Option<bool>,
+    gradient_tolerance: Option<f64>,
+    constraint_tolerance: Option<f64>,
+    lagrangian_tolerance: Option<f64>,
+    initial_lambda: Option<LambdaEntry>,
+    hessian_mode: Option<HessianMode>,
+    matrix_storage: Option<MatrixStorage>,
+    threads: Option<usize>,
+    solver: Option<SolverKind>,
+    subproblem: Option<SubproblemKind>,
+}
+
+impl OptimizerOverrides {
+    /// Applies the overrides that are set on top of the given parameters.
+    ///
+    /// # Arguments
+    ///
+    /// * `args` - The parameters to override.
+    /// * `num_strands` - The number of strands, which given multipliers must match.
+    fn apply(self, args: &mut OptimizerArgs, num_strands: usize) -> Result<(), CoffeeError> {
+        args.max_iterations = self.max_iterations.unwrap_or(args.max_iterations);
+        args.max_delta = self.max_delta.unwrap_or(args.max_delta);
+        args.eta = self.eta.unwrap_or(args.eta);
+        args.norm_ratio_threshold = self
+            .norm_ratio_threshold
+            .unwrap_or(args.norm_ratio_threshold);
+        args.rho_thresholds = self.rho_thresholds.unwrap_or(args.rho_thresholds);
+        args.scale_factors = self.scale_factors.unwrap_or(args.scale_factors);
+        args.scalarity = self.scalarity.unwrap_or(args.scalarity);
+        args.verbose = self.verbose.unwrap_or(args.verbose);
+        args.gradient_tolerance = self.gradient_tolerance.unwrap_or(args.gradient_tolerance);
+        args.constraint_tolerance = self
+            .constraint_tolerance
+            .unwrap_or(args.constraint_tolerance);
+        args.lagrangian_tolerance = self
+            .lagrangian_tolerance
+            .unwrap_or(args.lagrangian_tolerance);
+        args.hessian_mode = self.hessian_mode.unwrap_or(args.hessian_mode);
+        args.matrix_storage = self.matrix_storage.unwrap_or(args.matrix_storage);
+        args.threads = self.threads.unwrap_or(args.threads);
+        args.solver = self.solver.unwrap_or(args.solver);
+        args.subproblem = self.subproblem.unwrap_or(args.subproblem);
+
+        match self.initial_lambda {
+            Some(LambdaEntry::Start(LambdaStart::Zero)) => {
+                args.initial_lambda = InitialLambda::Zero
+            }
+            Some(LambdaEntry::Start(LambdaStart::Heuristic)) => {
+                args.initial_lambda = InitialLambda::Heuristic
+            }
+            Some(LambdaEntry::Given(lambda)) if lambda.len() != num_strands => {
+                return Err(json_error(
+                    "optimizer.initial_lambda",
+                    format!(
+                        "{} multipliers given for {} strands.",
+                        lambda.len(),
+                        num_strands
+                    ),
+                ))
+            }
+            Some(LambdaEntry::Given(lambda)) => args.initial_lambda = InitialLambda::Given(lambda),
+            None => {}
+        }
+        Ok(())
+    }
+}
+
+/// Creates an error at a JSON path.
+fn json_error(path: impl Into<String>, message: impl Into<String>) -> CoffeeError {
+    CoffeeError::Json {
+        path: path.into(),
+        message: message.into(),
+    }
+}
+
+/// Finds how a complex gives its energy.
+///
+/// # Arguments
+///
+/// * `complex` - The complex.
+/// * `path` - The JSON path of the complex, for errors.
+fn energy_layout(complex: &ComplexEntry, path: &str) -> Result<EnergyLayout, CoffeeError> {
+    match (
+        complex.energy,
+        complex.enthalpy,
+        complex.entropy,
+        complex.heat_capacity,
+    ) {
+        (Some(_), None, None, None) => Ok(EnergyLayout::FreeEnergy),
+        (None, Some(_), Some(_), None) => Ok(EnergyLayout::EnthalpyEntropy),
+        (None, Some(_), Some(_), Some(_)) => Ok(EnergyLayout::EnthalpyEntropyHeatCapacity),
+        (Some(_), _, _, _) => Err(json_error(
+            path,
+            "Give either \"energy\" or \"enthalpy\" and \"entropy\", not both.",
+        )),
+        _ => Err(json_error(
+            path,
+            "Missing \"energy\", or \"enthalpy\" and \"entropy\".",
+        )),
+    }
+}
+
+/// Checks that names are non-empty and distinct.
+///
+/// # Arguments
+///
+/// * `names` - Each name with its JSON path, for errors.
+fn check_names(names: &[(String, String)]) -> Result<(), CoffeeError> {
+    let mut seen = HashSet::new();
+    for (name, path) in names {
+        if name.is_empty() {
+            return Err(json_error(path.as_str(), "The name is empty."));
+        }
+        if !seen.insert(name) {
+            return Err(json_error(
+                path.as_str(),
+                format!("The name \"{}\" is used more than once.", name),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Reads a problem from a JSON input holding the strand concentrations, the complexes and
+/// optionally strand names, the temperature and optimizer parameters, e.g.
+///
+/// ```json
+/// {
+///   "strands": ["A", "B"],
+///   "concentrations": [1e-6, 1e-6],
+///   "complexes": [
+///     {"composition": "A1", "energy": 0},
+///     {"composition": "B1", "energy": 0},
+///     {"composition": "A1 B1", "energy": -20, "name": "AB"}
+///   ],
+///   "temperature": 25,
+///   "optimizer": {"solver": "newton-line-search", "max_iterations": 200}
+/// }
+/// ```
+///
+/// # Arguments
+///
+/// * `file_content` - The contents of the JSON input.
+/// * `optimizer_args` - The parameters to solve with where the input doesn't set them.
+///
+/// # Returns
+///
+/// The problem and its parameters, or a `CoffeeError::Json` at the path of the first value
+/// that can't be parsed or doesn't fit the problem.
+pub fn read_json(
+    file_content: &[u8],
+    optimizer_args: &OptimizerArgs,
+) -> Result<JsonProblem, CoffeeError> {
+    let mut deserializer = serde_json::Deserializer::from_slice(file_content);
+    let document: ProblemDocument = serde_path_to_error::deserialize(&mut deserializer)
+        .map_err(|e| json_error(e.path().to_string(), e.inner().to_string()))?;
+    deserializer
+        .end()
+        .map_err(|e| json_error(".", e.to_string()))?;
+
+    let num_strands = document.concentrations.len();
+    if num_strands == 0 {
+        return Err(json_error("concentrations", "No strand concentrations."));
+    }
+    if let Some(index) = document.concentrations.iter().position(|&c| c < 0.0) {
+        return Err(json_error(
+            format!("concentrations[{}]", index),
+            "The concentration is negative.",
+        ));
+    }
+    if let Some(strands) = &document.strands {
+        if strands.len() != num_strands {
+            return Err(json_error(
+                "strands",
+                format!(
+                    "{} strand names given for {} concentrations.",
+                    strands.len(),
+                    num_strands
+                ),
+            ));
+        }
+        let paths = (0..num_strands).map(|index| format!("strands[{}]", index));
+        check_names(&strands.iter().cloned().zip(paths).collect::<Vec<_>>())?;
+    }
+
+    let num_complexes = document.complexes.len();
+    if num_complexes < num_strands {
+        return Err(json_error(
+            "complexes",
+            format!(
+                "{} complexes given for {} strands, but every strand must also be a complex.",
+                num_complexes, num_strands
+            ),
+        ));
+    }
+
+    /* Every complex gives its energy the same way as the first. */
+    let layout = energy_layout(&document.complexes[0], "complexes[0]")?;
+    let mut compositions = Array2::zeros((num_complexes, num_strands));
+    let mut energies = Array2::zeros((num_complexes, layout.num_columns()));
+    let mut names = Vec::with_capacity(num_complexes);
+    for (index, complex) in document.complexes.into_iter().enumerate() {
+        let path = format!("complexes[{}]", index);
+        let composition_path = format!("{}.composition", path);
+        let (counts, text) = match complex.composition {
+            CompositionEntry::Counts(ref counts) => (counts.clone(), None),
+            CompositionEntry::Text(ref text) => {
+                let Some(strands) = &document.strands else {
+                    return Err(json_error(
+                        composition_path,
+                        "Composition strings need \"strands\" to name the strands.",
+                    ));
+                };
+                let counts = parse_composition(text, strands, index + 1).map_err(|e| match e {
+                    CoffeeError::Parse { message, .. } => {
+                        json_error(composition_path.as_str(), message)
+                    }
+                    e => e,
+                })?;
+                (counts, Some(text.trim().to_string()))
+            }
+        };
+        if counts.len() != num_strands {
+            return Err(json_error(
+                composition_path,
+                format!("{} counts given for {} strands.", counts.len(), num_strands),
+            ));
+        }
+        if let Some(strand) = counts.iter().position(|&count| count < 0.0) {
+            return Err(json_error(
+                format!("{}[{}]", composition_path, strand),
+                "The count is negative.",
+            ));
+        }
+        compositions
+            .row_mut(index)
+            .assign(&ArrayView1::from(&counts));
+
+        if energy_layout(&complex, &path)? != layout {
+            return Err(json_error(
+                path,
+                "Every complex must give its energy the same way as complexes[0].",
+            ));
+        }
+        let values = [
+            complex.energy,
+            complex.enthalpy,
+            complex.entropy,
+            complex.heat_capacity,
+        ];
+        energies
+            .row_mut(index)
+            .assign(&Array1::from_iter(values.into_iter().flatten()));
+
+        /* Complexes given by composition string are named by it. Count lists are only named
+         * by their composition string if other complexes are named. */
+        let name = match (complex.name, text) {
+            (Some(name), _) => Some((name, format!("{}.name", path))),
+            (None, Some(text)) => Some((text, composition_path.clone())),
+            (None, None) => None,
+        };
+        let implicit = match &document.strands {
+            Some(strands) if name.is_none() && counts.iter().all(|count| count.fract() == 0.0) => {
+                let counts: Vec<usize> = counts.iter().map(|&count| count as usize).collect();
+                Some((composition_string(&counts, strands), composition_path))
+            }
+            _ => None,
+        };
+        names.push((name, implicit, path));
+    }
+
+    let polymer_names = if names.iter().any(|(name, _, _)| name.is_some()) {
+        let mut named = Vec::with_capacity(num_complexes);
+        for (name, implicit, path) in names {
+            let Some(name) = name.or(implicit) else {
+                return Err(json_error(
+                    format!("{}.name", path),
+                    "Missing name, since other complexes are named.",
+                ));
+            };
+            named.push(name);
+        }
+        check_names(&named)?;
+        Some(named.into_iter().map(|(name, _)| name).collect())
+    } else {
+        None
+    };
+
+    let mut optimizer_args = optimizer_args.clone();
+    if let Some(temperature) = document.temperature {
+        optimizer_args.temp_celsius = temperature;
+    }
+    document.optimizer.apply(&mut optimizer_args, num_strands)?;
+
+    let polymers = CompositionMatrix::from(compositions).with_storage(MatrixStorage::Auto)?;
+    let problem = EquilibriumProblem::new(
+        Array1::from(document.concentrations),
+        polymers,
+        read_energies(&energies, layout),
+    )?
+    .with_names(Names {
+        monomers: document.strands,
+        polymers: polymer_names,
+    })?;
+    Ok(JsonProblem {
+        problem,
+        optimizer_args,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fileparse::ParseOptions;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_read_json() {
+        let input = br#"{
+            "strands": ["A", "B"],
+            "concentrations": [1e-6, 2e-6],
+            "complexes": [
+                {"composition": "A1", "energy": 0},
+                {"composition": [0, 1], "energy": 0, "name": "B"},
+                {"composition": "A1 B1", "energy": -20, "name": "AB"},
+                {"composition": "B2", "energy": -1}
+            ],
+            "temperature": 25,
+            "optimizer": {"solver": "newton-line-search", "max_iterations": 200, "initial_lambda": "heuristic"}
+        }"#;
+        let parsed = read_json(input, &OptimizerArgs::default()).unwrap();
+        let problem = &parsed.problem;
+        assert_eq!(problem.num_polymers(), 4);
+        assert_eq!(problem.names().monomer(1), Some("B"));
+        assert_eq!(problem.names().polymer(0), Some("A1"));
+        assert_eq!(problem.names().polymer(2), Some("AB"));
+        assert_eq!(parsed.optimizer_args.temp_celsius, 25.0);
+        assert_eq!(parsed.optimizer_args.solver, SolverKind::NewtonLineSearch);
+        assert_eq!(parsed.optimizer_args.max_iterations, 200);
+        assert_eq!(
+            parsed.optimizer_args.initial_lambda,
+            InitialLambda::Heuristic
+        );
+        assert_eq!(parsed.optimizer_args.hessian_mode, HessianMode::Auto);
+
+        /* The same problem as CFE and CON inputs solves to the same concentrations. */
+        let from_files = EquilibriumProblem::from_bytes(
+            b"1\t0\t0\n0\t1\t0\n1\t1\t-20\n0\t2\t-1\n",
+            b"1e-6\n2e-6\n",
+            &ParseOptions::default(),
+        )
+        .unwrap();
+        let expected = from_files.solve(&parsed.optimizer_args).unwrap();
+        let results = problem.solve(&parsed.optimizer_args).unwrap();
+        for (x, y) in results.optimal_x.iter().zip(&expected.optimal_x) {
+            assert_relative_eq!(x, y, max_relative = 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_read_json_errors() {
+        let error_path = |input: &str| match read_json(input.as_bytes(), &OptimizerArgs::default())
+        {
+            Err(CoffeeError::Json { path, .. }) => path,
+            other => panic!("expected a JSON error, got {:?}", other.err()),
+        };
+        let complexes =
+            r#"[{"composition": [1, 0], "energy": 0}, {"composition": [0, 1], "energy": 0}]"#;
+
+        /* Type errors and unknown fields are found by serde. */
+        assert_eq!(
+            error_path(
+                r#"{"concentrations": [1e-6, 1e-6], "complexes": [{"composition": [1, 0], "energy": "low"}]}"#
+            ),
+            "complexes[0].energy"
+        );
+        assert_eq!(
+            error_path(&format!(
+                r#"{{"concentrations": [1e-6, 1e-6], "complexes": {}, "optimizer": {{"solver": "simplex"}}}}"#,
+                complexes
+            )),
+            "optimizer.solver"
+        );
+        assert_eq!(
+            error_path(&format!(
+                r#"{{"concentrations": [1e-6, 1e-6], "complexes": {}, "optimizer": {{"temp_celsius": 20}}}}"#,
+                complexes
+            )),
+            "optimizer.temp_celsius"
+        );
+        assert_eq!(error_path(r#"{"concentrations": [1e-6"#), "concentrations");
+
+        /* Values that don't fit the problem are found after. */
+        assert_eq!(
+            error_path(
+                r#"{"concentrations": [1e-6, 1e-6], "complexes": [{"composition": [1, 0], "energy": 0}, {"composition": [0, 1, 0], "energy": 0}]}"#
+            ),
+            "complexes[1].composition"
+        );
+        assert_eq!(
+            error_path(
+                r#"{"concentrations": [1e-6, 1e-6], "complexes": [{"composition": [1, 0], "energy": 0}, {"composition": [0, 1], "enthalpy": 0, "entropy": 0}]}"#
+            ),
+            "complexes[1]"
+        );
+        assert_eq!(
+            error_path(&format!(
+                r#"{{"concentrations": [1e-6, 1e-6], "complexes": {}, "optimizer": {{"initial_lambda": [0]}}}}"#,
+                complexes
+            )),
+            "optimizer.initial_lambda"
+        );
+        assert_eq!(
+            error_path(
+                r#"{"strands": ["A", "B"], "concentrations": [1e-6, 1e-6], "complexes": [{"composition": "A1", "energy": 0}, {"composition": "C1", "energy": 0}]}"#
+            ),
+            "complexes[1].composition"
+        );
+        assert_eq!(
+            error_path(
+                r#"{"concentrations": [1e-6, 1e-6], "complexes": [{"composition": [1, 0], "energy": 0, "name": "A"}, {"composition": [0, 1], "energy": 0}]}"#
+            ),
+            "complexes[1].name"
+        );
+    }
+
+    #[test]
+    fn test_implicit_names() {
+        /* Without names, count lists take their composition string alongside composition
+         * strings. */
+        let input = br#"{
+            "strands": ["A", "B"],
+            "concentrations": [1e-6, 1e-6],
+            "complexes": [
+                {"composition": "A1", "energy": 0},
+                {"composition": [0, 1], "energy": 0},
+                {"composition": [1, 1], "energy": -20}
+            ]
+        }"#;
+        let problem = read_json(input, &OptimizerArgs::default()).unwrap().problem;
+        assert_eq!(problem.names().polymer(1), Some("B1"));
+        assert_eq!(problem.names().polymer(2), Some("A1 B1"));
+
+        /* Count lists alone stay unnamed. */
+        let input = br#"{
+            "strands": ["A", "B"],
+            "concentrations": [1e-6, 1e-6],
+            "complexes": [
+                {"composition": [1, 0], "energy": 0},
+                {"composition": [0, 1], "energy": 0}
+            ]
+        }"#;
+        let problem = read_json(input, &OptimizerArgs::default()).unwrap().problem;
+        assert_eq!(problem.names().polymer(0), None);
+    }
+}
@@ -1,154 +1,336 @@
+pub mod composition;
+pub mod dogleg;
+pub mod energy;
+pub mod enumerate;
+pub mod error;
 pub mod extras;
 pub mod fileparse;
 pub mod format;
+pub mod hessian;
+pub mod json;
+pub mod objective;
+pub mod observer;
 pub mod optimize;
+mod parallel;
+pub mod problem;
+pub mod sensitivity;
+pub mod solver;
 pub mod steihaug;
+pub mod sweep;
 
 use std::fs::File;
 use std::io::Read;
 
+use error::CoffeeError;
 use extras::{OptimizerArgs, OptimizerResults};
-use fileparse::{parse_float, read_inputs_to_dataframe};
-use format::results_message;
-use ndarray::{Array1, Array2};
-use optimize::Optimizer;
+use fileparse::{detect_delimiter, Delimiter, ParseOptions};
+use format::{delimiter_message, history_table, results_message, results_output, OutputFormat};
+use json::{read_json, JsonProblem};
+use observer::Observer;
+use problem::EquilibriumProblem;
 
 use core::result::Result;
-use std::error::Error;
 use std::io::Write;
 
-use polars::prelude::DataType;
-
-fn run_coffee_computation(
+/// Parses the CFE and CON inputs into an `EquilibriumProblem` and solves it.
+///
+/// # Arguments
+///
+/// * `cfe_bytes` - The contents of the compositions and free energies input.
+/// * `con_bytes` - The contents of the concentrations input.
+/// * `parse_options` - How the inputs are laid out.
+/// * `optimizer_args` - The optimizer parameters to solve with.
+///
+/// # Returns
+///
+/// The results of the optimization.
+pub fn run_coffee_computation(
     cfe_bytes: &[u8],
     con_bytes: &[u8],
+    parse_options: &ParseOptions,
     optimizer_args: &OptimizerArgs,
-) -> Result<OptimizerResults, Box<dyn Error>> {
-    // Call fileparse to read the inputs and create a dataframe
-    let table = match read_inputs_to_dataframe(cfe_bytes, con_bytes) {
-        Ok(table) => table,
-        Err(e) => {
-            return Err(format!("Error reading files: {}", e).into());
-        }
-    };
+) -> Result<OptimizerResults, CoffeeError> {
+    let problem = EquilibriumProblem::from_bytes(cfe_bytes, con_bytes, parse_options)?;
+    problem.solve(optimizer_args)
+}
 
-    let polymer_rows = table.0.height();
-    let polymer_cols = table.0.width();
+/// Parses a JSON problem and solves it.
+///
+/// # Arguments
+///
+/// * `json_bytes` - The contents of the JSON problem.
+/// * `optimizer_args` - The optimizer parameters where the input doesn't set them.
+///
+/// # Returns
+///
+/// The results of the optimization.
+pub fn run_coffee_json_computation(
+    json_bytes: &[u8],
+    optimizer_args: &OptimizerArgs,
+) -> Result<OptimizerResults, CoffeeError> {
+    let JsonProblem {
+        problem,
+        optimizer_args,
+    } = read_json(json_bytes, optimizer_args)?;
+    problem.solve(&optimizer_args)
+}
 
-    let mut polymer_data = Vec::<f64>::new();
-    for col in table.0.get_columns() {
-        let col_f64 = col.cast(&DataType::Float64)?;
-        let series = col_f64.f64()?;
-        polymer_data.extend(series.into_iter().map(|v| v.unwrap_or(0.0)));
-    }
+/// Reads the whole contents of an input file.
+///
+/// # Arguments
+///
+/// * `file_path` - The path of the file to read.
+///
+/// # Returns
+///
+/// The file contents.
+fn read_file(file_path: &str) -> Result<Vec<u8>, CoffeeError> {
+    let mut file = File::open(file_path).map_err(CoffeeError::io(file_path))?;
+    let mut file_content = Vec::new();
+    file.read_to_end(&mut file_content)
+        .map_err(CoffeeError::io(file_path))?;
+    Ok(file_content)
+}
 
-    let monomer_series_f64 = table.2.cast(&DataType::Float64)?;
-    let monomers_vec = monomer_series_f64
-        .f64()?
-        .into_iter()
-        .flatten()
-        .collect::<Vec<f64>>();
-    let polymer_energy_vec = parse_float(&table.1)?;
-
-    // Create the optimizer
-    let monomers = Array1::from_vec(monomers_vec);
-    let mut polymers = match Array2::from_shape_vec((polymer_cols, polymer_rows), polymer_data) {
-        Ok(polymers) => polymers,
-        Err(e) => {
-            return Err(format!("Failed to create polymers array: {}", e).into());
-        }
-    };
-    polymers.swap_axes(0, 1);
-    let polymers_energies = Array1::from_vec(polymer_energy_vec);
+/// Reads the CFE and CON files into an `EquilibriumProblem`, without solving it.
+///
+/// # Arguments
+///
+/// * `file_path_cfe` - The path of the compositions and free energies file.
+/// * `file_path_con` - The path of the concentrations file.
+/// * `parse_options` - How the inputs are laid out.
+///
+/// # Returns
+///
+/// The parsed and validated problem.
+pub fn read_problem(
+    file_path_cfe: &str,
+    file_path_con: &str,
+    parse_options: &ParseOptions,
+) -> Result<EquilibriumProblem, CoffeeError> {
+    let file_content_cfe = read_file(file_path_cfe)?;
+    let file_content_con = read_file(file_path_con)?;
+    EquilibriumProblem::from_bytes(&file_content_cfe, &file_content_con, parse_options)
+}
 
-    let initial_delta = 1.0;
-    let mut optimizer =
-        match Optimizer::new(&monomers, &polymers, &polymers_energies, optimizer_args) {
-            Ok(opt) => opt,
+pub fn run_coffee_server(cfe_bytes: &[u8], con_bytes: &[u8]) -> Result<String, CoffeeError> {
+    let args = OptimizerArgs {
+        use_terminal: true, // print to logs for websocket version
+        verbose: true,
+        ..Default::default()
+    };
+    let optimizer_results =
+        match run_coffee_computation(cfe_bytes, con_bytes, &ParseOptions::default(), &args) {
+            Ok(optimizer_results) => optimizer_results,
             Err(e) => {
-                return Err(format!("Failed to create optimizer: {}", e).into());
+                eprintln!("Error during optimization: {}", e);
+                return Err(e);
             }
         };
 
-    // Call the optimizer
-    if let Err(e) = optimizer.optimize(initial_delta) {
-        return Err(format!("Optimization failed: {}", e).into());
+    Ok(results_message(&optimizer_results))
+}
+
+/// Writes text to an output file and flushes it.
+///
+/// # Arguments
+///
+/// * `file` - The open output file.
+/// * `path` - The path of the file, used in errors.
+/// * `parts` - The text to write, in order.
+fn write_output<'a>(
+    file: &mut File,
+    path: &str,
+    parts: impl IntoIterator<Item = &'a str>,
+) -> Result<(), CoffeeError> {
+    for part in parts {
+        file.write_all(part.as_bytes())
+            .map_err(CoffeeError::io(path))?;
     }
+    file.flush().map_err(CoffeeError::io(path))
+}
 
-    Ok(optimizer.get_results())
+/// The files `run_coffee` writes to. Each output is skipped if its path isn't given, except the
+/// log, which goes to stdout instead.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OutputPaths<'a> {
+    /// The log, including the results.
+    pub log: Option<&'a str>,
+    /// Only the results.
+    pub results: Option<&'a str>,
+    /// The iteration history, comma-separated for .csv files and otherwise tab-separated.
+    pub history: Option<&'a str>,
+    /// How the results are written to `results` and stdout. The log always holds them as text.
+    pub results_format: OutputFormat,
 }
 
-pub fn run_coffee_server(cfe_bytes: &[u8], con_bytes: &[u8]) -> Result<String, Box<dyn Error>> {
-    let args = OptimizerArgs {
-        use_terminal: true, // print to logs for websocket version
-        verbose: true,
-        ..Default::default()
-    };
-    let optimizer_results = match run_coffee_computation(cfe_bytes, con_bytes, &args) {
-        Ok(optimizer_results) => optimizer_results,
-        Err(e) => {
-            eprintln!("Error during optimization: {}", e);
-            return Err(e);
-        }
-    };
+/// The open outputs of `OutputPaths`.
+struct OutputFiles<'a> {
+    log: Option<(&'a str, File)>,
+    results: Option<(&'a str, File)>,
+    history: Option<&'a str>,
+    results_format: OutputFormat,
+}
 
-    Ok(results_message(&optimizer_results))
+impl<'a> OutputFiles<'a> {
+    /// Creates the log and results files, truncating any that exist.
+    fn create(output_paths: &OutputPaths<'a>) -> Result<Self, CoffeeError> {
+        let mut log = None;
+        if let Some(log_path) = output_paths.log {
+            log = Some((
+                log_path,
+                File::create(log_path).map_err(CoffeeError::io(log_path))?,
+            ));
+        }
+        let mut results = None;
+        if let Some(out_path) = output_paths.results {
+            results = Some((
+                out_path,
+                File::create(out_path).map_err(CoffeeError::io(out_path))?,
+            ));
+        }
+        Ok(OutputFiles {
+            log,
+            results,
+            history: output_paths.history,
+            results_format: output_paths.results_format,
+        })
+    }
 }
 
 pub fn run_coffee(
     file_path_cfe: &str,
     file_path_con: &str,
-    file_path_log: Option<&str>,
-    file_path_out: Option<&str>,
+    output_paths: &OutputPaths,
+    parse_options: &ParseOptions,
     optimizer_args: &OptimizerArgs,
-) -> Result<String, Box<dyn Error>> {
+    observer: Option<&mut dyn Observer>,
+) -> Result<String, CoffeeError> {
     // Read the file contents
-    let mut file = File::open(file_path_cfe)?;
-    let mut file_content_cfe = Vec::new();
-    if let Err(e) = file.read_to_end(&mut file_content_cfe) {
-        return Err(format!("Error reading monomer/polymer file: {}", e).into());
-    }
+    let file_content_cfe = read_file(file_path_cfe)?;
+    let file_content_con = read_file(file_path_con)?;
+    let outputs = OutputFiles::create(output_paths)?;
 
-    file = File::open(file_path_con)?;
-    let mut file_content_con = Vec::new();
-    if let Err(e) = file.read_to_end(&mut file_content_con) {
-        return Err(format!("Error reading concentration file: {}", e).into());
+    /* Report the delimiter in verbose output. If it can't be detected, parsing reports why. */
+    let mut delimiter_log = None;
+    if optimizer_args.verbose {
+        let message = match parse_options.delimiter {
+            Delimiter::Auto => detect_delimiter(&file_content_cfe)
+                .ok()
+                .map(|delimiter| delimiter_message(delimiter, true)),
+            delimiter => Some(delimiter_message(delimiter, false)),
+        };
+        if optimizer_args.use_terminal {
+            print!("{}", message.unwrap_or_default());
+        } else {
+            delimiter_log = message;
+        }
     }
 
-    let mut log_file = None;
-    if let Some(log_path) = file_path_log {
-        log_file = Some(File::create(log_path)?);
-    }
-    let mut out_file = None;
-    if let Some(out_path) = file_path_out {
-        out_file = Some(File::create(out_path)?);
-    }
+    let problem =
+        EquilibriumProblem::from_bytes(&file_content_cfe, &file_content_con, parse_options);
+    solve_and_write(problem, optimizer_args, outputs, delimiter_log, observer)
+}
 
-    let optimizer_results =
-        match run_coffee_computation(&file_content_cfe, &file_content_con, optimizer_args) {
-            Ok(optimizer_results) => optimizer_results,
-            Err(e) => {
-                eprintln!("Error during optimization: {}", e);
-                return Err(e);
-            }
-        };
+/// Reads a JSON problem, which holds the strands, complexes and optionally the temperature
+/// and optimizer parameters, and solves it like `run_coffee`.
+///
+/// # Arguments
+///
+/// * `file_path_json` - The path of the JSON problem file.
+/// * `output_paths` - The files to write the log, results and history to.
+/// * `optimizer_args` - The optimizer parameters where the input doesn't set them.
+/// * `observer` - An observer of each iteration, if any.
+///
+/// # Returns
+///
+/// The results message.
+pub fn run_coffee_json(
+    file_path_json: &str,
+    output_paths: &OutputPaths,
+    optimizer_args: &OptimizerArgs,
+    observer: Option<&mut dyn Observer>,
+) -> Result<String, CoffeeError> {
+    let file_content = read_file(file_path_json)?;
+    let outputs = OutputFiles::create(output_paths)?;
+    match read_json(&file_content, optimizer_args) {
+        Ok(JsonProblem {
+            problem,
+            optimizer_args,
+        }) => solve_and_write(Ok(problem), &optimizer_args, outputs, None, observer),
+        Err(e) => solve_and_write(Err(e), optimizer_args, outputs, None, observer),
+    }
+}
 
-    let results_string = results_message(&optimizer_results);
+/// Solves a problem read by `run_coffee` or `run_coffee_json` and writes its outputs.
+///
+/// # Arguments
+///
+/// * `problem` - The problem, or the error reading it.
+/// * `optimizer_args` - The optimizer parameters to solve with.
+/// * `outputs` - The open outputs.
+/// * `delimiter_log` - The delimiter message to start the log file with, if any.
+/// * `observer` - An observer of each iteration, if any.
+///
+/// # Returns
+///
+/// The results message.
+fn solve_and_write(
+    problem: Result<EquilibriumProblem, CoffeeError>,
+    optimizer_args: &OptimizerArgs,
+    outputs: OutputFiles,
+    delimiter_log: Option<String>,
+    observer: Option<&mut dyn Observer>,
+) -> Result<String, CoffeeError> {
+    let OutputFiles {
+        log: mut log_file,
+        results: mut out_file,
+        history: history_path,
+        results_format,
+    } = outputs;
 
-    if let Some(ref mut log_file) = log_file {
-        for message in &optimizer_results.log_messages {
-            log_file.write_all(message.as_bytes())?;
+    /* Errors are returned for the caller to report. */
+    let optimizer_results = problem.and_then(|problem| {
+        for warning in problem.warnings() {
+            eprintln!("Warning: {}", warning);
         }
-        log_file.write_all(results_string.as_bytes())?;
-        log_file.flush()?;
-    } else {
+        match observer {
+            Some(observer) => problem.solve_with_observer(optimizer_args, observer),
+            None => problem.solve(optimizer_args),
+        }
+    })?;
+
+    let results_string = results_output(&optimizer_results, results_format);
+
+    if let Some((log_path, ref mut log_file)) = log_file {
+        let results_text = results_message(&optimizer_results);
+        let messages = delimiter_log
+            .iter()
+            .chain(optimizer_results.log_messages.iter())
+            .map(String::as_str);
+        write_output(log_file, log_path, messages.chain([results_text.as_str()]))?;
+    } else if results_format == OutputFormat::Text {
         println!("{}", results_string);
+    } else {
+        /* Machine-readable outputs already end with a line break. */
+        print!("{}", results_string);
     }
 
-    if let Some(ref mut out_file) = out_file {
-        out_file.write_all(results_string.as_bytes())?;
-        out_file.flush()?;
+    if let Some((out_path, ref mut out_file)) = out_file {
+        write_output(out_file, out_path, [results_string.as_str()])?;
     };
 
+    if let Some(history_path) = history_path {
+        /* Comma-separated for .csv files, otherwise tab-separated. */
+        let delimiter = if history_path.ends_with(".csv") {
+            ','
+        } else {
+            '\t'
+        };
+        let mut history_file = File::create(history_path).map_err(CoffeeError::io(history_path))?;
+        let table = history_table(&optimizer_results.history, delimiter);
+        write_output(&mut history_file, history_path, [table.as_str()])?;
+    }
+
     Ok(results_string)
 }
@@ -0,0 +1,222 @@
+use crate::composition::CompositionMatrix;
+use crate::hessian::{DualHessian, HessianOperator};
+use crate::parallel;
+use ndarray::{s, Array1, Array2, ArrayView1, Axis};
+
+/// The dual of the equilibrium problem, L(lambda) = ln(sum_j q_j exp(a_j . lambda) - lambda . x0),
+/// with one Lagrange multiplier per monomer. Its minimum gives the equilibrium concentrations
+/// x_j = q_j exp(a_j . lambda).
+///
+/// Everything is evaluated in log space from ln(q), so even very stable polymers don't
+/// overflow.
+pub struct DualObjective {
+    monomers: Array1<f64>,
+    polymers: CompositionMatrix,
+    polymers_log_q: Array1<f64>,
+    dense_hessian: bool,
+}
+
+/// The dual objective evaluated at one set of multipliers.
+pub struct DualPoint {
+    /// The Lagrange multipliers, one per monomer.
+    pub lambda: Array1<f64>,
+    /// The value of the objective, L.
+    pub value: f64,
+    /// The gradient of the objective, one entry per monomer.
+    pub gradient: Array1<f64>,
+    /// Each polymer's share of the objective, w = q exp(A lambda - L).
+    weights: Array1<f64>,
+}
+
+impl DualObjective {
+    /// Creates the objective for a composition matrix. The monomer concentrations and polymer
+    /// quantities start at zero and are set with `set_monomers` and `set_log_q`.
+    ///
+    /// # Arguments
+    ///
+    /// * `polymers` - The polymer composition matrix A (N x M).
+    /// * `dense_hessian` - Whether `hessian` builds the dense matrix or gives Hessian-vector
+    ///   products.
+    pub(crate) fn new(polymers: CompositionMatrix, dense_hessian: bool) -> Self {
+        DualObjective {
+            monomers: Array1::zeros(polymers.ncols()),
+            polymers_log_q: Array1::zeros(polymers.nrows()),
+            polymers,
+            dense_hessian,
+        }
+    }
+
+    /// Sets the monomer concentrations x0, already scaled to the units of the objective.
+    pub(crate) fn set_monomers(&mut self, monomers: Array1<f64>) {
+        self.monomers = monomers;
+    }
+
+    /// Sets the log polymer quantities, ln(q) = -dG / kT.
+    pub(crate) fn set_log_q(&mut self, polymers_log_q: Array1<f64>) {
+        self.polymers_log_q = polymers_log_q;
+    }
+
+    /// Returns the scaled monomer concentrations x0.
+    pub(crate) fn monomers(&self) -> &Array1<f64> {
+        &self.monomers
+    }
+
+    /// Returns the polymer composition matrix A.
+    pub(crate) fn polymers(&self) -> &CompositionMatrix {
+        &self.polymers
+    }
+
+    /// Returns the number of multipliers, M.
+    pub fn dim(&self) -> usize {
+        self.monomers.len()
+    }
+
+    /// Returns the log of each polymer's term in the objective, ln(q) + A lambda.
+    ///
+    /// # Arguments
+    ///
+    /// * `lambda` - The Lagrange multipliers.
+    ///
+    /// # Returns
+    ///
+    /// A 1-dimensional array with one exponent per polymer.
+    pub(crate) fn exponents(&self, lambda: ArrayView1<f64>) -> Array1<f64> {
+        self.polymers.dot(lambda) + &self.polymers_log_q
+    }
+
+    /// Calculates the objective from the polymer exponents.
+    ///
+    /// The objective is ln(sum_j exp(s_j) - lambda . x0) for the polymer exponents s. Every
+    /// term is shifted by the largest one before exponentiating (log-sum-exp), so it stays
+    /// finite however large the exponents are.
+    ///
+    /// # Arguments
+    ///
+    /// * `lambda` - The Lagrange multipliers.
+    /// * `exponents` - The polymer exponents from `exponents` at the same lambda.
+    ///
+    /// # Returns
+    ///
+    /// The objective, or NaN if lambda is outside the domain of the logarithm.
+    fn lagrangian(&self, lambda: ArrayView1<f64>, exponents: &Array1<f64>) -> f64 {
+        let initial = -lambda.dot(&self.monomers);
+        let largest = parallel::ordered_reduce(
+            exponents.len(),
+            f64::NEG_INFINITY,
+            |range| {
+                exponents
+                    .slice(s![range])
+                    .fold(f64::NEG_INFINITY, |a, &b| a.max(b))
+            },
+            |total, part| *total = total.max(part),
+        );
+        let shift = largest.max(initial.abs().ln());
+        let shifted_sum = parallel::ordered_reduce(
+            exponents.len(),
+            0.0,
+            |range| {
+                exponents
+                    .slice(s![range])
+                    .iter()
+                    .map(|v| (v - shift).exp())
+                    .sum::<f64>()
+            },
+            |total, part| *total += part,
+        );
+
+        shift + (shifted_sum + initial.signum() * (initial.abs().ln() - shift).exp()).ln()
+    }
+
+    /// Calculates the objective at the given multipliers, without its derivatives.
+    ///
+    /// # Arguments
+    ///
+    /// * `lambda` - The Lagrange multipliers.
+    ///
+    /// # Returns
+    ///
+    /// The objective, or NaN if lambda is outside the domain of the logarithm.
+    pub fn value(&self, lambda: ArrayView1<f64>) -> f64 {
+        self.lagrangian(lambda, &self.exponents(lambda))
+    }
+
+    /// Evaluates the objective and its gradient at the given multipliers. The gradient is
+    /// A^T w - x0 exp(-L) for the normalized polymer weights w = exp(s - L), each polymer's
+    /// share of the objective.
+    ///
+    /// # Arguments
+    ///
+    /// * `lambda` - The Lagrange multipliers.
+    ///
+    /// # Returns
+    ///
+    /// The evaluated point, which `hessian` can then use.
+    pub fn evaluate(&self, lambda: Array1<f64>) -> DualPoint {
+        let exponents = self.exponents(lambda.view());
+        let value = self.lagrangian(lambda.view(), &exponents);
+
+        let mut weights = exponents - value;
+        weights.par_mapv_inplace(f64::exp);
+
+        let initial = self
+            .monomers
+            .mapv(|c| if c > 0.0 { (c.ln() - value).exp() } else { 0.0 });
+        let gradient = self.polymers.t_dot(weights.view()) - initial;
+
+        DualPoint {
+            lambda,
+            value,
+            gradient,
+            weights,
+        }
+    }
+
+    /// Builds the dense Hessian at an evaluated point, A^T diag(w) A - g g^T.
+    ///
+    /// # Arguments
+    ///
+    /// * `point` - The point from `evaluate`.
+    ///
+    /// # Returns
+    ///
+    /// The M x M Hessian.
+    pub fn dense_hessian(&self, point: &DualPoint) -> Array2<f64> {
+        /* Weight each polymer's row of the composition matrix, reducing it to M x M. */
+        let second_part = self.polymers.weighted_gram(point.weights.view());
+
+        let fourth_part = point.gradient.view().insert_axis(Axis(1));
+
+        let fifth_part = point.gradient.view().insert_axis(Axis(0));
+
+        second_part - fourth_part.dot(&fifth_part)
+    }
+
+    /// Returns the Hessian at an evaluated point, either as the dense matrix or as an operator
+    /// giving Hessian-vector products, depending on how the objective was configured.
+    ///
+    /// # Arguments
+    ///
+    /// * `point` - The point from `evaluate`.
+    ///
+    /// # Returns
+    ///
+    /// The Hessian operator.
+    pub fn hessian<'a>(&'a self, point: &'a DualPoint) -> Box<dyn HessianOperator + 'a> {
+        if self.dense_hessian {
+            Box::new(self.dense_hessian(point))
+        } else {
+            Box::new(DualHessian::new(
+                &self.polymers,
+                point.weights.view(),
+                point.gradient.view(),
+            ))
+        }
+    }
+}
+
+impl DualPoint {
+    /// Returns each polymer's share of the objective, w = q exp(A lambda - L).
+    pub fn weights(&self) -> ArrayView1<'_, f64> {
+        self.weights.view()
+    }
+}
@@ -0,0 +1,111 @@
+use crate::extras::IterationRecord;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// What the optimizer should do after an observer has seen an iteration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ObserverAction {
+    /// Keep iterating.
+    Continue,
+    /// Stop now, ending the optimization as cancelled.
+    Stop,
+}
+
+/// The progress of an optimization after one iteration.
+#[derive(Clone, Copy, Debug)]
+pub struct IterationEvent<'a> {
+    /// The state after the iteration, as recorded in `OptimizerResults::history`.
+    pub record: &'a IterationRecord,
+    /// Time since the optimization started, in microseconds.
+    pub elapsed_us: usize,
+}
+
+/// Watches an optimization, getting an event after each iteration. Stopping has no effect after
+/// an iteration that converged or stalled. The optimizer runs on its thread pool, so observers
+/// must be `Send`.
+pub trait Observer: Send {
+    /// Called after each iteration.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The progress after the iteration.
+    ///
+    /// # Returns
+    ///
+    /// Whether to continue or to stop the optimization.
+    fn on_iteration(&mut self, event: &IterationEvent) -> ObserverAction;
+}
+
+/// Any closure taking an event can observe an optimization.
+impl<F> Observer for F
+where
+    F: FnMut(&IterationEvent) -> ObserverAction + Send,
+{
+    fn on_iteration(&mut self, event: &IterationEvent) -> ObserverAction {
+        self(event)
+    }
+}
+
+/// Stops an optimization once it has run for longer than a wall-clock budget. The budget is
+/// checked after each iteration, so the last iteration can run past it.
+pub struct TimeLimit {
+    limit_us: usize,
+}
+
+impl TimeLimit {
+    /// Creates a time limit.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - The longest time the optimization may run.
+    pub fn new(limit: Duration) -> Self {
+        TimeLimit {
+            limit_us: limit.as_micros().min(usize::MAX as u128) as usize,
+        }
+    }
+}
+
+impl Observer for TimeLimit {
+    fn on_iteration(&mut self, event: &IterationEvent) -> ObserverAction {
+        if event.elapsed_us >= self.limit_us {
+            ObserverAction::Stop
+        } else {
+            ObserverAction::Continue
+        }
+    }
+}
+
+/// Stops an optimization when cancelled from another thread. Clones share the same flag, so
+/// one clone can be given to the optimizer and another kept to call `cancel`.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// Creates a token that hasn't been cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels every optimization observing this token after its current iteration.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns whether the token has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+impl Observer for CancellationToken {
+    fn on_iteration(&mut self, _event: &IterationEvent) -> ObserverAction {
+        if self.is_cancelled() {
+            ObserverAction::Stop
+        } else {
+            ObserverAction::Continue
+        }
+    }
+}
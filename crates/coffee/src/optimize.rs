@@ -1,36 +1,59 @@
-use crate::extras::{OptimizerArgs, OptimizerError, OptimizerResults};
+use crate::composition::CompositionMatrix;
+use crate::energy::PolymerEnergies;
+use crate::error::CoffeeError;
+use crate::extras::{
+    HessianMode, InitialLambda, IterationRecord, Names, OptimizerArgs, OptimizerResults,
+    SubproblemOutcome, TerminationReason,
+};
 use crate::format::{conclude_message, process_message, start_message};
-use crate::steihaug::Steihaug;
+use crate::hessian::solve_positive_definite;
+use crate::objective::DualObjective;
+use crate::observer::{IterationEvent, Observer, ObserverAction};
+use crate::parallel::{install, thread_pool};
+use crate::problem::validate_dimensions;
+use crate::sensitivity::Sensitivities;
+use crate::solver::{solver_from_args, EquilibriumSolver, IterationOutcome};
 use chrono::Utc;
 use core::f64;
-use ndarray::{Array1, Array2, ArrayView1, Axis};
-use std::error::Error;
+use ndarray::{Array1, Array2};
+use rayon::ThreadPool;
+use std::sync::Arc;
 
 /// Cuts off values smaller than e^(this value) due to lack of precision in f64.
 const SMALLEST_EXP_VALUE: f64 = -230.0;
 
+/// Largest exponent whose exponential is still a finite f64.
+const LARGEST_EXP_VALUE: f64 = 709.0;
+
+/// Largest number of monomers for which `HessianMode::Auto` builds the dense Hessian.
+const AUTO_DENSE_HESSIAN_MAX_MONOMERS: usize = 32;
+
 pub struct Optimizer {
-    monomers: Array1<f64>,
-    polymers: Array2<f64>,
-    polymers_q: Array1<f64>,
+    initial_monomers: Array1<f64>,
+    objective: DualObjective,
+    polymers_energies: PolymerEnergies,
     max_iterations: usize,
     curr_iteration: usize,
     time_us: usize,
-    delta: f64,
-    max_delta: f64,
-    eta: f64,
-    norm_ratio_threshold: f64,
-    rho_thresholds: [f64; 2],
-    scale_factors: [f64; 2],
     optimal_lambda: Array1<f64>,
     optimal_x: Array1<f64>,
     optimal_lagrangian: f64,
-    steihaug_trust_region: Steihaug,
+    solver: Box<dyn EquilibriumSolver>,
     use_terminal: bool,
     verbose: bool,
     log_msgs: Vec<String>,
     scalarity: bool,
     temp_celsius: f64,
+    gradient_tolerance: f64,
+    constraint_tolerance: f64,
+    lagrangian_tolerance: f64,
+    initial_lambda: InitialLambda,
+    termination_reason: TerminationReason,
+    thread_pool: Option<Arc<ThreadPool>>,
+    clamped_values: usize,
+    subproblem_outcomes: Vec<SubproblemOutcome>,
+    history: Vec<IterationRecord>,
+    names: Names,
 }
 
 /// Caclulates the density of water at a given temperature.
@@ -58,8 +81,8 @@ fn density_water(t: f64) -> f64 {
 /// # Arguments
 ///
 /// * `monomers` - A reference to a 1-dimensional array of monomer concentrations.
-/// * `polymers` - A reference to a 2-dimensional array representing the polymer matrix.
-/// * `polymers_q` - A reference to a 1-dimensional array of polymer quantities.
+/// * `polymers` - The polymer composition matrix, stored as chosen by `matrix_storage`.
+/// * `polymers_q` - The polymer free energies, either fixed or temperature-dependent.
 /// * `optional_args` - An instance of `OptimizerArgs` containing optional parameters for the optimizer.
 ///
 /// # Returns
@@ -75,181 +98,210 @@ fn density_water(t: f64) -> f64 {
 impl Optimizer {
     pub fn new(
         monomers: &Array1<f64>,
-        polymers: &Array2<f64>,
-        polymers_q_nonexp: &Array1<f64>,
+        polymers: impl Into<CompositionMatrix>,
+        polymers_q_nonexp: impl Into<PolymerEnergies>,
         optional_args: &OptimizerArgs,
-    ) -> Result<Self, Box<dyn Error>> {
+    ) -> Result<Self, CoffeeError> {
+        let polymers = polymers.into();
+        let polymers_energies = polymers_q_nonexp.into();
         let num_monomers = monomers.len();
-        let num_polymers = polymers.len_of(Axis(0));
-
-        if num_monomers == 0 {
-            return Err(Box::new(OptimizerError(
-                "Monomers array is empty.".to_string(),
-            )));
-        }
-        if num_polymers == 0 {
-            return Err(Box::new(OptimizerError(
-                "Polymers array is empty.".to_string(),
-            )));
-        }
-        if num_polymers < num_monomers {
-            return Err(Box::new(OptimizerError(
-                "Number of polymers is less than number of monomers.".to_string(),
-            )));
-        }
+        let num_polymers = polymers.nrows();
 
         /* Check sizes between the arrays. */
-        if num_monomers != polymers.len_of(Axis(1)) {
-            return Err(Box::new(OptimizerError(
-                "Monomers and polymer compositions inconsistent.".to_string(),
-            )));
-        }
-        if num_polymers != polymers_q_nonexp.len() {
-            return Err(Box::new(OptimizerError(
-                "Polymers and polymer quantities have different sizes.".to_string(),
-            )));
-        }
+        validate_dimensions(
+            num_monomers,
+            (num_polymers, polymers.ncols()),
+            polymers_energies.len(),
+        )?;
+        polymers_energies.validate()?;
+        let polymers = polymers.with_storage(optional_args.matrix_storage)?;
 
-        /* Scale for water molecule volume size if necessary. */
-        let temp_celsius = optional_args.temp_celsius;
-        let scalarity = optional_args.scalarity;
-        let k_t = if scalarity {
-            0.00198717 * (temp_celsius + 273.15)
-        } else {
-            1.0
+        let dense_hessian = match optional_args.hessian_mode {
+            HessianMode::Auto => num_monomers <= AUTO_DENSE_HESSIAN_MAX_MONOMERS,
+            HessianMode::Dense => true,
+            HessianMode::MatrixFree => false,
         };
-        let scaled_monomers = if scalarity {
-            monomers / density_water(temp_celsius)
-        } else {
-            monomers.clone()
-        };
-        let polymers_q = polymers_q_nonexp.mapv(|x| (-x.max(SMALLEST_EXP_VALUE) / k_t).exp());
-
-        let max_iterations = optional_args.max_iterations;
-        Ok(Optimizer {
-            monomers: scaled_monomers,
-            polymers: polymers.clone(),
-            polymers_q,
-            max_iterations,
+        let mut optimizer = Optimizer {
+            initial_monomers: monomers.clone(),
+            objective: DualObjective::new(polymers, dense_hessian),
+            polymers_energies,
+            max_iterations: optional_args.max_iterations,
             curr_iteration: 0,
             time_us: 0,
-            delta: 1.0,
-            max_delta: optional_args.max_delta,
-            eta: optional_args.eta,
-            norm_ratio_threshold: optional_args.norm_ratio_threshold,
-            rho_thresholds: optional_args.rho_thresholds,
-            scale_factors: optional_args.scale_factors,
             optimal_lambda: Array1::zeros(num_monomers),
             optimal_x: Array1::zeros(num_polymers),
             optimal_lagrangian: 0.0,
-            steihaug_trust_region: Steihaug::new(max_iterations, num_monomers),
+            solver: solver_from_args(optional_args, num_monomers),
             use_terminal: optional_args.use_terminal,
             verbose: optional_args.verbose,
             log_msgs: Vec::new(),
-            scalarity,
-            temp_celsius,
-        })
+            scalarity: optional_args.scalarity,
+            temp_celsius: optional_args.temp_celsius,
+            gradient_tolerance: optional_args.gradient_tolerance,
+            constraint_tolerance: optional_args.constraint_tolerance,
+            lagrangian_tolerance: optional_args.lagrangian_tolerance,
+            initial_lambda: InitialLambda::Zero,
+            termination_reason: TerminationReason::MaxIterations,
+            thread_pool: thread_pool(optional_args.threads)?,
+            clamped_values: 0,
+            subproblem_outcomes: Vec::new(),
+            history: Vec::new(),
+            names: Names::default(),
+        };
+        optimizer.set_temperature(optional_args.temp_celsius)?;
+        optimizer.set_initial_lambda(optional_args.initial_lambda.clone())?;
+        Ok(optimizer)
     }
 
-    /// Calculates the norm of the given vector. Replaces ndarray-linalg crate's implementation
-    /// because it isn't working with WASM. Equivalent to `ndarray_linalg::Norm::norm2`, which is
-    /// the implementation of pythagorean theorem for N elements.
-    ///
-    /// # Arguments
-    ///
-    /// * `v` - A 1-dimensional array representing the vector to calculate the norm of.
-    ///
-    /// # Returns
-    ///
-    /// The norm of the vector.
-    fn norm(&self, v: ArrayView1<f64>) -> f64 {
-        v.iter().map(|&x| x * x).sum::<f64>().sqrt()
+    /// Scales the monomer concentrations for water molecule volume size if necessary.
+    fn scale_monomers(&mut self) {
+        self.objective.set_monomers(if self.scalarity {
+            &self.initial_monomers / density_water(self.temp_celsius)
+        } else {
+            self.initial_monomers.clone()
+        });
     }
 
-    /// Updates the optimal x values based on the current polymer lambdas and polymer quantities.
-    /// This is used to calculate the optimal concentrations of the polymers.
-    /// It also scales the values based on the temperature and whether scalarity is enabled.
-    /// No output is needed as it is automatically updated internally.
-    fn update_optimal_x(&mut self) {
+    /// Converts the polymer free energies at the current temperature into log polymer quantities,
+    /// ln(q) = -dG / kT. These are kept in log form, so even very stable polymers don't overflow.
+    fn scale_energies(&mut self) {
+        let k_t = self.k_t();
+        self.objective.set_log_q(
+            self.polymers_energies
+                .free_energies(self.temp_celsius)
+                .mapv(|x| -x / k_t),
+        );
+    }
+
+    /// Returns kT in kcal/mol at the current temperature, or 1 if the energies are already
+    /// unitless because scalarity is disabled.
+    fn k_t(&self) -> f64 {
         if self.scalarity {
-            self.optimal_x =
-                &self.polymers_q * &self.polymer_lambdas() * density_water(self.temp_celsius);
+            0.00198717 * (self.temp_celsius + 273.15)
         } else {
-            self.optimal_x = &self.polymers_q * &self.polymer_lambdas();
+            1.0
         }
     }
 
-    /// Returns the current polymer lambdas given the current monomer lambda values.
-    /// This is used to calculate the polymer quantities.
+    /// Sets the temperature for the next optimization, rescaling the monomer concentrations and
+    /// polymer quantities. Used to re-solve the same system at a new temperature.
     ///
-    /// # Returns
+    /// # Arguments
     ///
-    /// A 1-dimensional array of polymer lambdas in exponential form.
+    /// * `temp_celsius` - The new temperature in Celsius.
     ///
-    /// # Panics
+    /// # Returns
     ///
-    /// This function will panic if the non-exponentiated polymer concentrations are not finite.
-    /// This is to ensure that the optimization is working correctly.
-    fn polymer_lambdas(&self) -> Array1<f64> {
-        (self.polymers.dot(&self.optimal_lambda)).exp()
+    /// An error if the temperature is not finite or is below absolute zero.
+    pub fn set_temperature(&mut self, temp_celsius: f64) -> Result<(), CoffeeError> {
+        if !temp_celsius.is_finite() || temp_celsius <= -273.15 {
+            return Err(CoffeeError::InvalidInput(format!(
+                "Temperature {} C is not valid.",
+                temp_celsius
+            )));
+        }
+        self.temp_celsius = temp_celsius;
+        self.scale_monomers();
+        self.scale_energies();
+        Ok(())
     }
 
-    /// Calculates the Lagrangian of the optimization using the current lambda and also:
-    /// - The polymer quantities.
-    /// - The polymer energies.
-    /// - The monomer concentrations.
+    /// Replaces the initial monomer concentrations for the next optimization, keeping the
+    /// polymers and their energies. Used to re-solve the same system at new concentrations.
     ///
-    /// # Returns
+    /// # Arguments
     ///
-    /// The Lagrangian scalar value.
+    /// * `monomers` - The new monomer concentrations, one per monomer.
     ///
-    /// # Panics
+    /// # Returns
     ///
-    /// This function will panic if the Lagrangian value is not finite.
-    /// This is to ensure that the optimization is working correctly.
-    fn lagrangian(&self, polymer_lambdas: &Array1<f64>) -> f64 {
-        let after_energies = self.polymers_q.dot(polymer_lambdas);
-        let after_initial = self.optimal_lambda.dot(&self.monomers);
-
-        (after_energies - after_initial).ln()
+    /// An error if the number of concentrations doesn't match the number of monomers.
+    pub fn set_monomers(&mut self, monomers: &Array1<f64>) -> Result<(), CoffeeError> {
+        if monomers.len() != self.objective.dim() {
+            return Err(CoffeeError::MonomerCountMismatch {
+                monomers: monomers.len(),
+                columns: self.objective.dim(),
+            });
+        }
+        self.initial_monomers = monomers.clone();
+        self.scale_monomers();
+        Ok(())
     }
 
-    fn jacobian(&self, polymer_lambdas: &Array1<f64>, lagrangian: f64) -> Array1<f64> {
-        let after_energies = &self.polymers_q * polymer_lambdas;
-        let jacobian = self.polymers.t().dot(&after_energies) - &self.monomers;
-        jacobian / lagrangian.exp()
+    /// Sets the names the results label the monomers and polymers with.
+    ///
+    /// # Arguments
+    ///
+    /// * `names` - The names, with one per monomer and polymer if given.
+    ///
+    /// # Returns
+    ///
+    /// An error if the names don't match the problem's sizes, or are empty or repeated.
+    pub fn set_names(&mut self, names: Names) -> Result<(), CoffeeError> {
+        names.validate(self.objective.dim(), self.objective.polymers().nrows())?;
+        self.names = names;
+        Ok(())
     }
 
-    /// Calculates the Hessian of the optimization using the current lambda and also:
-    /// - The polymer quantities.
-    /// - The polymer energies.
-    /// - The monomer concentrations.
+    /// Sets the starting point for the Lagrange multipliers of the next optimization.
+    ///
+    /// # Arguments
+    ///
+    /// * `initial_lambda` - The starting point to use.
     ///
     /// # Returns
     ///
-    /// A 2-dimensional array representing the Hessian. Its size is M x M, where M is the number of monomers.
-    fn hessian(
-        &self,
-        polymer_lambdas: &Array1<f64>,
-        lagrangian: f64,
-        jacobian: &Array1<f64>,
-    ) -> Array2<f64> {
-        let first_part = 1. / lagrangian.exp();
-
-        let after_energies = &self.polymers_q * polymer_lambdas;
-        /* Truncate it to M x M, and element wise multiply with the polymers matrix. */
-        let polymerization = &self.polymers * &after_energies.insert_axis(Axis(1));
-
-        let second_part = self.polymers.t().dot(&polymerization);
-
-        let fourth_part = jacobian.view().insert_axis(Axis(1));
+    /// An error if given multipliers don't have one finite entry per monomer.
+    pub fn set_initial_lambda(&mut self, initial_lambda: InitialLambda) -> Result<(), CoffeeError> {
+        if let InitialLambda::Given(lambda) = &initial_lambda {
+            if lambda.len() != self.objective.dim() {
+                return Err(CoffeeError::InvalidInput(format!(
+                    "Initial lambda has {} values, expected one per monomer ({}).",
+                    lambda.len(),
+                    self.objective.dim()
+                )));
+            }
+            if lambda.iter().any(|l| !l.is_finite()) {
+                return Err(CoffeeError::InvalidInput(
+                    "Initial lambda values must be finite.".to_string(),
+                ));
+            }
+        }
+        self.initial_lambda = initial_lambda;
+        Ok(())
+    }
 
-        let fifth_part = jacobian.view().insert_axis(Axis(0));
+    /// Returns the starting Lagrange multipliers for the configured `InitialLambda`.
+    /// The heuristic start is the log of the scaled monomer concentrations, cut off at
+    /// `SMALLEST_EXP_VALUE` for monomers with no concentration.
+    fn starting_lambda(&self) -> Array1<f64> {
+        match &self.initial_lambda {
+            InitialLambda::Zero => Array1::zeros(self.objective.dim()),
+            InitialLambda::Heuristic => self
+                .objective
+                .monomers()
+                .mapv(|c| c.ln().max(SMALLEST_EXP_VALUE)),
+            InitialLambda::Given(lambda) => Array1::from_vec(lambda.clone()),
+        }
+    }
 
-        first_part * second_part - fourth_part.dot(&fifth_part)
+    /// Updates the optimal x values based on the current polymer lambdas and polymer quantities.
+    /// This is used to calculate the optimal concentrations of the polymers.
+    /// It also scales the values based on the temperature and whether scalarity is enabled.
+    /// Concentrations too large for an f64 are clamped to the largest finite value, and counted.
+    /// No output is needed as it is automatically updated internally.
+    fn update_optimal_x(&mut self) {
+        let mut exponents = self.objective.exponents(self.optimal_lambda.view());
+        if self.scalarity {
+            exponents += density_water(self.temp_celsius).ln();
+        }
+        self.clamped_values = exponents.iter().filter(|&&v| v > LARGEST_EXP_VALUE).count();
+        exponents.par_mapv_inplace(|v| v.min(LARGEST_EXP_VALUE).exp());
+        self.optimal_x = exponents;
     }
 
-    /// Optimizes the given function using the Steihaug trust region method.
+    /// Optimizes the given function using the solver selected in `OptimizerArgs`, by default
+    /// the Steihaug trust region method.
     /// Requires an initial delta value to start the optimization.
     /// Initialized with the monomer concentrations, exponentiated polymer energies, and the polymer quantities.
     ///
@@ -259,113 +311,160 @@ impl Optimizer {
     ///
     /// # Returns
     ///
-    /// A 1-dimensional array representing the optimal x values. Its size is N, where N is the number of polymers.
+    /// Whether one of the convergence tests was satisfied. The termination reason and the optimal
+    /// values are available through `get_results`. An error if the objective is not finite or the
+    /// solver couldn't compute a step.
+    pub fn optimize(&mut self, initial_delta: f64) -> Result<bool, CoffeeError> {
+        self.optimize_with_observer(initial_delta, &mut |_: &IterationEvent| {
+            ObserverAction::Continue
+        })
+    }
+
+    /// Optimizes like `optimize`, reporting each iteration to an observer, which can stop the
+    /// optimization early, e.g. to cancel it from another thread or to enforce a time limit.
+    /// A stopped optimization ends with `TerminationReason::Cancelled` and keeps the results
+    /// of its last iteration.
+    ///
+    /// # Arguments
     ///
-    /// # Panics
+    /// * `initial_delta` - The initial delta value to start the optimization.
+    /// * `observer` - The observer to call after each iteration.
     ///
-    /// This function will panic if the calculations are not finite.
-    /// This is to ensure that the optimization is working correctly.
-    pub fn optimize(&mut self, initial_delta: f64) -> Result<bool, Box<dyn Error>> {
+    /// # Returns
+    ///
+    /// Whether one of the convergence tests was satisfied. The termination reason and the optimal
+    /// values are available through `get_results`.
+    pub fn optimize_with_observer(
+        &mut self,
+        initial_delta: f64,
+        observer: &mut dyn Observer,
+    ) -> Result<bool, CoffeeError> {
+        /* Run on the configured thread pool, so the parallel products inside use it too. */
+        let thread_pool = self.thread_pool.clone();
+        install(thread_pool.as_deref(), || {
+            self.run_optimization(initial_delta, observer)
+        })
+    }
+
+    /// Runs the optimization loop of `optimize` on the current thread pool.
+    fn run_optimization(
+        &mut self,
+        initial_delta: f64,
+        observer: &mut dyn Observer,
+    ) -> Result<bool, CoffeeError> {
         /* Error Check for delta value. */
         if initial_delta <= 0.0 || !initial_delta.is_finite() {
-            return Err(Box::new(OptimizerError(
+            return Err(CoffeeError::InvalidInput(
                 "Initial delta value is not valid.".to_string(),
-            )));
+            ));
         }
 
         self.print(&start_message());
 
         /* Initialization and resetting from previous optimizations. */
-        self.delta = initial_delta;
-        let mut final_it = 0;
         self.reset();
+        self.solver.reset(initial_delta);
         let start_time = Utc::now();
+        let mut termination_reason = TerminationReason::MaxIterations;
 
         /* Start of optimization. */
         for it in 0..self.max_iterations {
-            /* Calculate mathematical values to generate predictions for changes. */
-            let polymer_lambdas = self.polymer_lambdas();
-            self.optimal_lagrangian = self.lagrangian(&polymer_lambdas);
-
-            let function = self.optimal_lagrangian;
-            let gradient = self.jacobian(&polymer_lambdas, self.optimal_lagrangian);
-            let hessian = self.hessian(&polymer_lambdas, self.optimal_lagrangian, &gradient);
-
-            /* Get miscellaneous difference values for the steihaug calculation. */
-            let step = self.norm(gradient.view());
-            let epsilon = step.sqrt().min(0.5f64) * step;
-
-            /* Find predicted next step through the steihaug trust method. */
-            let success = self
-                .steihaug_trust_region
-                .iterate(&gradient, &hessian, epsilon, self.delta);
-            if !success {
-                /* Conclude the optimization prematurely as it failed. */
-                self.time_us = (Utc::now() - start_time)
-                    .num_microseconds()
-                    .unwrap_or_default() as usize;
-                self.print(&conclude_message(
-                    it,
-                    success,
-                    self.time_us,
-                    self.verbose,
-                    None,
-                ));
-
-                return Err(Box::new(OptimizerError(
-                    "The Steihaug optimization did not succeed".to_string(),
-                )));
-            }
-            let update_step = self.steihaug_trust_region.get_result();
-
-            /* Pre-emptively update optimal lambdas and their math calcs to find whether reduction is accurate. */
-            self.optimal_lambda = &self.optimal_lambda + &update_step;
-            self.optimal_lagrangian = self.lagrangian(&self.polymer_lambdas());
+            /* Calculate the objective and its gradient at the current multipliers. */
+            let point = self.objective.evaluate(self.optimal_lambda.clone());
+            self.optimal_lagrangian = point.value;
 
-            /* Find predicted and actual reductions to see how significant the optimizing change is. */
-            let pred_reduction =
-                -(gradient.dot(&update_step) + 0.5 * update_step.dot(&hessian.dot(&update_step)));
-            let actual_reduction = function - self.optimal_lagrangian;
-
-            /* No more optimization is needed as there is no optimizing change. */
-            if actual_reduction == 0.0 {
-                final_it = it;
+            /* Stop before stepping if the gradient is already small enough. */
+            let step = point.gradient.iter().map(|&x| x * x).sum::<f64>().sqrt();
+            if step < self.gradient_tolerance {
+                termination_reason = TerminationReason::Converged;
                 break;
             }
 
-            /* Ratio calculation to determine next iteration's parameters. */
-            let rho = if pred_reduction != 0.0 {
-                actual_reduction / pred_reduction
+            /* Find the next multipliers through the configured solver. A non-finite objective
+             * means the multipliers are outside its domain, so no step can be found from them. */
+            let iteration = if point.value.is_finite() {
+                self.solver.iterate(&self.objective, &point)
             } else {
-                0.0
+                Err(CoffeeError::NonFiniteObjective { iteration: it })
             };
+            let iteration = match iteration {
+                Ok(iteration) => iteration,
+                Err(err) => {
+                    /* Conclude the optimization prematurely as it failed. */
+                    self.termination_reason = TerminationReason::SubproblemFailed;
+                    self.time_us = (Utc::now() - start_time)
+                        .num_microseconds()
+                        .unwrap_or_default() as usize;
+                    self.print(&conclude_message(
+                        self.curr_iteration,
+                        self.termination_reason,
+                        self.time_us,
+                        self.verbose,
+                        None,
+                    ));
 
-            /* Change delta based on whether reductions is too small or too high. */
-            if rho < self.rho_thresholds[0] {
-                /* Actual reduction is much less than predicted --> scale down delta param. */
-                self.delta *= self.scale_factors[0];
-            } else if rho > self.rho_thresholds[1]
-                && self.norm(update_step.view()) >= self.norm_ratio_threshold * self.delta
-            {
-                /* Actual reduction is close to predicted --> scale up delta param up to a point. */
-                self.delta = self.max_delta.min(self.scale_factors[1] * self.delta);
-            }
-
-            /* Actual reduction is scary less than predicted --> can't trust steihaug update value. */
-            if rho <= self.eta {
-                /* Remove the update from lambda if quadratic isn't reliable. Update relevant values for debugging. */
-                self.optimal_lambda = &self.optimal_lambda - &update_step;
-                self.optimal_lagrangian = self.lagrangian(&self.polymer_lambdas());
-            }
+                    return Err(err);
+                }
+            };
+            self.optimal_lambda = iteration.lambda;
+            self.optimal_lagrangian = iteration.value;
+            self.subproblem_outcomes.extend(iteration.subproblem);
 
             /* Calculate backtrack (error) by updating optimal_x to latest vals. */
             self.update_optimal_x();
-            self.print(&process_message(it, self.optimal_lagrangian, self.error()));
+            let error = self.error();
+            self.history.push(IterationRecord {
+                iteration: it,
+                objective: self.optimal_lagrangian,
+                gradient_norm: step,
+                constraint_error: error,
+                delta: iteration.delta,
+                rho: iteration.rho,
+                accepted: iteration.outcome == IterationOutcome::Accepted,
+                inner_iterations: iteration.subproblem.map_or(0, |s| s.inner_iterations),
+            });
 
             /* Update iteration. */
-            final_it = it;
             self.curr_iteration += 1;
+
+            /* Report the iteration. The convergence tests still take precedence over a stop. */
+            let event = IterationEvent {
+                record: &self.history[self.history.len() - 1],
+                elapsed_us: (Utc::now() - start_time)
+                    .num_microseconds()
+                    .unwrap_or_default() as usize,
+            };
+            let action = observer.on_iteration(&event);
+
+            /* No more optimization is possible as there is no optimizing change. */
+            if iteration.outcome == IterationOutcome::Stalled {
+                termination_reason = if error < self.constraint_tolerance {
+                    TerminationReason::Converged
+                } else {
+                    TerminationReason::Stalled
+                };
+                break;
+            }
+            self.print(&process_message(it, self.optimal_lagrangian, error));
+
+            /* Check the remaining convergence tests against the latest values. */
+            let relative_change =
+                (point.value - self.optimal_lagrangian).abs() / point.value.abs().max(1.0);
+            if error < self.constraint_tolerance
+                || (iteration.outcome == IterationOutcome::Accepted
+                    && relative_change < self.lagrangian_tolerance)
+            {
+                termination_reason = TerminationReason::Converged;
+                break;
+            }
+
+            /* Otherwise stop if the observer asked to. */
+            if action == ObserverAction::Stop {
+                termination_reason = TerminationReason::Cancelled;
+                break;
+            }
         }
+        self.termination_reason = termination_reason;
 
         /* Find the optimal concentrations. */
         self.update_optimal_x();
@@ -376,32 +475,39 @@ impl Optimizer {
             .unwrap_or_default() as usize;
 
         self.print(&conclude_message(
-            final_it,
-            true,
+            self.curr_iteration,
+            self.termination_reason,
             self.time_us,
             self.verbose,
-            Some(&OptimizerResults {
-                optimal_x: self.optimal_x.to_vec(),
-                optimal_lagrangian: self.optimal_lagrangian,
-                optimal_lambda: self.optimal_lambda.to_vec(),
-                concentration_error: self.error(),
-                log_messages: self.log_msgs.clone(),
-                elapsed_time: self.time_us,
-            }),
+            Some(&self.get_results()),
         ));
 
-        Ok(true)
+        Ok(self.termination_reason.is_converged())
+    }
+
+    /// Replaces the solver used by the next optimization, e.g. with a custom
+    /// `EquilibriumSolver` that isn't selectable through `OptimizerArgs`.
+    ///
+    /// # Arguments
+    ///
+    /// * `solver` - The solver to use.
+    pub fn set_solver(&mut self, solver: Box<dyn EquilibriumSolver>) {
+        self.solver = solver;
     }
 
     /// Resets the optimizer to its initial state.
     /// This is useful when reusing the optimizer for multiple optimizations.
-    /// It resets the lambda values and the x values.
+    /// It resets the lambda values to the configured starting point and the x values.
     pub fn reset(&mut self) {
         self.curr_iteration = 0;
         self.time_us = 0;
-        self.optimal_lambda.fill(0.);
+        self.optimal_lambda = self.starting_lambda();
         self.optimal_x.fill(0.);
         self.optimal_lagrangian = 0.0;
+        self.clamped_values = 0;
+        self.subproblem_outcomes.clear();
+        self.history.clear();
+        self.termination_reason = TerminationReason::MaxIterations;
         self.log_msgs.clear();
     }
 
@@ -412,16 +518,89 @@ impl Optimizer {
     ///
     /// (Optimal X Values, Optimal Lagrangian, Optimal Lambda Values)
     pub fn get_results(&self) -> OptimizerResults {
+        let optimal_x = self.optimal_x.to_vec();
         OptimizerResults {
-            optimal_x: self.optimal_x.to_vec(),
+            free_monomers: self.objective.polymers().free_monomers(&optimal_x),
+            optimal_x,
             optimal_lagrangian: self.optimal_lagrangian,
             optimal_lambda: self.optimal_lambda.to_vec(),
-            concentration_error: self.error(),
+            concentration_error: install(self.thread_pool.as_deref(), || self.error()),
             log_messages: self.log_msgs.clone(),
             elapsed_time: self.time_us,
+            termination_reason: self.termination_reason,
+            iterations: self.curr_iteration,
+            clamped_values: self.clamped_values,
+            subproblem_outcomes: self.subproblem_outcomes.clone(),
+            history: self.history.clone(),
+            names: self.names.clone(),
         }
     }
 
+    /// Calculates the sensitivities of the selected polymers' concentrations at the current
+    /// lambda, using the Hessian of the dual objective there. Only defined once `optimize`
+    /// has converged.
+    ///
+    /// # Arguments
+    ///
+    /// * `polymers` - The 0-based indices of the polymers to find the sensitivities of.
+    ///
+    /// # Returns
+    ///
+    /// The sensitivities, or an error if the last optimization didn't converge, a polymer index
+    /// is out of range or the Hessian is singular.
+    pub fn sensitivities(&self, polymers: &[usize]) -> Result<Sensitivities, CoffeeError> {
+        if !self.termination_reason.is_converged() {
+            return Err(CoffeeError::NotConverged {
+                reason: self.termination_reason,
+            });
+        }
+        let polymers_matrix = self.objective.polymers();
+        let num_polymers = polymers_matrix.nrows();
+        let num_monomers = self.objective.dim();
+        if let Some(&index) = polymers.iter().find(|&&j| j >= num_polymers) {
+            return Err(CoffeeError::InvalidInput(format!(
+                "Polymer {} is out of range for {} polymers.",
+                index, num_polymers
+            )));
+        }
+
+        install(self.thread_pool.as_deref(), || {
+            let point = self.objective.evaluate(self.optimal_lambda.clone());
+            let hessian = self.objective.dense_hessian(&point);
+            let weights = point.weights();
+
+            /* Solve H u_j = a_j for each selected polymer. With x = exp(L) w the scale of the
+             * weights cancels, so d x_j / d x0 = w_j u_j and
+             * d ln x_j / d dG_k = (w_k a_k . u_j - [j = k]) / kT. */
+            let mut compositions = Array2::zeros((num_monomers, polymers.len()));
+            for (column, &j) in polymers.iter().enumerate() {
+                compositions
+                    .column_mut(column)
+                    .assign(&polymers_matrix.row(j));
+            }
+            let solutions = solve_positive_definite(&hessian, &compositions)?;
+
+            let k_t = self.k_t();
+            let mut concentration = Array2::zeros((polymers.len(), num_monomers));
+            let mut energy = Array2::zeros((polymers.len(), num_polymers));
+            for (row, &j) in polymers.iter().enumerate() {
+                let solution = solutions.column(row);
+                concentration.row_mut(row).assign(&(weights[j] * &solution));
+
+                let mut response = polymers_matrix.dot(solution) * weights / k_t;
+                response[j] -= 1.0 / k_t;
+                energy.row_mut(row).assign(&response);
+            }
+
+            Ok(Sensitivities {
+                polymers: polymers.to_vec(),
+                concentration,
+                energy,
+                names: self.names.clone(),
+            })
+        })
+    }
+
     fn print(&mut self, msg: &str) {
         if self.use_terminal {
             print!("{}", msg);
@@ -450,16 +629,13 @@ impl Optimizer {
     /// A f64 value representing the maximum error .
     /// This is the maximum difference between the monomer concentrations and the polymer concentrations.
     fn error(&self) -> f64 {
-        let concs = self
-            .polymers
-            .t()
-            .dot(&self.optimal_x.view().insert_axis(Axis(1)));
+        let concs = self.objective.polymers().t_dot(self.optimal_x.view());
         let scaling = if self.scalarity {
             density_water(self.temp_celsius)
         } else {
             1.0
         };
-        let backtrack = (&self.monomers * scaling).insert_axis(Axis(1)) - concs;
+        let backtrack = self.objective.monomers() * scaling - concs;
         backtrack
             .iter()
             .fold(f64::NEG_INFINITY, |a, &b| f64::max(a, b.abs()))
@@ -469,7 +645,13 @@ impl Optimizer {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::extras::{SolverKind, SubproblemKind};
+    use crate::objective::DualPoint;
+    use crate::observer::{CancellationToken, TimeLimit};
+    use crate::problem::{dimer_problem, three_strand_problem};
+    use crate::solver::{Iteration, NewtonLineSearch};
     use ndarray::array;
+    use std::time::Duration;
 
     #[test]
     fn test_wrong_size_params() {
@@ -498,4 +680,443 @@ mod tests {
         let result = Optimizer::new(&monomers, &polymers, &polymers_q, &args);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_termination_reason() {
+        let problem = dimer_problem(-20.0);
+
+        /* Default tolerances converge well within the iteration limit. */
+        let args = OptimizerArgs {
+            use_terminal: false,
+            ..Default::default()
+        };
+        let mut optimizer = problem.optimizer(&args).unwrap();
+        assert!(optimizer.optimize(1.0).unwrap());
+        let results = optimizer.get_results();
+        assert_eq!(results.termination_reason, TerminationReason::Converged);
+        assert!(results.iterations > 0 && results.iterations < args.max_iterations);
+
+        /* A single iteration is not enough to converge. */
+        let args = OptimizerArgs {
+            use_terminal: false,
+            max_iterations: 1,
+            ..Default::default()
+        };
+        let mut optimizer = problem.optimizer(&args).unwrap();
+        assert!(!optimizer.optimize(1.0).unwrap());
+        let results = optimizer.get_results();
+        assert_eq!(results.termination_reason, TerminationReason::MaxIterations);
+        assert_eq!(results.iterations, 1);
+    }
+
+    /// A solver that never moves the multipliers, reporting every iteration as stalled.
+    struct StalledSolver;
+
+    impl EquilibriumSolver for StalledSolver {
+        fn reset(&mut self, _initial_delta: f64) {}
+
+        fn iterate(
+            &mut self,
+            _objective: &DualObjective,
+            point: &DualPoint,
+        ) -> Result<Iteration, CoffeeError> {
+            Ok(Iteration {
+                lambda: point.lambda.clone(),
+                value: point.value,
+                outcome: IterationOutcome::Stalled,
+                subproblem: None,
+                delta: None,
+                rho: None,
+            })
+        }
+    }
+
+    #[test]
+    fn test_stalled_termination() {
+        let problem = dimer_problem(-20.0);
+        let args = OptimizerArgs {
+            use_terminal: false,
+            gradient_tolerance: 0.0,
+            ..Default::default()
+        };
+        let mut optimizer = problem.optimizer(&args).unwrap();
+        assert!(optimizer.optimize(1.0).unwrap());
+        let optimum = optimizer.get_results().optimal_lambda;
+
+        /* A stall away from the optimum leaves the constraints unmet. */
+        optimizer.set_solver(Box::new(StalledSolver));
+        assert!(!optimizer.optimize(1.0).unwrap());
+        let results = optimizer.get_results();
+        assert_eq!(results.termination_reason, TerminationReason::Stalled);
+        assert_eq!(results.iterations, 1);
+        assert!(results.concentration_error >= args.constraint_tolerance);
+
+        /* A stall that already meets the constraint tolerance counts as converged. */
+        optimizer
+            .set_initial_lambda(InitialLambda::Given(optimum))
+            .unwrap();
+        assert!(optimizer.optimize(1.0).unwrap());
+        let results = optimizer.get_results();
+        assert_eq!(results.termination_reason, TerminationReason::Converged);
+        assert_eq!(results.iterations, 1);
+        assert!(results.concentration_error < args.constraint_tolerance);
+    }
+
+    #[test]
+    fn test_warm_start() {
+        let problem = dimer_problem(-20.0);
+        let args = OptimizerArgs {
+            use_terminal: false,
+            ..Default::default()
+        };
+        let mut optimizer = problem.optimizer(&args).unwrap();
+        optimizer.optimize(1.0).unwrap();
+        let cold = optimizer.get_results();
+
+        /* Starting from the previous optimum needs far fewer iterations. */
+        optimizer
+            .set_initial_lambda(InitialLambda::Given(cold.optimal_lambda.clone()))
+            .unwrap();
+        optimizer.optimize(1.0).unwrap();
+        let warm = optimizer.get_results();
+        assert_eq!(warm.termination_reason, TerminationReason::Converged);
+        assert!(warm.iterations < cold.iterations);
+
+        /* Given multipliers must match the number of monomers. */
+        assert!(optimizer
+            .set_initial_lambda(InitialLambda::Given(vec![0.0]))
+            .is_err());
+        let args = OptimizerArgs {
+            initial_lambda: InitialLambda::Given(vec![0.0, f64::NAN]),
+            ..args
+        };
+        assert!(problem.optimizer(&args).is_err());
+    }
+
+    #[test]
+    fn test_non_finite_objective() {
+        /* A very unstable dimer makes lambda . x0 outweigh the polymer terms, leaving the
+         * starting multipliers outside the domain of the logarithm. */
+        let monomers = array![1.0e-6];
+        let polymers = array![[2.0]];
+        let polymers_q = array![100.0];
+        let args = OptimizerArgs {
+            use_terminal: false,
+            initial_lambda: InitialLambda::Given(vec![1.0]),
+            ..Default::default()
+        };
+        let mut optimizer = Optimizer::new(&monomers, &polymers, &polymers_q, &args).unwrap();
+
+        let result = optimizer.optimize(1.0);
+        assert!(matches!(
+            result,
+            Err(CoffeeError::NonFiniteObjective { iteration: 0 })
+        ));
+        assert_eq!(
+            optimizer.get_results().termination_reason,
+            TerminationReason::SubproblemFailed
+        );
+    }
+
+    #[test]
+    fn test_extreme_energies() {
+        let problem = dimer_problem(-2000.0);
+        let args = OptimizerArgs {
+            use_terminal: false,
+            ..Default::default()
+        };
+
+        /* exp(-dG/kT) overflows an f64 here, but the log-space objective stays finite. */
+        let mut optimizer = problem.optimizer(&args).unwrap();
+        assert!(optimizer.optimize(1.0).unwrap());
+        let results = optimizer.get_results();
+        assert_eq!(results.clamped_values, 0);
+        assert!(results.optimal_lagrangian.is_finite());
+        assert!(results.optimal_x.iter().all(|x| x.is_finite()));
+        assert!((results.optimal_x[2] - 1.0e-6).abs() < 1.0e-12);
+    }
+
+    #[test]
+    fn test_energies_below_old_clamp() {
+        /* Energies used to be clamped at -230 kcal/mol, which left the free monomers about
+         * exp(5 / kT) times too high for this dimer. */
+        let energy = -240.0;
+        let args = OptimizerArgs {
+            use_terminal: false,
+            ..Default::default()
+        };
+        let mut optimizer = dimer_problem(energy).optimizer(&args).unwrap();
+        assert!(optimizer.optimize(1.0).unwrap());
+        let results = optimizer.get_results();
+
+        /* With mole fractions a = b free and d = K a b bound, a solves K a^2 + a - c = 0. */
+        let density = density_water(args.temp_celsius);
+        let k = (-energy / optimizer.k_t()).exp();
+        let c = 1.0e-6 / density;
+        let free = 2.0 * c / (1.0 + (1.0 + 4.0 * k * c).sqrt()) * density;
+        for &monomer in &results.free_monomers {
+            assert!((monomer - free).abs() < 1.0e-6 * free);
+        }
+        assert!((results.optimal_x[2] - (1.0e-6 - free)).abs() < 1.0e-12);
+    }
+
+    #[test]
+    fn test_hessian_modes() {
+        let problem = three_strand_problem([-12.0, -11.0, -25.0]);
+
+        /* Both representations of the Hessian reach the same optimum. */
+        let mut results = Vec::new();
+        for hessian_mode in [HessianMode::Dense, HessianMode::MatrixFree] {
+            let args = OptimizerArgs {
+                use_terminal: false,
+                hessian_mode,
+                ..Default::default()
+            };
+            let mut optimizer = problem.optimizer(&args).unwrap();
+            assert!(optimizer.optimize(1.0).unwrap());
+            results.push(optimizer.get_results());
+        }
+        for (dense, matrix_free) in results[0].optimal_x.iter().zip(&results[1].optimal_x) {
+            assert!((dense - matrix_free).abs() <= 1.0e-9 * dense.abs().max(1.0e-15));
+        }
+    }
+
+    #[test]
+    fn test_solver_kinds() {
+        let problem = three_strand_problem([-12.0, -11.0, -25.0]);
+
+        /* Every backend fills the same results and reaches the same optimum. */
+        let mut results = Vec::new();
+        for (solver, subproblem) in [
+            (SolverKind::TrustRegion, SubproblemKind::Steihaug),
+            (SolverKind::TrustRegion, SubproblemKind::Dogleg),
+            (SolverKind::NewtonLineSearch, SubproblemKind::Steihaug),
+        ] {
+            let args = OptimizerArgs {
+                use_terminal: false,
+                solver,
+                subproblem,
+                ..Default::default()
+            };
+            let mut optimizer = problem.optimizer(&args).unwrap();
+            assert!(optimizer.optimize(1.0).unwrap());
+            results.push(optimizer.get_results());
+        }
+
+        /* A solver can also be given directly. */
+        let args = OptimizerArgs {
+            use_terminal: false,
+            ..Default::default()
+        };
+        let mut optimizer = problem.optimizer(&args).unwrap();
+        optimizer.set_solver(Box::new(NewtonLineSearch::new(&args)));
+        assert!(optimizer.optimize(1.0).unwrap());
+        results.push(optimizer.get_results());
+
+        /* Trust region solvers record one subproblem outcome per iteration. */
+        for trust_region in &results[..2] {
+            assert_eq!(
+                trust_region.subproblem_outcomes.len(),
+                trust_region.iterations
+            );
+        }
+        assert!(results[2].subproblem_outcomes.is_empty());
+
+        /* Every solver records one history entry per iteration. */
+        for (index, result) in results.iter().enumerate() {
+            assert_eq!(result.history.len(), result.iterations);
+            let last = result.history.last().unwrap();
+            assert_eq!(last.constraint_error, result.concentration_error);
+            assert_eq!(last.objective, result.optimal_lagrangian);
+            assert!(result.history.iter().any(|record| record.accepted));
+            for record in &result.history {
+                assert_eq!(record.delta.is_some(), index < 2);
+                assert!(record.gradient_norm.is_finite());
+            }
+        }
+        let steihaug = &results[0];
+        for (record, outcome) in steihaug.history.iter().zip(&steihaug.subproblem_outcomes) {
+            assert_eq!(record.inner_iterations, outcome.inner_iterations);
+        }
+
+        for other in &results[1..] {
+            assert_eq!(other.termination_reason, TerminationReason::Converged);
+            assert!(other.concentration_error < args.constraint_tolerance);
+            for (expected, actual) in results[0].optimal_x.iter().zip(&other.optimal_x) {
+                assert!((expected - actual).abs() <= 1.0e-6 * expected.abs().max(1.0e-15));
+            }
+        }
+    }
+
+    #[test]
+    fn test_observer() {
+        let args = OptimizerArgs {
+            use_terminal: false,
+            ..Default::default()
+        };
+        let mut optimizer = dimer_problem(-20.0).optimizer(&args).unwrap();
+
+        /* An observer sees every iteration of an uninterrupted run. */
+        let mut events = 0;
+        let converged = optimizer
+            .optimize_with_observer(1.0, &mut |_: &IterationEvent| {
+                events += 1;
+                ObserverAction::Continue
+            })
+            .unwrap();
+        assert!(converged);
+        assert_eq!(events, optimizer.get_results().iterations);
+
+        /* Stopping ends the run as cancelled, keeping the iterations done so far. */
+        let mut seen = Vec::new();
+        let converged = optimizer
+            .optimize_with_observer(1.0, &mut |event: &IterationEvent| {
+                seen.push(event.record.iteration);
+                if seen.len() == 2 {
+                    ObserverAction::Stop
+                } else {
+                    ObserverAction::Continue
+                }
+            })
+            .unwrap();
+        assert!(!converged);
+        let results = optimizer.get_results();
+        assert_eq!(results.termination_reason, TerminationReason::Cancelled);
+        assert_eq!(results.iterations, 2);
+        assert_eq!(seen, vec![0, 1]);
+        assert_eq!(results.history.len(), 2);
+
+        /* A token cancelled from another thread stops the run after its first iteration. */
+        let token = CancellationToken::new();
+        let canceller = token.clone();
+        std::thread::spawn(move || canceller.cancel())
+            .join()
+            .unwrap();
+        optimizer
+            .optimize_with_observer(1.0, &mut token.clone())
+            .unwrap();
+        let results = optimizer.get_results();
+        assert_eq!(results.termination_reason, TerminationReason::Cancelled);
+        assert_eq!(results.iterations, 1);
+
+        /* An exhausted time limit does the same. */
+        optimizer
+            .optimize_with_observer(1.0, &mut TimeLimit::new(Duration::ZERO))
+            .unwrap();
+        assert_eq!(
+            optimizer.get_results().termination_reason,
+            TerminationReason::Cancelled
+        );
+        optimizer
+            .optimize_with_observer(1.0, &mut TimeLimit::new(Duration::from_secs(3600)))
+            .unwrap();
+        assert_eq!(
+            optimizer.get_results().termination_reason,
+            TerminationReason::Converged
+        );
+    }
+
+    #[test]
+    fn test_sensitivities() {
+        let problem = three_strand_problem([-9.0, -8.5, -17.0]);
+        let args = OptimizerArgs {
+            use_terminal: false,
+            ..Default::default()
+        };
+        let monomers = problem.monomers().to_owned();
+        let energies = problem.energies().free_energies(args.temp_celsius);
+        let solve = |monomers: &Array1<f64>, energies: &Array1<f64>| {
+            let mut optimizer = Optimizer::new(
+                monomers,
+                problem.polymers().clone(),
+                energies.clone(),
+                &args,
+            )
+            .unwrap();
+            assert!(optimizer.optimize(1.0).unwrap());
+            optimizer
+        };
+
+        let targets = [3, 5];
+        let sensitivities = solve(&monomers, &energies).sensitivities(&targets).unwrap();
+        assert_eq!(sensitivities.concentration.dim(), (2, 3));
+        assert_eq!(sensitivities.energy.dim(), (2, 6));
+
+        /* Compare against central differences of re-solved problems. */
+        for i in 0..monomers.len() {
+            let step = 1.0e-3 * monomers[i];
+            let mut up = monomers.clone();
+            up[i] += step;
+            let mut down = monomers.clone();
+            down[i] -= step;
+            let x_up = solve(&up, &energies).get_results().optimal_x;
+            let x_down = solve(&down, &energies).get_results().optimal_x;
+            for (row, &j) in targets.iter().enumerate() {
+                let expected = (x_up[j] - x_down[j]) / (2.0 * step);
+                assert!((sensitivities.concentration[[row, i]] - expected).abs() < 1.0e-4);
+            }
+        }
+        for k in 0..energies.len() {
+            let step = 1.0e-3;
+            let mut up = energies.clone();
+            up[k] += step;
+            let mut down = energies.clone();
+            down[k] -= step;
+            let x_up = solve(&monomers, &up).get_results().optimal_x;
+            let x_down = solve(&monomers, &down).get_results().optimal_x;
+            for (row, &j) in targets.iter().enumerate() {
+                let expected = (x_up[j].ln() - x_down[j].ln()) / (2.0 * step);
+                assert!((sensitivities.energy[[row, k]] - expected).abs() < 1.0e-3);
+            }
+        }
+
+        assert!(solve(&monomers, &energies).sensitivities(&[6]).is_err());
+    }
+
+    #[test]
+    fn test_deterministic_threads() {
+        /* Enough polymers to be split into several chunks. */
+        let num_monomers = 6;
+        let num_polymers = 5000;
+        let polymers = Array2::from_shape_fn((num_polymers, num_monomers), |(j, i)| {
+            if j < num_monomers {
+                (i == j) as u8 as f64
+            } else {
+                ((j * (i + 3) + i) % 4 / 3) as f64
+            }
+        });
+        let polymers_q = Array1::from_shape_fn(num_polymers, |j| {
+            if j < num_monomers {
+                0.0
+            } else {
+                -8.0 - (j % 7) as f64
+            }
+        });
+        let monomers = Array1::from_elem(num_monomers, 1.0e-6);
+
+        let results: Vec<OptimizerResults> = [1, 3]
+            .iter()
+            .map(|&threads| {
+                let args = OptimizerArgs {
+                    use_terminal: false,
+                    max_iterations: 20,
+                    threads,
+                    ..Default::default()
+                };
+                let mut optimizer =
+                    Optimizer::new(&monomers, &polymers, &polymers_q, &args).unwrap();
+                optimizer.optimize(1.0).unwrap();
+                optimizer.get_results()
+            })
+            .collect();
+
+        assert_eq!(results[0].iterations, results[1].iterations);
+        for (a, b) in results[0].optimal_x.iter().zip(&results[1].optimal_x) {
+            assert_eq!(a.to_bits(), b.to_bits());
+        }
+        assert_eq!(
+            results[0].optimal_lagrangian.to_bits(),
+            results[1].optimal_lagrangian.to_bits()
+        );
+    }
 }
@@ -0,0 +1,140 @@
+use crate::error::CoffeeError;
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::ops::Range;
+use std::sync::Arc;
+
+/// Smallest number of polymers handled by one task.
+const MIN_CHUNK_SIZE: usize = 1024;
+
+/// Largest number of tasks a reduction over the polymers is split into.
+const MAX_CHUNKS: usize = 64;
+
+/// Splits `0..len` into contiguous chunks for parallel work. The chunks only depend on `len`,
+/// never on the number of threads, so reductions that combine per-chunk results in order give
+/// bit-identical results for any thread count.
+///
+/// # Arguments
+///
+/// * `len` - The number of items to split, usually the number of polymers.
+///
+/// # Returns
+///
+/// The ranges of the chunks, in order.
+pub(crate) fn chunk_ranges(len: usize) -> Vec<Range<usize>> {
+    let chunk_size = MIN_CHUNK_SIZE.max(len.div_ceil(MAX_CHUNKS));
+    (0..len)
+        .step_by(chunk_size)
+        .map(|start| start..len.min(start + chunk_size))
+        .collect()
+}
+
+/// Reduces over `0..len` in parallel with a fixed reduction order: each chunk from
+/// `chunk_ranges` is reduced by `partial`, and the per-chunk results are then combined
+/// sequentially in chunk order.
+///
+/// # Arguments
+///
+/// * `len` - The number of items to reduce over.
+/// * `init` - The starting value of the reduction.
+/// * `partial` - Reduces a single chunk.
+/// * `combine` - Adds a chunk's result into the running total.
+///
+/// # Returns
+///
+/// The reduced value.
+pub(crate) fn ordered_reduce<T, P, C>(len: usize, init: T, partial: P, mut combine: C) -> T
+where
+    T: Send,
+    P: Fn(Range<usize>) -> T + Sync + Send,
+    C: FnMut(&mut T, T),
+{
+    let partials: Vec<T> = chunk_ranges(len).into_par_iter().map(partial).collect();
+    let mut total = init;
+    for part in partials {
+        combine(&mut total, part);
+    }
+    total
+}
+
+/// Creates the thread pool the optimizer runs its parallel work on.
+///
+/// # Arguments
+///
+/// * `threads` - The number of threads, or zero to share rayon's global pool, which uses every
+///   available core.
+///
+/// # Returns
+///
+/// The pool, `None` for the global pool, or an error if the pool couldn't be created.
+pub(crate) fn thread_pool(threads: usize) -> Result<Option<Arc<ThreadPool>>, CoffeeError> {
+    if threads == 0 {
+        return Ok(None);
+    }
+    match ThreadPoolBuilder::new().num_threads(threads).build() {
+        Ok(pool) => Ok(Some(Arc::new(pool))),
+        Err(e) => Err(CoffeeError::ThreadPool(format!(
+            "Failed to create a pool of {} threads: {}",
+            threads, e
+        ))),
+    }
+}
+
+/// Runs `op` on the given pool, or on the global pool if there is none. Parallel iterators
+/// inside `op` use the same pool.
+pub(crate) fn install<R, F>(pool: Option<&ThreadPool>, op: F) -> R
+where
+    R: Send,
+    F: FnOnce() -> R + Send,
+{
+    match pool {
+        Some(pool) => pool.install(op),
+        None => op(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::{s, Array1};
+
+    #[test]
+    fn test_chunk_ranges() {
+        assert!(chunk_ranges(0).is_empty());
+        assert_eq!(chunk_ranges(10), vec![0..10]);
+
+        /* Chunks cover every item once, in order. */
+        let ranges = chunk_ranges(1_000_000);
+        assert!(ranges.len() <= MAX_CHUNKS);
+        assert_eq!(ranges.first().unwrap().start, 0);
+        assert_eq!(ranges.last().unwrap().end, 1_000_000);
+        for pair in ranges.windows(2) {
+            assert_eq!(pair[0].end, pair[1].start);
+        }
+    }
+
+    #[test]
+    fn test_deterministic_reduce() {
+        let a = Array1::from_shape_fn(100_000, |i| ((i * 7919) % 1000) as f64 * 1.0e-3 + 1.0e-9);
+        let b = Array1::from_shape_fn(100_000, |i| 1.0 / (1.0 + i as f64));
+
+        /* The same chunks are combined in the same order for every thread count. */
+        let results: Vec<f64> = [1, 2, 4]
+            .iter()
+            .map(|&threads| {
+                let pool = thread_pool(threads).unwrap();
+                install(pool.as_deref(), || {
+                    ordered_reduce(
+                        a.len(),
+                        0.0,
+                        |range| a.slice(s![range.clone()]).dot(&b.slice(s![range])),
+                        |total, part| *total += part,
+                    )
+                })
+            })
+            .collect();
+        assert_eq!(results[0].to_bits(), results[1].to_bits());
+        assert_eq!(results[0].to_bits(), results[2].to_bits());
+        assert!((results[0] - a.dot(&b)).abs() < 1.0e-9 * results[0].abs());
+    }
+}
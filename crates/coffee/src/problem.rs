@@ -0,0 +1,523 @@
+use crate::composition::{CompositionMatrix, MatrixStorage};
+use crate::energy::PolymerEnergies;
+use crate::error::CoffeeError;
+use crate::extras::{Names, OptimizerArgs, OptimizerResults};
+use crate::fileparse::{read_inputs, ParseOptions};
+use crate::observer::{IterationEvent, Observer, ObserverAction};
+use crate::optimize::Optimizer;
+use crate::sensitivity::Sensitivities;
+use ndarray::{Array1, ArrayView1};
+
+/// Initial trust region radius used when solving a problem.
+pub(crate) const INITIAL_DELTA: f64 = 1.0;
+
+/// An in-memory equilibrium problem, holding everything the optimizer needs:
+/// - The initial monomer concentrations (length M).
+/// - The polymer composition matrix (N x M), where entry (j, i) is the number of monomer i in polymer j,
+///   stored dense or sparse.
+/// - The polymer free energies (length N), fixed or as a function of temperature.
+///
+/// Problems are validated on construction, so a successfully built problem can always be handed
+/// to the optimizer.
+#[derive(Clone, Debug)]
+pub struct EquilibriumProblem {
+    monomers: Array1<f64>,
+    polymers: CompositionMatrix,
+    energies: PolymerEnergies,
+    names: Names,
+    warnings: Vec<String>,
+}
+
+/// Checks that the monomer, polymer and energy arrays have consistent sizes.
+///
+/// # Arguments
+///
+/// * `num_monomers` - The number of monomer concentrations.
+/// * `polymers` - The size of the polymer composition matrix, (N, M).
+/// * `num_energies` - The number of polymer free energies.
+///
+/// # Returns
+///
+/// A `CoffeeError` describing the first inconsistency found, if any.
+pub(crate) fn validate_dimensions(
+    num_monomers: usize,
+    polymers: (usize, usize),
+    num_energies: usize,
+) -> Result<(), CoffeeError> {
+    let (num_polymers, num_columns) = polymers;
+
+    if num_monomers == 0 {
+        return Err(CoffeeError::InvalidInput(
+            "Monomers array is empty.".to_string(),
+        ));
+    }
+    if num_polymers == 0 {
+        return Err(CoffeeError::InvalidInput(
+            "Polymers array is empty.".to_string(),
+        ));
+    }
+    if num_polymers < num_monomers {
+        return Err(CoffeeError::InvalidInput(
+            "Number of polymers is less than number of monomers.".to_string(),
+        ));
+    }
+    if num_monomers != num_columns {
+        return Err(CoffeeError::MonomerCountMismatch {
+            monomers: num_monomers,
+            columns: num_columns,
+        });
+    }
+    if num_polymers != num_energies {
+        return Err(CoffeeError::EnergyCountMismatch {
+            energies: num_energies,
+            rows: num_polymers,
+        });
+    }
+    Ok(())
+}
+
+impl EquilibriumProblem {
+    /// Creates a new problem from its monomer concentrations, composition matrix and free energies.
+    ///
+    /// # Arguments
+    ///
+    /// * `monomers` - The initial monomer concentrations, one per monomer.
+    /// * `polymers` - The composition matrix, one row per polymer and one column per monomer,
+    ///   either as a dense array or as a `CompositionMatrix`.
+    /// * `energies` - The free energy of each polymer, either as an array of fixed free energies
+    ///   or as `PolymerEnergies`.
+    ///
+    /// # Returns
+    ///
+    /// The problem, or an error if:
+    /// - Any of the arrays are empty or their sizes are inconsistent.
+    /// - There are fewer polymers than monomers.
+    /// - A concentration is negative or not finite.
+    /// - A composition entry is negative or not finite.
+    /// - An energy is not finite.
+    pub fn new(
+        monomers: Array1<f64>,
+        polymers: impl Into<CompositionMatrix>,
+        energies: impl Into<PolymerEnergies>,
+    ) -> Result<Self, CoffeeError> {
+        let polymers = polymers.into();
+        let energies = energies.into();
+        validate_dimensions(
+            monomers.len(),
+            (polymers.nrows(), polymers.ncols()),
+            energies.len(),
+        )?;
+
+        if let Some(index) = monomers.iter().position(|c| !c.is_finite() || *c < 0.0) {
+            return Err(CoffeeError::InvalidInput(format!(
+                "Monomer concentration {} is not a finite, non-negative value.",
+                index
+            )));
+        }
+        if let Some((row, col, _)) = polymers
+            .nonzeros()
+            .find(|(_, _, a)| !a.is_finite() || *a < 0.0)
+        {
+            return Err(CoffeeError::InvalidInput(format!(
+                "Composition of polymer {} for monomer {} is not a finite, non-negative value.",
+                row, col
+            )));
+        }
+        energies.validate()?;
+
+        Ok(EquilibriumProblem {
+            monomers,
+            polymers,
+            energies,
+            names: Names::default(),
+            warnings: Vec::new(),
+        })
+    }
+
+    /// Creates a new problem from the contents of CFE and CON inputs. The composition matrix is
+    /// stored sparse when it is mostly zeros.
+    ///
+    /// # Arguments
+    ///
+    /// * `cfe_bytes` - The contents of the compositions and free energies input.
+    /// * `con_bytes` - The contents of the concentrations input.
+    /// * `options` - How the inputs are laid out.
+    ///
+    /// # Returns
+    ///
+    /// The problem, or an error if the inputs can't be parsed or fail validation. Names from a
+    /// CFE header row, a CFE name column or a CON name column are kept, and anything suspicious
+    /// about inputs that were still read is kept in `warnings`.
+    pub fn from_bytes(
+        cfe_bytes: &[u8],
+        con_bytes: &[u8],
+        options: &ParseOptions,
+    ) -> Result<Self, CoffeeError> {
+        let inputs = read_inputs(cfe_bytes, con_bytes, options)?;
+        let polymers =
+            CompositionMatrix::from(inputs.compositions).with_storage(MatrixStorage::Auto)?;
+        let problem = Self::new(inputs.concentrations, polymers, inputs.energies)?
+            .with_names(inputs.names)?;
+        Ok(EquilibriumProblem {
+            warnings: inputs.warnings,
+            ..problem
+        })
+    }
+
+    /// Names the monomers and polymers, to label their values in the results.
+    ///
+    /// # Arguments
+    ///
+    /// * `names` - The names, with one per monomer and polymer if given.
+    ///
+    /// # Returns
+    ///
+    /// The named problem, or an error if the names don't match the problem's sizes, or are
+    /// empty or repeated.
+    pub fn with_names(self, names: Names) -> Result<Self, CoffeeError> {
+        names.validate(self.num_monomers(), self.num_polymers())?;
+        Ok(EquilibriumProblem { names, ..self })
+    }
+
+    /// Returns the names of the monomers and polymers.
+    pub fn names(&self) -> &Names {
+        &self.names
+    }
+
+    /// Returns the warnings raised while reading the problem's inputs, e.g. about an ambiguous
+    /// input format. Problems built with `new` have none.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Solves the problem for its equilibrium concentrations.
+    ///
+    /// # Arguments
+    ///
+    /// * `optimizer_args` - The optimizer parameters to solve with.
+    ///
+    /// # Returns
+    ///
+    /// The results of the optimization, or an error if the optimization failed.
+    pub fn solve(&self, optimizer_args: &OptimizerArgs) -> Result<OptimizerResults, CoffeeError> {
+        self.solve_with_observer(optimizer_args, &mut |_: &IterationEvent| {
+            ObserverAction::Continue
+        })
+    }
+
+    /// Solves the problem for its equilibrium concentrations, reporting each iteration to an
+    /// observer that can stop the optimization early.
+    ///
+    /// # Arguments
+    ///
+    /// * `optimizer_args` - The optimizer parameters to solve with.
+    /// * `observer` - The observer to call after each iteration.
+    ///
+    /// # Returns
+    ///
+    /// The results of the optimization, or an error if the optimization failed. A stopped
+    /// optimization is not an error; its results have `TerminationReason::Cancelled`.
+    pub fn solve_with_observer(
+        &self,
+        optimizer_args: &OptimizerArgs,
+        observer: &mut dyn Observer,
+    ) -> Result<OptimizerResults, CoffeeError> {
+        let mut optimizer = self.optimizer(optimizer_args)?;
+        optimizer.optimize_with_observer(INITIAL_DELTA, observer)?;
+
+        Ok(optimizer.get_results())
+    }
+
+    /// Solves the problem and finds the sensitivities of the selected polymers' concentrations
+    /// to the monomer concentrations and polymer free energies.
+    ///
+    /// # Arguments
+    ///
+    /// * `optimizer_args` - The optimizer parameters to solve with.
+    /// * `polymers` - The 0-based indices of the polymers to find the sensitivities of.
+    ///
+    /// # Returns
+    ///
+    /// The results of the optimization and the sensitivities at its optimum, or an error if the
+    /// optimization failed or didn't converge, or the sensitivities are undefined there.
+    pub fn solve_with_sensitivities(
+        &self,
+        optimizer_args: &OptimizerArgs,
+        polymers: &[usize],
+    ) -> Result<(OptimizerResults, Sensitivities), CoffeeError> {
+        let mut optimizer = self.optimizer(optimizer_args)?;
+        optimizer.optimize(INITIAL_DELTA)?;
+
+        let sensitivities = optimizer.sensitivities(polymers)?;
+        Ok((optimizer.get_results(), sensitivities))
+    }
+
+    /// Creates an optimizer for this problem, which can be reused to re-solve variations of it.
+    ///
+    /// # Arguments
+    ///
+    /// * `optimizer_args` - The optimizer parameters to solve with.
+    ///
+    /// # Returns
+    ///
+    /// The optimizer, ready to call `optimize` on.
+    pub(crate) fn optimizer(
+        &self,
+        optimizer_args: &OptimizerArgs,
+    ) -> Result<Optimizer, CoffeeError> {
+        let mut optimizer = Optimizer::new(
+            &self.monomers,
+            self.polymers.clone(),
+            self.energies.clone(),
+            optimizer_args,
+        )?;
+        optimizer.set_names(self.names.clone())?;
+        Ok(optimizer)
+    }
+
+    /// Returns the initial monomer concentrations.
+    pub fn monomers(&self) -> ArrayView1<'_, f64> {
+        self.monomers.view()
+    }
+
+    /// Returns the polymer composition matrix (N x M).
+    pub fn polymers(&self) -> &CompositionMatrix {
+        &self.polymers
+    }
+
+    /// Returns the polymer energies.
+    pub fn energies(&self) -> &PolymerEnergies {
+        &self.energies
+    }
+
+    /// Returns the number of monomers, M.
+    pub fn num_monomers(&self) -> usize {
+        self.monomers.len()
+    }
+
+    /// Returns the number of polymers, N.
+    pub fn num_polymers(&self) -> usize {
+        self.polymers.nrows()
+    }
+}
+
+/// Builds the A + B <-> AB problem the tests share, with 1 uM of each monomer.
+///
+/// # Arguments
+///
+/// * `energy` - The free energy of the dimer.
+///
+/// # Returns
+///
+/// The dimer problem.
+#[cfg(test)]
+pub(crate) fn dimer_problem(energy: f64) -> EquilibriumProblem {
+    EquilibriumProblem::new(
+        ndarray::array![1.0e-6, 1.0e-6],
+        ndarray::array![[1.0, 0.0], [0.0, 1.0], [1.0, 1.0]],
+        ndarray::array![0.0, 0.0, energy],
+    )
+    .unwrap()
+}
+
+/// Builds the three-strand problem the tests share, with complexes AB, BC and ABBC.
+///
+/// # Arguments
+///
+/// * `energies` - The free energies of AB, BC and ABBC.
+///
+/// # Returns
+///
+/// The three-strand problem.
+#[cfg(test)]
+pub(crate) fn three_strand_problem(energies: [f64; 3]) -> EquilibriumProblem {
+    EquilibriumProblem::new(
+        ndarray::array![1.0e-6, 2.0e-6, 5.0e-7],
+        ndarray::array![
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 0.0, 1.0],
+            [1.0, 1.0, 0.0],
+            [0.0, 1.0, 1.0],
+            [1.0, 2.0, 1.0]
+        ],
+        ndarray::array![0.0, 0.0, 0.0, energies[0], energies[1], energies[2]],
+    )
+    .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::InputFile;
+    use crate::extras::TerminationReason;
+    use crate::fileparse::EnergyLayout;
+    use crate::format::results_message;
+    use ndarray::array;
+
+    #[test]
+    fn test_invalid_values() {
+        let polymers = array![[1.0, 0.0], [0.0, 1.0], [1.0, 1.0]];
+        let energies = array![0.0, 0.0, -10.0];
+
+        /* Negative concentration. */
+        let result =
+            EquilibriumProblem::new(array![1.0e-6, -1.0e-6], polymers.clone(), energies.clone());
+        assert!(result.is_err());
+
+        /* Non-finite free energy. */
+        let result = EquilibriumProblem::new(
+            array![1.0e-6, 1.0e-6],
+            polymers.clone(),
+            array![0.0, 0.0, f64::NAN],
+        );
+        assert!(result.is_err());
+
+        /* Negative composition. */
+        let result = EquilibriumProblem::new(
+            array![1.0e-6, 1.0e-6],
+            array![[1.0, 0.0], [0.0, 1.0], [-1.0, 1.0]],
+            energies.clone(),
+        );
+        assert!(result.is_err());
+
+        let result = EquilibriumProblem::new(array![1.0e-6, 1.0e-6], polymers, energies);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_error_kinds() {
+        let polymers = array![[1.0, 0.0], [0.0, 1.0], [1.0, 1.0]];
+
+        /* Shape mismatches report both sizes. */
+        let result = EquilibriumProblem::new(
+            array![1.0e-6, 1.0e-6, 1.0e-6],
+            polymers.clone(),
+            array![0.0, 0.0, -10.0],
+        );
+        assert!(matches!(
+            result,
+            Err(CoffeeError::MonomerCountMismatch {
+                monomers: 3,
+                columns: 2
+            })
+        ));
+        let result = EquilibriumProblem::new(array![1.0e-6, 1.0e-6], polymers, array![0.0, 0.0]);
+        assert!(matches!(
+            result,
+            Err(CoffeeError::EnergyCountMismatch {
+                energies: 2,
+                rows: 3
+            })
+        ));
+
+        /* A bad free energy is located by line and column. */
+        let cfe = b"1,0,0.0\n0,1,x1.5\n1,1,-10.0\n";
+        let result = EquilibriumProblem::from_bytes(cfe, b"1e-6\n1e-6\n", &ParseOptions::default());
+        match result {
+            Err(CoffeeError::Parse {
+                file, line, column, ..
+            }) => {
+                assert_eq!(file, InputFile::Compositions);
+                assert_eq!((line, column), (2, 3));
+            }
+            _ => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn test_solve_dimer() {
+        /* A + B <-> AB with a strongly favourable dimer. */
+        let problem = dimer_problem(-20.0);
+        let args = OptimizerArgs {
+            use_terminal: false,
+            ..Default::default()
+        };
+
+        let results = problem.solve(&args).unwrap();
+        assert_eq!(results.optimal_x.len(), 3);
+        assert!(results.optimal_x[2] > results.optimal_x[0]);
+        assert!(results.concentration_error < 1.0e-12);
+    }
+
+    #[test]
+    fn test_sensitivities_need_convergence() {
+        let problem = dimer_problem(-20.0);
+        let args = OptimizerArgs {
+            use_terminal: false,
+            ..Default::default()
+        };
+        assert!(problem.solve_with_sensitivities(&args, &[2]).is_ok());
+
+        /* A single iteration stops short of the optimum the sensitivities are taken at. */
+        let args = OptimizerArgs {
+            max_iterations: 1,
+            ..args
+        };
+        assert!(matches!(
+            problem.solve_with_sensitivities(&args, &[2]),
+            Err(CoffeeError::NotConverged {
+                reason: TerminationReason::MaxIterations
+            })
+        ));
+    }
+
+    #[test]
+    fn test_from_bytes_enthalpy_entropy() {
+        let cfe = b"1,0,0.0,0.0\n0,1,0.0,0.0\n1,1,-60.0,-0.16\n";
+        let con = b"1e-6\n1e-6\n";
+        let options = ParseOptions {
+            energy_layout: EnergyLayout::EnthalpyEntropy,
+            ..Default::default()
+        };
+
+        let problem = EquilibriumProblem::from_bytes(cfe, con, &options).unwrap();
+        assert_eq!(problem.num_polymers(), 3);
+        assert_eq!(problem.num_monomers(), 2);
+        assert!(problem.warnings().is_empty());
+        assert!(problem.energies().is_temperature_dependent());
+
+        /* The dimer is less stable at higher temperatures. */
+        let cold = problem.energies().free_energies(25.0);
+        let hot = problem.energies().free_energies(75.0);
+        assert!(hot[2] > cold[2]);
+
+        /* Too few columns for the requested layout. */
+        let options = ParseOptions {
+            energy_layout: EnergyLayout::EnthalpyEntropyHeatCapacity,
+            ..Default::default()
+        };
+        assert!(EquilibriumProblem::from_bytes(b"1,0.0,0.0\n", b"1e-6\n", &options).is_err());
+    }
+
+    #[test]
+    fn test_names() {
+        let cfe = b"complex,A,B,dG\nA,1,0,0\nB,0,1,0\nAB,1,1,-20\n";
+        let problem =
+            EquilibriumProblem::from_bytes(cfe, b"1e-6\n1e-6\n", &ParseOptions::default()).unwrap();
+        assert_eq!(problem.names().polymer(2), Some("AB"));
+
+        /* Names are carried into the results and label the concentrations. */
+        let args = OptimizerArgs {
+            use_terminal: false,
+            ..Default::default()
+        };
+        let results = problem.solve(&args).unwrap();
+        assert_eq!(results.names.monomer(1), Some("B"));
+        let message = results_message(&results);
+        assert!(message.starts_with("A: "));
+        assert!(message.contains("\r\nAB: "));
+
+        /* Names must fit the problem and be unique. */
+        let names = Names {
+            monomers: Some(vec!["A".into(), "A".into()]),
+            polymers: None,
+        };
+        assert!(problem.clone().with_names(names).is_err());
+        let names = Names {
+            monomers: Some(vec!["A".into()]),
+            polymers: None,
+        };
+        assert!(problem.with_names(names).is_err());
+    }
+}
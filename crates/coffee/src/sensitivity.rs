@@ -0,0 +1,23 @@
+use crate::extras::Names;
+use ndarray::Array2;
+
+/// Sensitivities of the equilibrium concentrations of selected polymers, found from the Hessian
+/// of the dual objective at the optimum.
+///
+/// At equilibrium x_j = q_j exp(a_j . lambda) with A^T x = x0. Differentiating the constraint
+/// gives d lambda / d x0 = (A^T diag(x) A)^-1, which is the inverse Hessian up to a scale that
+/// cancels, so every sensitivity costs one solve with the M x M Hessian per selected polymer.
+#[derive(Clone, Debug)]
+pub struct Sensitivities {
+    /// The selected polymers, as 0-based row indices of the composition matrix.
+    pub polymers: Vec<usize>,
+    /// d x_j / d x0_i, the change in each selected polymer's concentration per unit change in
+    /// each monomer's total concentration. One row per selected polymer, one column per monomer.
+    pub concentration: Array2<f64>,
+    /// d ln x_j / d dG_k, the relative change in each selected polymer's concentration per
+    /// kcal/mol change in each polymer's free energy. One row per selected polymer, one column
+    /// per polymer.
+    pub energy: Array2<f64>,
+    /// The names of the problem's monomers and polymers, if it has any.
+    pub names: Names,
+}
@@ -0,0 +1,374 @@
+use crate::dogleg::Dogleg;
+use crate::error::CoffeeError;
+use crate::extras::{OptimizerArgs, SolverKind, SubproblemKind, SubproblemOutcome};
+use crate::hessian::{damped_newton_step, HessianOperator};
+use crate::objective::{DualObjective, DualPoint};
+use crate::steihaug::Steihaug;
+use ndarray::{Array1, ArrayView1};
+
+/// Changes below this fraction of the Lagrangian are lost to rounding in its value.
+const UNRESOLVED_REDUCTION: f64 = 4.0 * f64::EPSILON;
+
+/// Fraction of the predicted decrease a line search step must achieve (Armijo condition).
+const ARMIJO_FRACTION: f64 = 1.0e-4;
+
+/// Largest number of times a line search halves its step before giving up.
+const MAX_BACKTRACKS: usize = 60;
+
+/// Solves the trust region subproblem:
+/// min m(p) = g^T p + 1/2 p^T H p
+/// s.t. ||p|| <= delta
+/// where H is the Hessian and g is the gradient of the objective.
+pub trait SubproblemSolver: Send + Sync {
+    /// Solves the subproblem. The step is then available through `step`.
+    ///
+    /// # Arguments
+    ///
+    /// * `gradient` - The gradient g.
+    /// * `hessian` - The Hessian H, as a dense matrix or an operator giving Hessian-vector
+    ///   products.
+    /// * `eps` - Tolerance on the residual of the model gradient, for iterative solvers.
+    /// * `delta` - The trust region radius.
+    ///
+    /// # Returns
+    ///
+    /// How the subproblem was solved, or an error if no step was found.
+    fn solve(
+        &mut self,
+        gradient: &Array1<f64>,
+        hessian: &dyn HessianOperator,
+        eps: f64,
+        delta: f64,
+    ) -> Result<SubproblemOutcome, CoffeeError>;
+
+    /// Returns the step found by the last successful `solve`.
+    fn step(&self) -> Array1<f64>;
+
+    /// Resets any state kept between solves, before a new optimization.
+    fn reset(&mut self);
+}
+
+/// Outcome of one iteration of an `EquilibriumSolver`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IterationOutcome {
+    /// The step was taken.
+    Accepted,
+    /// The step was rejected, and the multipliers are unchanged.
+    Rejected,
+    /// The step made no change to the objective, so no further progress is possible.
+    Stalled,
+}
+
+/// The multipliers after one iteration of an `EquilibriumSolver`.
+pub struct Iteration {
+    /// The multipliers to continue from.
+    pub lambda: Array1<f64>,
+    /// The objective at `lambda`.
+    pub value: f64,
+    /// What happened to the step.
+    pub outcome: IterationOutcome,
+    /// How the trust region subproblem was solved, for solvers that have one.
+    pub subproblem: Option<SubproblemOutcome>,
+    /// The trust region radius the step was limited to, for solvers that have one.
+    pub delta: Option<f64>,
+    /// The ratio of the actual to the predicted reduction of the objective, if it was found.
+    pub rho: Option<f64>,
+}
+
+/// An algorithm that drives the multipliers of the dual objective to its minimum, one iteration
+/// at a time. The optimizer evaluates the objective, checks for convergence and fills the
+/// `OptimizerResults`, so every solver reports its results the same way.
+pub trait EquilibriumSolver: Send + Sync {
+    /// Resets the solver's state before a new optimization.
+    ///
+    /// # Arguments
+    ///
+    /// * `initial_delta` - The initial trust region radius, for solvers that use one.
+    fn reset(&mut self, initial_delta: f64);
+
+    /// Takes one iteration from an evaluated point.
+    ///
+    /// # Arguments
+    ///
+    /// * `objective` - The dual objective.
+    /// * `point` - The objective and its gradient at the current multipliers.
+    ///
+    /// # Returns
+    ///
+    /// The multipliers to continue from, or an error if no step could be computed.
+    fn iterate(
+        &mut self,
+        objective: &DualObjective,
+        point: &DualPoint,
+    ) -> Result<Iteration, CoffeeError>;
+}
+
+/// Creates the solver selected by the optimizer parameters.
+///
+/// # Arguments
+///
+/// * `args` - The optimizer parameters.
+/// * `num_monomers` - The number of multipliers, M.
+///
+/// # Returns
+///
+/// The solver, ready for `reset`.
+pub fn solver_from_args(args: &OptimizerArgs, num_monomers: usize) -> Box<dyn EquilibriumSolver> {
+    match args.solver {
+        SolverKind::TrustRegion => {
+            let subproblem: Box<dyn SubproblemSolver> = match args.subproblem {
+                SubproblemKind::Steihaug => {
+                    Box::new(Steihaug::new(args.max_iterations, num_monomers))
+                }
+                SubproblemKind::Dogleg => Box::new(Dogleg::new(num_monomers)),
+            };
+            Box::new(TrustRegion::new(subproblem, args))
+        }
+        SolverKind::NewtonLineSearch => Box::new(NewtonLineSearch::new(args)),
+    }
+}
+
+/// Calculates the norm of the given vector.
+fn norm(v: ArrayView1<f64>) -> f64 {
+    v.iter().map(|&x| x * x).sum::<f64>().sqrt()
+}
+
+/// Returns whether both the predicted and the actual change of the objective are below its
+/// resolution. Near the optimum the actual change is then only rounding noise, and the
+/// quadratic model is accurate, so the step should be trusted rather than rejected.
+fn is_unresolved(value: f64, pred_reduction: f64, actual_reduction: f64) -> bool {
+    let resolution = UNRESOLVED_REDUCTION * value.abs().max(1.0);
+    pred_reduction.abs() <= resolution && actual_reduction.abs() <= resolution
+}
+
+/// Returns the Newton direction if it is a descent direction, or steepest descent otherwise.
+/// Rounding in a nearly singular Newton system can leave the direction pointing uphill, where a
+/// negative predicted reduction would let the line search accept an increase.
+///
+/// # Arguments
+///
+/// * `newton` - The Newton direction.
+/// * `gradient` - The gradient of the objective.
+///
+/// # Returns
+///
+/// A direction along which the objective decreases, unless the gradient is zero.
+fn descent_direction(newton: Array1<f64>, gradient: &Array1<f64>) -> Array1<f64> {
+    if gradient.dot(&newton) < 0.0 {
+        newton
+    } else {
+        -gradient
+    }
+}
+
+/// Trust region method: each step minimizes the quadratic model within a radius delta, and the
+/// radius grows or shrinks with how well the model predicted the actual reduction.
+pub struct TrustRegion {
+    subproblem: Box<dyn SubproblemSolver>,
+    delta: f64,
+    max_delta: f64,
+    eta: f64,
+    norm_ratio_threshold: f64,
+    rho_thresholds: [f64; 2],
+    scale_factors: [f64; 2],
+}
+
+impl TrustRegion {
+    /// Creates a trust region solver.
+    ///
+    /// # Arguments
+    ///
+    /// * `subproblem` - The solver for the trust region subproblem.
+    /// * `args` - The optimizer parameters, for the radius limits and update thresholds.
+    pub fn new(subproblem: Box<dyn SubproblemSolver>, args: &OptimizerArgs) -> Self {
+        TrustRegion {
+            subproblem,
+            delta: 1.0,
+            max_delta: args.max_delta,
+            eta: args.eta,
+            norm_ratio_threshold: args.norm_ratio_threshold,
+            rho_thresholds: args.rho_thresholds,
+            scale_factors: args.scale_factors,
+        }
+    }
+}
+
+impl EquilibriumSolver for TrustRegion {
+    fn reset(&mut self, initial_delta: f64) {
+        self.delta = initial_delta;
+        self.subproblem.reset();
+    }
+
+    fn iterate(
+        &mut self,
+        objective: &DualObjective,
+        point: &DualPoint,
+    ) -> Result<Iteration, CoffeeError> {
+        let gradient_norm = norm(point.gradient.view());
+        let epsilon = gradient_norm.sqrt().min(0.5f64) * gradient_norm;
+
+        /* Find predicted next step through the subproblem solver. */
+        let delta = self.delta;
+        let hessian = objective.hessian(point);
+        let subproblem =
+            self.subproblem
+                .solve(&point.gradient, hessian.as_ref(), epsilon, self.delta)?;
+        let update_step = self.subproblem.step();
+
+        /* Evaluate the step to find whether the predicted reduction is accurate. */
+        let lambda = &point.lambda + &update_step;
+        let value = objective.value(lambda.view());
+
+        /* Find predicted and actual reductions to see how significant the optimizing change is. */
+        let pred_reduction = -(point.gradient.dot(&update_step)
+            + 0.5 * update_step.dot(&hessian.apply(update_step.view())));
+        let actual_reduction = point.value - value;
+        let unresolved = is_unresolved(point.value, pred_reduction, actual_reduction);
+
+        /* No more optimization is possible as there is no optimizing change. */
+        if actual_reduction == 0.0 && !unresolved {
+            return Ok(Iteration {
+                lambda,
+                value,
+                outcome: IterationOutcome::Stalled,
+                subproblem: Some(subproblem),
+                delta: Some(delta),
+                rho: None,
+            });
+        }
+
+        /* Ratio calculation to determine next iteration's parameters. */
+        let rho = if unresolved {
+            1.0
+        } else if pred_reduction != 0.0 {
+            actual_reduction / pred_reduction
+        } else {
+            0.0
+        };
+
+        /* Change delta based on whether reductions is too small or too high. */
+        if rho < self.rho_thresholds[0] {
+            /* Actual reduction is much less than predicted --> scale down delta param. */
+            self.delta *= self.scale_factors[0];
+        } else if rho > self.rho_thresholds[1]
+            && norm(update_step.view()) >= self.norm_ratio_threshold * self.delta
+        {
+            /* Actual reduction is close to predicted --> scale up delta param up to a point. */
+            self.delta = self.max_delta.min(self.scale_factors[1] * self.delta);
+        }
+
+        /* Actual reduction is scary less than predicted --> can't trust the step. */
+        if rho > self.eta {
+            Ok(Iteration {
+                lambda,
+                value,
+                outcome: IterationOutcome::Accepted,
+                subproblem: Some(subproblem),
+                delta: Some(delta),
+                rho: Some(rho),
+            })
+        } else {
+            Ok(Iteration {
+                lambda: point.lambda.clone(),
+                value: point.value,
+                outcome: IterationOutcome::Rejected,
+                subproblem: Some(subproblem),
+                delta: Some(delta),
+                rho: Some(rho),
+            })
+        }
+    }
+}
+
+/// Damped Newton method with a backtracking line search: each step solves H p = -g, adding
+/// damping to H if it is singular and taking -g if p still points uphill, and halves the step
+/// until it decreases the objective enough.
+pub struct NewtonLineSearch {
+    max_step: f64,
+}
+
+impl NewtonLineSearch {
+    /// Creates a line search solver.
+    ///
+    /// # Arguments
+    ///
+    /// * `args` - The optimizer parameters. Steps are limited to a length of `max_delta`.
+    pub fn new(args: &OptimizerArgs) -> Self {
+        NewtonLineSearch {
+            max_step: args.max_delta,
+        }
+    }
+}
+
+impl EquilibriumSolver for NewtonLineSearch {
+    fn reset(&mut self, _initial_delta: f64) {}
+
+    fn iterate(
+        &mut self,
+        objective: &DualObjective,
+        point: &DualPoint,
+    ) -> Result<Iteration, CoffeeError> {
+        let hessian = objective.hessian(point).to_dense();
+        let (newton, _) = damped_newton_step(&hessian, &point.gradient)?;
+        let mut direction = descent_direction(newton, &point.gradient);
+
+        /* Limit the step, since a nearly singular Hessian gives very long directions. */
+        let length = norm(direction.view());
+        if length > self.max_step {
+            direction *= self.max_step / length;
+        }
+        let slope = point.gradient.dot(&direction);
+
+        let mut step_size = 1.0;
+        for _ in 0..MAX_BACKTRACKS {
+            let lambda = &point.lambda + &(step_size * &direction);
+            let value = objective.value(lambda.view());
+            let pred_reduction = -step_size * slope;
+            let actual_reduction = point.value - value;
+
+            if actual_reduction >= ARMIJO_FRACTION * pred_reduction
+                || is_unresolved(point.value, pred_reduction, actual_reduction)
+            {
+                return Ok(Iteration {
+                    lambda,
+                    value,
+                    outcome: IterationOutcome::Accepted,
+                    subproblem: None,
+                    delta: None,
+                    rho: (pred_reduction != 0.0).then(|| actual_reduction / pred_reduction),
+                });
+            }
+            step_size *= 0.5;
+        }
+
+        /* No step along the direction decreased the objective. */
+        Ok(Iteration {
+            lambda: point.lambda.clone(),
+            value: point.value,
+            outcome: IterationOutcome::Stalled,
+            subproblem: None,
+            delta: None,
+            rho: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn test_descent_direction() {
+        let gradient = array![1.0, -2.0];
+
+        /* A downhill Newton direction is kept. */
+        let newton = array![-0.5, 0.5];
+        assert_eq!(descent_direction(newton.clone(), &gradient), newton);
+
+        /* An uphill or flat one is replaced by steepest descent. */
+        for newton in [array![0.5, -0.5], array![2.0, 1.0]] {
+            assert_eq!(descent_direction(newton, &gradient), -&gradient);
+        }
+    }
+}
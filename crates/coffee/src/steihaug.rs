@@ -1,4 +1,8 @@
-use ndarray::{Array1, Array2, ArrayView1};
+use crate::error::CoffeeError;
+use crate::extras::SubproblemOutcome;
+use crate::hessian::HessianOperator;
+use crate::solver::SubproblemSolver;
+use ndarray::{Array1, ArrayView1};
 
 pub struct Steihaug {
     curr_iterations: usize,
@@ -33,6 +37,11 @@ impl Steihaug {
         }
     }
 
+    /// Resets the iteration count, so the solver can be reused for a new optimization.
+    pub fn reset(&mut self) {
+        self.curr_iterations = 0;
+    }
+
     /// Calculates the norm of the given vector. Replaces ndarray-linalg crate's implementation
     /// because it isn't working with WASM. Equivalent to `ndarray_linalg::Norm::norm2`, which is
     /// the implementation of pythagorean theorem for N elements.
@@ -48,7 +57,8 @@ impl Steihaug {
         v.iter().map(|&x| x * x).sum::<f64>().sqrt()
     }
 
-    /// Solve the quadratic equation for the curvature.
+    /// Solve the quadratic equation ||z + tau d|| = delta for the steps to the trust region
+    /// boundary along the current direction.
     ///
     /// # Arguments
     ///
@@ -56,8 +66,8 @@ impl Steihaug {
     ///
     /// # Returns
     ///
-    /// * `Option<f64>` - The solution to the quadratic equation.
-    fn solve_curvature_quadratic(&self, delta: f64) -> Option<f64> {
+    /// * `Option<[f64; 2]>` - The positive and the negative solution to the quadratic equation.
+    fn solve_curvature_quadratic(&self, delta: f64) -> Option<[f64; 2]> {
         /* Operation is d * d, returning scalar. */
         let a = self.curr_dstep.dot(&self.curr_dstep);
 
@@ -67,37 +77,58 @@ impl Steihaug {
         /* Operation is z * z - delta^2, returning scalar. */
         let c = self.curr_zstep.dot(&self.curr_zstep) - delta * delta;
 
-        /* Solve for real solution for quadratic equation given coefficients. */
-        let t = (-b + (b * b - 4.0 * a * c).sqrt()) / (2.0 * a);
+        /* Solve for real solutions for quadratic equation given coefficients. */
+        let root = (b * b - 4.0 * a * c).sqrt();
+        let t = [(-b + root) / (2.0 * a), (-b - root) / (2.0 * a)];
 
-        if t.is_finite() {
+        if t.iter().all(|v| v.is_finite()) {
             Some(t)
         } else {
             None
         }
     }
 
-    /// Update the zstep early.
+    /// Update the zstep early, moving it to the trust region boundary along the current
+    /// direction.
     ///
     /// # Arguments
     ///
     /// * `delta` - The trust region radius.
+    /// * `curvature` - The curvature d^T H d along the current direction, if it isn't positive.
+    ///   Both points on the boundary are then candidates, and the one with the lower model
+    ///   value is taken (Steihaug-Toint). Otherwise the step continues forward along d.
     ///
     /// # Returns
     ///
-    /// * `bool` - Whether the update was successful.
-    fn early_update_zstep(&mut self, delta: f64) -> bool {
+    /// * `Result<(), CoffeeError>` - An error if the boundary can't be reached.
+    fn early_update_zstep(
+        &mut self,
+        delta: f64,
+        curvature: Option<f64>,
+    ) -> Result<(), CoffeeError> {
         /* Find weight solution. */
-        let tau = match self.solve_curvature_quadratic(delta) {
-            Some(t) => t,
-            None => {
-                println!("Failed to find weight solution.");
-                return false;
+        let [forward, backward] = self.solve_curvature_quadratic(delta).ok_or_else(|| {
+            CoffeeError::SubproblemFailed(
+                "Failed to find the step to the trust region boundary.".to_string(),
+            )
+        })?;
+
+        let tau = match curvature {
+            Some(curvature) => {
+                /* Model change from z along d is tau r^T d + 1/2 tau^2 d^T H d. */
+                let slope = self.curr_rstep.dot(&self.curr_dstep);
+                let model = |t: f64| t * slope + 0.5 * t * t * curvature;
+                if model(backward) < model(forward) {
+                    backward
+                } else {
+                    forward
+                }
             }
+            None => forward,
         };
 
         self.curr_zstep += &(tau * &self.curr_dstep);
-        true
+        Ok(())
     }
 
     /// Iterate the trust region subproblem.
@@ -105,33 +136,35 @@ impl Steihaug {
     /// # Arguments
     ///
     /// * `gradient` - The gradient vector.
-    /// * `hessian` - The Hessian matrix.
+    /// * `hessian` - The Hessian, as a dense matrix or any other operator giving Hessian-vector
+    ///   products.
     /// * `delta` - The trust region radius.
     ///
     /// # Returns
     ///
-    /// * `bool` - Whether the iteration was successful.
+    /// * `Result<SubproblemOutcome, CoffeeError>` - How the subproblem was solved, or an
+    ///   error if no step was found.
     ///
     /// # Panics
     ///
-    /// If the calculations are not finite or they are NaN.
     /// If the vectors are not sized correctly.
-    pub fn iterate(
+    pub fn iterate<H: HessianOperator + ?Sized>(
         &mut self,
         gradient: &Array1<f64>,
-        hessian: &Array2<f64>,
+        hessian: &H,
         eps: f64,
         delta: f64,
-    ) -> bool {
+    ) -> Result<SubproblemOutcome, CoffeeError> {
         /* Limit number of iterations. */
         if self.curr_iterations >= self.max_iterations {
-            println!("Exceeded maximum iterations.");
-            return false;
+            return Err(CoffeeError::SubproblemFailed(
+                "The Steihaug solver exceeded its maximum iterations.".to_string(),
+            ));
         }
 
         /* Assert that vectors are sized correctly. */
         assert_eq!(gradient.dim(), self.vector_size);
-        assert_eq!(hessian.dim(), (self.vector_size, self.vector_size));
+        assert_eq!(hessian.dim(), self.vector_size);
 
         /* Reset ztep. */
         self.curr_zstep.fill(0.0);
@@ -141,27 +174,43 @@ impl Steihaug {
         self.curr_dstep = gradient.iter().map(|&x| -x).collect();
 
         /* Stop early if the magnitude of the gradient is within tolerance. */
+        let mut outcome = SubproblemOutcome::default();
         if self.norm(self.curr_rstep.view()) < eps {
-            return true;
+            outcome.converged = true;
+            return Ok(outcome);
         }
 
         for _i in 0..self.vector_size {
+            outcome.inner_iterations += 1;
+
             /* Calculate the curvature. Matrix operation is d^T @ hessian @ d, returns a scalar. */
-            let curvature = self.curr_dstep.t().dot(&hessian.dot(&self.curr_dstep));
+            let hessian_dstep = hessian.apply(self.curr_dstep.view());
+            let curvature = self.curr_dstep.dot(&hessian_dstep);
+
+            /* The model doesn't curve up along d, so it keeps decreasing up to the boundary. */
+            if curvature <= 0.0 {
+                self.early_update_zstep(delta, Some(curvature))?;
+                outcome.hit_boundary = true;
+                outcome.negative_curvature = true;
+                return Ok(outcome);
+            }
 
             /* Find new zstep, wait if it's needed for next iteration. */
             let alpha = (self.curr_rstep.dot(&self.curr_rstep)) / curvature;
             let new_zstep = &self.curr_zstep + alpha * &self.curr_dstep;
 
             if self.norm(new_zstep.view()) >= delta {
-                return self.early_update_zstep(delta);
+                self.early_update_zstep(delta, None)?;
+                outcome.hit_boundary = true;
+                return Ok(outcome);
             }
 
             /* Find new rstep, wait if it's needed for next iteration. */
-            let new_rstep = &self.curr_rstep + alpha * &hessian.dot(&self.curr_dstep);
+            let new_rstep = &self.curr_rstep + alpha * &hessian_dstep;
             if self.norm(new_rstep.view()) < eps {
                 self.curr_zstep = new_zstep;
-                return true;
+                outcome.converged = true;
+                return Ok(outcome);
             }
 
             /* Find new dstep and assign it back for next iteration. */
@@ -174,7 +223,7 @@ impl Steihaug {
         }
 
         self.curr_iterations += 1;
-        true
+        Ok(outcome)
     }
 
     /// Get the latest read-only result.
@@ -182,7 +231,7 @@ impl Steihaug {
     /// # Returns
     ///
     /// * `ArrayView1<f64>` - The latest result read-only.
-    pub fn get_result_readonly(&self) -> ArrayView1<f64> {
+    pub fn get_result_readonly(&self) -> ArrayView1<'_, f64> {
         self.curr_zstep.view()
     }
 
@@ -204,3 +253,102 @@ impl Steihaug {
         self.curr_zstep.clone()
     }
 }
+
+impl SubproblemSolver for Steihaug {
+    fn solve(
+        &mut self,
+        gradient: &Array1<f64>,
+        hessian: &dyn HessianOperator,
+        eps: f64,
+        delta: f64,
+    ) -> Result<SubproblemOutcome, CoffeeError> {
+        self.iterate(gradient, hessian, eps, delta)
+    }
+
+    fn step(&self) -> Array1<f64> {
+        self.get_result()
+    }
+
+    fn reset(&mut self) {
+        Steihaug::reset(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn test_steihaug_outcome() {
+        let gradient = array![1.0, 1.0];
+        let model = |hessian: &ndarray::Array2<f64>, p: &Array1<f64>| {
+            gradient.dot(p) + 0.5 * p.dot(&hessian.dot(p))
+        };
+        let mut steihaug = Steihaug::new(100, 2);
+
+        /* A positive definite model is solved inside a large region. */
+        let hessian = array![[2.0, 0.0], [0.0, 4.0]];
+        let outcome = steihaug.iterate(&gradient, &hessian, 1e-12, 10.0).unwrap();
+        assert!(outcome.converged && !outcome.hit_boundary && !outcome.negative_curvature);
+        assert!(outcome.inner_iterations <= 2);
+        let step = steihaug.get_result();
+        assert!((step[0] + 0.5).abs() < 1e-12 && (step[1] + 0.25).abs() < 1e-12);
+
+        /* Negative curvature along the first direction exits to the boundary. */
+        let hessian = array![[-2.0, 0.0], [0.0, 1.0]];
+        let outcome = steihaug.iterate(&gradient, &hessian, 1e-12, 1.0).unwrap();
+        assert_eq!(
+            outcome,
+            SubproblemOutcome {
+                inner_iterations: 1,
+                hit_boundary: true,
+                negative_curvature: true,
+                converged: false,
+            }
+        );
+        let step = steihaug.get_result();
+        assert!((step.dot(&step).sqrt() - 1.0).abs() < 1e-12);
+        assert!(model(&hessian, &step) < model(&hessian, &-&step));
+
+        /* Zero curvature also exits to the boundary instead of dividing by it. */
+        let hessian = array![[1.0, 0.0], [0.0, -1.0]];
+        let outcome = steihaug.iterate(&gradient, &hessian, 1e-12, 2.0).unwrap();
+        assert!(outcome.negative_curvature);
+        assert!(steihaug.get_result().iter().all(|v| v.is_finite()));
+    }
+
+    #[test]
+    fn test_later_negative_curvature() {
+        let gradient = array![1.0, 1.0];
+        let hessian = array![[4.0, 0.0], [0.0, -1.0]];
+        let model = |p: &Array1<f64>| gradient.dot(p) + 0.5 * p.dot(&hessian.dot(p));
+        let mut steihaug = Steihaug::new(100, 2);
+
+        /* The first direction curves up, so one conjugate gradient step is taken before the
+         * second direction, d = (-10/9, -40/9), curves down. */
+        let delta = 2.0;
+        let outcome = steihaug.iterate(&gradient, &hessian, 1e-12, delta).unwrap();
+        assert_eq!(
+            outcome,
+            SubproblemOutcome {
+                inner_iterations: 2,
+                hit_boundary: true,
+                negative_curvature: true,
+                converged: false,
+            }
+        );
+
+        /* The step continues from the inner iterate z = (-2/3, -2/3), not from the origin, to
+         * whichever end of the line through d on the boundary has the lower model value. */
+        let step = steihaug.get_result();
+        assert!((step.dot(&step).sqrt() - delta).abs() < 1e-12);
+        let z = array![-2.0 / 3.0, -2.0 / 3.0];
+        let d = array![-10.0 / 9.0, -40.0 / 9.0];
+        let (a, b, c) = (d.dot(&d), 2.0 * z.dot(&d), z.dot(&z) - delta * delta);
+        let root = (b * b - 4.0 * a * c).sqrt();
+        let ends = [(-b + root) / (2.0 * a), (-b - root) / (2.0 * a)].map(|t| &z + t * &d);
+        let best = ends.iter().map(&model).fold(f64::INFINITY, f64::min);
+        assert!((model(&step) - best).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,468 @@
+use crate::composition::CompositionMatrix;
+use crate::error::CoffeeError;
+use crate::extras::{InitialLambda, OptimizerArgs, OptimizerResults};
+use crate::optimize::Optimizer;
+use crate::problem::{EquilibriumProblem, INITIAL_DELTA};
+
+/// Spacing of the points along a sweep range.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SweepSpacing {
+    /// Evenly spaced values between the start and the stop.
+    Linear,
+    /// Evenly spaced values on a log scale. Both ends must be positive.
+    Log,
+}
+
+/// Describes a titration of one or more monomers over a range of concentrations.
+/// Every varied monomer takes the same value at each point; all other monomers keep
+/// their concentrations from the base problem.
+#[derive(Clone, Debug)]
+pub struct ConcentrationSweep {
+    /// Indices (0-based) of the monomers to vary.
+    pub monomers: Vec<usize>,
+    /// First concentration of the range.
+    pub start: f64,
+    /// Last concentration of the range.
+    pub stop: f64,
+    /// Number of points in the range, including both ends.
+    pub points: usize,
+    /// Spacing of the points between the start and the stop.
+    pub spacing: SweepSpacing,
+}
+
+/// Describes a melt curve: a linear sweep over a range of temperatures.
+#[derive(Clone, Debug)]
+pub struct TemperatureSweep {
+    /// First temperature of the range, in Celsius.
+    pub start: f64,
+    /// Last temperature of the range, in Celsius.
+    pub stop: f64,
+    /// Number of points in the range, including both ends.
+    pub points: usize,
+    /// Indices (0-based) of the polymers to estimate melting temperatures for.
+    pub targets: Vec<usize>,
+}
+
+/// Results of a sweep, one entry per point.
+#[derive(Clone)]
+pub struct SweepResults {
+    /// The swept value at each point.
+    pub values: Vec<f64>,
+    /// The optimizer results at each point.
+    pub results: Vec<OptimizerResults>,
+}
+
+/// Results of a temperature sweep.
+#[derive(Clone)]
+pub struct MeltCurve {
+    /// The temperatures (in Celsius) and the optimizer results at each of them.
+    pub sweep: SweepResults,
+    /// The fraction of each monomer bound in a polymer other than its free form, per temperature.
+    pub fraction_bound: Vec<Vec<f64>>,
+    /// The target polymers, paired with their estimated melting temperatures in Celsius.
+    /// `None` if the target's concentration never fell to half its maximum within the range.
+    pub melting_temperatures: Vec<(usize, Option<f64>)>,
+}
+
+/// Builds the values of a sweep range.
+///
+/// # Arguments
+///
+/// * `start` - The first value of the range.
+/// * `stop` - The last value of the range.
+/// * `points` - The number of values, including both ends.
+/// * `spacing` - Whether the values are spaced linearly or logarithmically.
+///
+/// # Returns
+///
+/// The values of the range, or an error if the range is empty or not finite, or if a log range
+/// doesn't have positive ends.
+pub fn sweep_values(
+    start: f64,
+    stop: f64,
+    points: usize,
+    spacing: SweepSpacing,
+) -> Result<Vec<f64>, CoffeeError> {
+    if points == 0 {
+        return Err(CoffeeError::InvalidInput(
+            "A sweep needs at least one point.".to_string(),
+        ));
+    }
+    if !start.is_finite() || !stop.is_finite() {
+        return Err(CoffeeError::InvalidInput(
+            "Sweep range must be finite.".to_string(),
+        ));
+    }
+    if spacing == SweepSpacing::Log && (start <= 0.0 || stop <= 0.0) {
+        return Err(CoffeeError::InvalidInput(
+            "Log-spaced sweep range must be positive.".to_string(),
+        ));
+    }
+    if points == 1 {
+        return Ok(vec![start]);
+    }
+
+    let last = (points - 1) as f64;
+    let values = (0..points)
+        .map(|i| {
+            let fraction = i as f64 / last;
+            match spacing {
+                SweepSpacing::Linear => start + fraction * (stop - start),
+                SweepSpacing::Log => (start.ln() + fraction * (stop.ln() - start.ln())).exp(),
+            }
+        })
+        .collect();
+    Ok(values)
+}
+
+/// Solves a problem at every point of a concentration sweep. A single optimizer is reused for
+/// the whole sweep, and each point is warm started from the multipliers of the previous one.
+///
+/// # Arguments
+///
+/// * `problem` - The base problem, whose concentrations are used for the monomers not varied.
+/// * `sweep` - The monomers to vary and the range to vary them over.
+/// * `optimizer_args` - The optimizer parameters. `initial_lambda` only applies to the first point.
+///
+/// # Returns
+///
+/// The results at every point of the sweep, or an error if the sweep is invalid or a point
+/// couldn't be solved or didn't converge.
+pub fn concentration_sweep(
+    problem: &EquilibriumProblem,
+    sweep: &ConcentrationSweep,
+    optimizer_args: &OptimizerArgs,
+) -> Result<SweepResults, CoffeeError> {
+    if sweep.monomers.is_empty() {
+        return Err(CoffeeError::InvalidInput(
+            "No monomers given to sweep.".to_string(),
+        ));
+    }
+    if let Some(index) = sweep
+        .monomers
+        .iter()
+        .find(|&&i| i >= problem.num_monomers())
+    {
+        return Err(CoffeeError::InvalidInput(format!(
+            "Monomer index {} is out of range for {} monomers.",
+            index,
+            problem.num_monomers()
+        )));
+    }
+    let values = sweep_values(sweep.start, sweep.stop, sweep.points, sweep.spacing)?;
+    if values.iter().any(|&v| v < 0.0) {
+        return Err(CoffeeError::InvalidInput(
+            "Swept concentrations must be non-negative.".to_string(),
+        ));
+    }
+
+    let mut optimizer = problem.optimizer(optimizer_args)?;
+    let mut monomers = problem.monomers().to_owned();
+    let mut results = Vec::with_capacity(values.len());
+
+    for (point, &value) in values.iter().enumerate() {
+        for &index in sweep.monomers.iter() {
+            monomers[index] = value;
+        }
+        optimizer.set_monomers(&monomers)?;
+        let point_results = solve_point(&mut optimizer, point)?;
+
+        /* Continue the next point from this optimum. */
+        optimizer.set_initial_lambda(InitialLambda::Given(point_results.optimal_lambda.clone()))?;
+        results.push(point_results);
+    }
+
+    Ok(SweepResults { values, results })
+}
+
+/// Solves one point of a sweep. A point that stops before converging fails the sweep, since its
+/// concentrations aren't the equilibrium and its multipliers would warm start the next point.
+///
+/// # Arguments
+///
+/// * `optimizer` - The optimizer, set up for the point.
+/// * `point` - The 0-based index of the point, to report failures with.
+///
+/// # Returns
+///
+/// The results at the point, or `SweepPointFailed` if it couldn't be solved or didn't converge.
+fn solve_point(optimizer: &mut Optimizer, point: usize) -> Result<OptimizerResults, CoffeeError> {
+    let source = match optimizer.optimize(INITIAL_DELTA) {
+        Ok(_) => {
+            let results = optimizer.get_results();
+            if results.termination_reason.is_converged() {
+                return Ok(results);
+            }
+            CoffeeError::NotConverged {
+                reason: results.termination_reason,
+            }
+        }
+        Err(e) => e,
+    };
+    Err(CoffeeError::SweepPointFailed {
+        point,
+        source: Box::new(source),
+    })
+}
+
+/// Solves a problem at every point of a temperature sweep. A single optimizer is reused for the
+/// whole sweep, and each point is warm started from the multipliers of the previous one.
+///
+/// # Arguments
+///
+/// * `problem` - The problem to solve at each temperature.
+/// * `sweep` - The temperature range and the polymers to find melting temperatures for.
+/// * `optimizer_args` - The optimizer parameters. `temp_celsius` is replaced by the swept
+///   temperatures, and `initial_lambda` only applies to the first point.
+///
+/// # Returns
+///
+/// The melt curve, or an error if the sweep is invalid or a point couldn't be solved or didn't
+/// converge.
+pub fn temperature_sweep(
+    problem: &EquilibriumProblem,
+    sweep: &TemperatureSweep,
+    optimizer_args: &OptimizerArgs,
+) -> Result<MeltCurve, CoffeeError> {
+    if let Some(index) = sweep.targets.iter().find(|&&i| i >= problem.num_polymers()) {
+        return Err(CoffeeError::InvalidInput(format!(
+            "Polymer index {} is out of range for {} polymers.",
+            index,
+            problem.num_polymers()
+        )));
+    }
+    let temperatures = sweep_values(sweep.start, sweep.stop, sweep.points, SweepSpacing::Linear)?;
+
+    let mut optimizer = problem.optimizer(optimizer_args)?;
+    let mut results = Vec::with_capacity(temperatures.len());
+
+    for (point, &temperature) in temperatures.iter().enumerate() {
+        optimizer.set_temperature(temperature)?;
+        let point_results = solve_point(&mut optimizer, point)?;
+
+        /* Continue the next point from this optimum. */
+        optimizer.set_initial_lambda(InitialLambda::Given(point_results.optimal_lambda.clone()))?;
+        results.push(point_results);
+    }
+
+    let fraction_bound = results
+        .iter()
+        .map(|r| {
+            fraction_bound(
+                problem.polymers(),
+                problem.monomers().as_slice(),
+                &r.optimal_x,
+            )
+        })
+        .collect();
+    let melting_temperatures = sweep
+        .targets
+        .iter()
+        .map(|&target| {
+            let concentrations: Vec<f64> = results.iter().map(|r| r.optimal_x[target]).collect();
+            (target, melting_temperature(&temperatures, &concentrations))
+        })
+        .collect();
+
+    Ok(MeltCurve {
+        sweep: SweepResults {
+            values: temperatures,
+            results,
+        },
+        fraction_bound,
+        melting_temperatures,
+    })
+}
+
+/// Finds the fraction of each monomer that is bound, i.e. not in the polymer made of that
+/// monomer alone. Monomers with no such polymer in the inputs are counted as fully bound.
+///
+/// # Arguments
+///
+/// * `polymers` - The composition matrix (N x M).
+/// * `monomers` - The initial monomer concentrations, if contiguous.
+/// * `optimal_x` - The equilibrium polymer concentrations.
+///
+/// # Returns
+///
+/// The bound fraction of each monomer, or NaN for monomers with no concentration.
+fn fraction_bound(
+    polymers: &CompositionMatrix,
+    monomers: Option<&[f64]>,
+    optimal_x: &[f64],
+) -> Vec<f64> {
+    let free = polymers.free_monomers(optimal_x);
+    free.iter()
+        .zip(monomers.unwrap_or(&[]))
+        .map(|(free, &total)| {
+            if total > 0.0 {
+                (1.0 - free / total).clamp(0.0, 1.0)
+            } else {
+                f64::NAN
+            }
+        })
+        .collect()
+}
+
+/// Estimates the melting temperature of a polymer as the first temperature where its
+/// concentration falls below half of its largest concentration over the sweep as the
+/// temperature rises, interpolating linearly between sweep points.
+///
+/// # Arguments
+///
+/// * `temperatures` - The temperatures of the sweep.
+/// * `concentrations` - The polymer's concentration at each temperature.
+///
+/// # Returns
+///
+/// The melting temperature, or `None` if the concentration never falls below half its maximum.
+pub fn melting_temperature(temperatures: &[f64], concentrations: &[f64]) -> Option<f64> {
+    let max = concentrations.iter().cloned().fold(0.0, f64::max);
+    if max <= 0.0 {
+        return None;
+    }
+    let half = 0.5 * max;
+
+    /* Only count crossings where the polymer falls below half as the temperature rises. */
+    temperatures
+        .windows(2)
+        .zip(concentrations.windows(2))
+        .find(|(t, c)| {
+            let (c_low, c_high) = if t[0] <= t[1] {
+                (c[0], c[1])
+            } else {
+                (c[1], c[0])
+            };
+            c_low >= half && c_high < half
+        })
+        .map(|(t, c)| t[0] + (half - c[0]) / (c[1] - c[0]) * (t[1] - t[0]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extras::TerminationReason;
+    use crate::problem::dimer_problem;
+
+    #[test]
+    fn test_sweep_values() {
+        let values = sweep_values(1.0, 3.0, 3, SweepSpacing::Linear).unwrap();
+        assert_eq!(values, vec![1.0, 2.0, 3.0]);
+
+        let values = sweep_values(1.0e-8, 1.0e-6, 3, SweepSpacing::Log).unwrap();
+        assert!((values[1] - 1.0e-7).abs() < 1.0e-20);
+
+        assert!(sweep_values(0.0, 1.0, 3, SweepSpacing::Log).is_err());
+        assert!(sweep_values(0.0, 1.0, 0, SweepSpacing::Linear).is_err());
+    }
+
+    #[test]
+    fn test_concentration_sweep() {
+        let problem = dimer_problem(-20.0);
+        let sweep = ConcentrationSweep {
+            monomers: vec![1],
+            start: 1.0e-7,
+            stop: 1.0e-5,
+            points: 5,
+            spacing: SweepSpacing::Log,
+        };
+        let args = OptimizerArgs {
+            use_terminal: false,
+            ..Default::default()
+        };
+
+        let sweep_results = concentration_sweep(&problem, &sweep, &args).unwrap();
+        assert_eq!(sweep_results.results.len(), 5);
+
+        /* The dimer is limited by the swept monomer until it exceeds the fixed one. */
+        let dimers: Vec<f64> = sweep_results
+            .results
+            .iter()
+            .map(|r| r.optimal_x[2])
+            .collect();
+        assert!(dimers.windows(2).all(|w| w[1] >= w[0]));
+        assert!(sweep_results
+            .results
+            .iter()
+            .all(|r| r.termination_reason.is_converged()));
+
+        /* A point that doesn't converge fails the sweep. */
+        let short_args = OptimizerArgs {
+            max_iterations: 1,
+            ..args.clone()
+        };
+        match concentration_sweep(&problem, &sweep, &short_args) {
+            Err(CoffeeError::SweepPointFailed { point: 0, source }) => assert!(matches!(
+                *source,
+                CoffeeError::NotConverged {
+                    reason: TerminationReason::MaxIterations
+                }
+            )),
+            _ => panic!("expected the first point to fail"),
+        }
+
+        /* Out of range monomers are rejected. */
+        let sweep = ConcentrationSweep {
+            monomers: vec![2],
+            ..sweep
+        };
+        assert!(concentration_sweep(&problem, &sweep, &args).is_err());
+    }
+
+    #[test]
+    fn test_melting_temperature() {
+        let temperatures = [20.0, 30.0, 40.0, 50.0];
+        let concentrations = [1.0, 0.8, 0.4, 0.0];
+        let tm = melting_temperature(&temperatures, &concentrations).unwrap();
+        assert!((tm - 37.5).abs() < 1.0e-12);
+
+        /* The same curve swept downwards gives the same estimate. */
+        let reversed_tm = melting_temperature(&[50.0, 40.0, 30.0, 20.0], &[0.0, 0.4, 0.8, 1.0]);
+        assert!((reversed_tm.unwrap() - 37.5).abs() < 1.0e-12);
+
+        /* A flat or rising curve never melts. */
+        assert_eq!(melting_temperature(&temperatures, &[1.0; 4]), None);
+        assert_eq!(
+            melting_temperature(&temperatures, &[0.0, 0.4, 0.8, 1.0]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_temperature_sweep() {
+        let problem = dimer_problem(-13.0);
+        let sweep = TemperatureSweep {
+            start: 20.0,
+            stop: 100.0,
+            points: 11,
+            targets: vec![2],
+        };
+        let args = OptimizerArgs {
+            use_terminal: false,
+            ..Default::default()
+        };
+
+        let curve = temperature_sweep(&problem, &sweep, &args).unwrap();
+        assert_eq!(curve.sweep.results.len(), 11);
+        assert_eq!(curve.fraction_bound.len(), 11);
+
+        /* The dimer dissociates as the temperature rises. */
+        let bound: Vec<f64> = curve.fraction_bound.iter().map(|f| f[0]).collect();
+        assert!(bound.windows(2).all(|w| w[1] <= w[0] + 1.0e-9));
+        let (target, tm) = curve.melting_temperatures[0];
+        assert_eq!(target, 2);
+        assert!(tm.is_some_and(|tm| tm > 20.0 && tm < 100.0));
+
+        /* A point that doesn't converge fails the sweep. */
+        let args = OptimizerArgs {
+            max_iterations: 1,
+            ..args
+        };
+        let result = temperature_sweep(&problem, &sweep, &args);
+        assert!(matches!(
+            result,
+            Err(CoffeeError::SweepPointFailed { point: 0, .. })
+        ));
+        assert_eq!(result.err().unwrap().code(), "not_converged");
+    }
+}